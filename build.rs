@@ -0,0 +1,15 @@
+use std::env;
+
+fn main() {
+    // use the bundled protoc unless one is given explicitly
+    if env::var_os("PROTOC").is_none() {
+        if let Ok(protoc) = protoc_bin_vendored::protoc_bin_path() {
+            env::set_var("PROTOC", protoc);
+        }
+    }
+    // only the server side is served from here
+    tonic_build::configure()
+        .build_client(false)
+        .compile_protos(&["proto/deals.proto"], &["proto"])
+        .unwrap();
+}
@@ -0,0 +1,639 @@
+use std::convert::TryFrom;
+use std::path::Path;
+
+use chrono::DateTime;
+use rusqlite::{params, Connection, OptionalExtension, NO_PARAMS};
+
+use crate::error::Result;
+
+pub struct Archive {
+    connection: Connection,
+}
+
+pub struct Discount {
+    pub title: String,
+    pub url: String,
+    pub price: f64,
+    pub typical_price: f64,
+}
+
+pub struct ReactedPost {
+    pub title: String,
+    pub url: String,
+    pub reactions: u32,
+}
+
+// the earlier prices of a product
+pub struct PriceHistory {
+    pub previous: f64,
+    pub lowest: f64,
+}
+
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub price: Option<f64>,
+    pub timestamp: String,
+}
+
+// quotes every term so that user input can't use the FTS query syntax
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// a message sent to a bot channel
+pub struct SentMessage {
+    pub channel_id: String,
+    pub message_id: String,
+    pub post_id: u32,
+    pub sent_at: i64,
+}
+
+// a webhook message kept up to date with the post, the destination is hashed
+// as the webhook url is a secret
+pub struct TrackedMessage {
+    pub destination: String,
+    pub post_id: u32,
+    pub message_id: String,
+    pub content_hash: String,
+}
+
+// a bot message a forwarded post was sent as
+pub struct ForwardedMessage {
+    pub channel_id: String,
+    pub message_id: String,
+    pub title: String,
+}
+
+pub struct ArchivedPost<'a> {
+    pub id: u32,
+    pub author: &'a str,
+    pub title: &'a str,
+    pub product_key: Option<&'a str>,
+    pub price: Option<f64>,
+    pub url: &'a str,
+    pub timestamp: &'a str,
+    pub content: &'a str,
+    pub category: Option<&'a str>,
+    pub store: Option<&'a str>,
+    pub store_url: Option<&'a str>,
+    pub typical_price: Option<f64>,
+}
+
+// each migration upgrades the schema by one version
+static MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS posts (
+        id INTEGER PRIMARY KEY,
+        author TEXT NOT NULL,
+        title TEXT NOT NULL,
+        product_key TEXT,
+        price REAL,
+        url TEXT NOT NULL,
+        timestamp TEXT NOT NULL,
+        content TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS posts_product_key ON posts (product_key);",
+    "ALTER TABLE posts ADD COLUMN category TEXT;
+    ALTER TABLE posts ADD COLUMN store TEXT;
+    ALTER TABLE posts ADD COLUMN typical_price REAL;
+    ALTER TABLE posts ADD COLUMN posted_at INTEGER;
+    CREATE INDEX posts_posted_at ON posts (posted_at);",
+    "ALTER TABLE posts ADD COLUMN reactions INTEGER NOT NULL DEFAULT 0;
+    CREATE INDEX posts_author ON posts (author);",
+    "CREATE VIRTUAL TABLE posts_fts USING fts5(
+        title, content, content='posts', content_rowid='id'
+    );
+    CREATE TRIGGER posts_fts_insert AFTER INSERT ON posts BEGIN
+        INSERT INTO posts_fts (rowid, title, content) VALUES (new.id, new.title, new.content);
+    END;
+    CREATE TRIGGER posts_fts_delete AFTER DELETE ON posts BEGIN
+        INSERT INTO posts_fts (posts_fts, rowid, title, content)
+            VALUES ('delete', old.id, old.title, old.content);
+    END;
+    CREATE TRIGGER posts_fts_update AFTER UPDATE ON posts BEGIN
+        INSERT INTO posts_fts (posts_fts, rowid, title, content)
+            VALUES ('delete', old.id, old.title, old.content);
+        INSERT INTO posts_fts (rowid, title, content) VALUES (new.id, new.title, new.content);
+    END;
+    INSERT INTO posts_fts (posts_fts) VALUES ('rebuild');",
+    "CREATE TABLE messages (
+        message_id TEXT PRIMARY KEY,
+        channel_id TEXT NOT NULL,
+        post_id INTEGER NOT NULL,
+        sent_at INTEGER NOT NULL,
+        pinned_at INTEGER,
+        unpinned INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX messages_sent_at ON messages (sent_at);",
+    "CREATE TABLE tracked_messages (
+        destination TEXT NOT NULL,
+        post_id INTEGER NOT NULL,
+        message_id TEXT NOT NULL,
+        content_hash TEXT NOT NULL,
+        PRIMARY KEY (destination, post_id)
+    );",
+    "ALTER TABLE posts ADD COLUMN store_url TEXT;
+    CREATE INDEX posts_store_url ON posts (store_url);",
+    // replacing posts left their old terms in the search index
+    "INSERT INTO posts_fts (posts_fts) VALUES ('rebuild');",
+];
+
+// forum timestamps look like 2021-01-05T12:34:56+0200
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    DateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S%z")
+        .or_else(|_| DateTime::parse_from_rfc3339(timestamp))
+        .ok()
+        .map(|timestamp| timestamp.timestamp())
+}
+
+fn migrate(connection: &Connection) -> Result<()> {
+    let version: i64 = connection.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+    let version = usize::try_from(version).unwrap_or(0);
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        connection.execute_batch(migration)?;
+        connection.execute_batch(&format!("PRAGMA user_version = {}", i + 1))?;
+    }
+    Ok(())
+}
+
+impl Archive {
+    pub fn open(state_directory: &Path) -> Result<Self> {
+        let connection = Connection::open(state_directory.join("archive.sqlite"))?;
+        migrate(&connection)?;
+        Ok(Self { connection })
+    }
+
+    // updated in place when sent again, which keeps the reactions and the pins and
+    // fires the update trigger of the search index unlike replacing the row
+    pub fn insert_post(&self, post: &ArchivedPost) -> Result<()> {
+        self.insert(
+            post,
+            "DO UPDATE SET author = excluded.author, title = excluded.title,
+                product_key = excluded.product_key, price = excluded.price,
+                url = excluded.url, timestamp = excluded.timestamp,
+                content = excluded.content, category = excluded.category,
+                store = excluded.store, typical_price = excluded.typical_price,
+                posted_at = excluded.posted_at, store_url = excluded.store_url",
+        )
+    }
+
+    // keeps what was archived when the post was forwarded
+    pub fn import_post(&self, post: &ArchivedPost) -> Result<()> {
+        self.insert(post, "DO NOTHING")
+    }
+
+    fn insert(&self, post: &ArchivedPost, on_conflict: &str) -> Result<()> {
+        self.connection.execute(
+            &format!(
+                "INSERT INTO posts
+                    (id, author, title, product_key, price, url, timestamp, content,
+                        category, store, typical_price, posted_at, store_url)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                    ON CONFLICT (id) {on_conflict}"
+            ),
+            params![
+                post.id,
+                post.author,
+                post.title,
+                post.product_key,
+                post.price,
+                post.url,
+                post.timestamp,
+                post.content,
+                post.category,
+                post.store,
+                post.typical_price,
+                parse_timestamp(post.timestamp),
+                post.store_url
+            ],
+        )?;
+        Ok(())
+    }
+
+    // the prices of other posts of the same product or with the same store link
+    pub fn price_history(
+        &self,
+        post_id: u32,
+        product_key: Option<&str>,
+        store_url: Option<&str>,
+    ) -> Result<Option<PriceHistory>> {
+        let (previous, lowest): (Option<f64>, Option<f64>) = self.connection.query_row(
+            "SELECT
+                (SELECT price FROM posts
+                    WHERE (product_key = ?1 OR store_url = ?2) AND price IS NOT NULL AND id != ?3
+                    ORDER BY id DESC LIMIT 1),
+                MIN(price)
+                FROM posts
+                WHERE (product_key = ?1 OR store_url = ?2) AND price IS NOT NULL AND id != ?3",
+            params![product_key, store_url, post_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok(previous
+            .zip(lowest)
+            .map(|(previous, lowest)| PriceHistory { previous, lowest }))
+    }
+
+    // the latest earlier post with the store link posted since the given time
+    pub fn earlier_post(
+        &self,
+        post_id: u32,
+        store_url: &str,
+        since: i64,
+    ) -> Result<Option<String>> {
+        let url = self
+            .connection
+            .query_row(
+                "SELECT url FROM posts
+                    WHERE store_url = ?1 AND posted_at >= ?2 AND id < ?3
+                    ORDER BY id DESC LIMIT 1",
+                params![store_url, since, post_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(url)
+    }
+
+    fn counts(&self, query: &str, since: i64) -> Result<Vec<(String, u32)>> {
+        let mut statement = self.connection.prepare(query)?;
+        let rows = statement.query_map(params![since], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn category_counts(&self, since: i64) -> Result<Vec<(String, u32)>> {
+        self.counts(
+            "SELECT COALESCE(category, 'Muu'), COUNT(*) FROM posts
+                WHERE posted_at >= ?1 GROUP BY 1 ORDER BY 2 DESC",
+            since,
+        )
+    }
+
+    pub fn store_counts(&self, since: i64) -> Result<Vec<(String, u32)>> {
+        self.counts(
+            "SELECT store, COUNT(*) FROM posts
+                WHERE posted_at >= ?1 AND store IS NOT NULL GROUP BY 1 ORDER BY 2 DESC LIMIT 10",
+            since,
+        )
+    }
+
+    // relative to the typical price from the price comparison
+    pub fn biggest_discounts(&self, since: i64, limit: u32) -> Result<Vec<Discount>> {
+        let mut statement = self.connection.prepare(
+            "SELECT title, url, price, typical_price FROM posts
+                WHERE posted_at >= ?1 AND typical_price > price
+                ORDER BY (typical_price - price) / typical_price DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![since, limit], |row| {
+            Ok(Discount {
+                title: row.get(0)?,
+                url: row.get(1)?,
+                price: row.get(2)?,
+                typical_price: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    // the reactions are recorded only when enabled in the reactions config
+    pub fn most_reacted(&self, since: i64, limit: u32) -> Result<Vec<ReactedPost>> {
+        let mut statement = self.connection.prepare(
+            "SELECT title, url, reactions FROM posts
+                WHERE posted_at >= ?1 AND reactions > 0 ORDER BY reactions DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![since, limit], |row| {
+            Ok(ReactedPost {
+                title: row.get(0)?,
+                url: row.get(1)?,
+                reactions: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn author_post_counts(
+        &self,
+        since: i64,
+        until: i64,
+        limit: u32,
+    ) -> Result<Vec<(String, u32)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT author, COUNT(*) FROM posts
+                WHERE posted_at >= ?1 AND posted_at < ?2
+                GROUP BY author ORDER BY 2 DESC LIMIT ?3",
+        )?;
+        let rows = statement.query_map(params![since, until, limit], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn author_reaction_counts(
+        &self,
+        since: i64,
+        until: i64,
+        limit: u32,
+    ) -> Result<Vec<(String, u32)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT author, SUM(reactions) FROM posts
+                WHERE posted_at >= ?1 AND posted_at < ?2
+                GROUP BY author HAVING SUM(reactions) > 0 ORDER BY 2 DESC LIMIT ?3",
+        )?;
+        let rows = statement.query_map(params![since, until, limit], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn insert_message(&self, message: &SentMessage) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO messages (message_id, channel_id, post_id, sent_at)
+                VALUES (?1, ?2, ?3, ?4)",
+            params![
+                message.message_id,
+                message.channel_id,
+                message.post_id,
+                message.sent_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn messages(&self, query: &str, time: i64) -> Result<Vec<SentMessage>> {
+        let mut statement = self.connection.prepare(query)?;
+        let rows = statement.query_map(params![time], |row| {
+            Ok(SentMessage {
+                message_id: row.get(0)?,
+                channel_id: row.get(1)?,
+                post_id: row.get(2)?,
+                sent_at: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn recent_messages(&self, since: i64) -> Result<Vec<SentMessage>> {
+        self.messages(
+            "SELECT message_id, channel_id, post_id, sent_at FROM messages WHERE sent_at >= ?1",
+            since,
+        )
+    }
+
+    // messages sent since the time which haven't been pinned yet
+    pub fn unpinned_messages(&self, since: i64) -> Result<Vec<SentMessage>> {
+        self.messages(
+            "SELECT message_id, channel_id, post_id, sent_at FROM messages
+                WHERE sent_at >= ?1 AND pinned_at IS NULL",
+            since,
+        )
+    }
+
+    // messages pinned before the time which are still pinned
+    pub fn pinned_messages(&self, before: i64) -> Result<Vec<SentMessage>> {
+        self.messages(
+            "SELECT message_id, channel_id, post_id, sent_at FROM messages
+                WHERE pinned_at < ?1 AND NOT unpinned",
+            before,
+        )
+    }
+
+    // the first message the post was sent as
+    pub fn forwarded_message(&self, post_id: u32) -> Result<Option<ForwardedMessage>> {
+        let message = self
+            .connection
+            .query_row(
+                "SELECT messages.channel_id, messages.message_id, posts.title
+                    FROM messages JOIN posts ON posts.id = messages.post_id
+                    WHERE messages.post_id = ?1 ORDER BY messages.sent_at LIMIT 1",
+                params![post_id],
+                |row| {
+                    Ok(ForwardedMessage {
+                        channel_id: row.get(0)?,
+                        message_id: row.get(1)?,
+                        title: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(message)
+    }
+
+    pub fn insert_tracked_message(&self, message: &TrackedMessage) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO tracked_messages
+                (destination, post_id, message_id, content_hash) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                message.destination,
+                message.post_id,
+                message.message_id,
+                message.content_hash
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn tracked_message(
+        &self,
+        destination: &str,
+        post_id: u32,
+    ) -> Result<Option<TrackedMessage>> {
+        let message = self
+            .connection
+            .query_row(
+                "SELECT message_id, content_hash FROM tracked_messages
+                    WHERE destination = ?1 AND post_id = ?2",
+                params![destination, post_id],
+                |row| {
+                    Ok(TrackedMessage {
+                        destination: destination.to_owned(),
+                        post_id,
+                        message_id: row.get(0)?,
+                        content_hash: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(message)
+    }
+
+    // keeps the reactions and the rest, unlike inserting the post again
+    pub fn update_post_content(&self, post_id: u32, title: &str, content: &str) -> Result<()> {
+        self.connection.execute(
+            "UPDATE posts SET title = ?2, content = ?3 WHERE id = ?1",
+            params![post_id, title, content],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_reactions(&self, post_id: u32, reactions: u32) -> Result<()> {
+        self.connection.execute(
+            "UPDATE posts SET reactions = ?2 WHERE id = ?1",
+            params![post_id, reactions],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_pinned(&self, message_id: &str, pinned_at: i64) -> Result<()> {
+        self.connection.execute(
+            "UPDATE messages SET pinned_at = ?2 WHERE message_id = ?1",
+            params![message_id, pinned_at],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_unpinned(&self, message_id: &str) -> Result<()> {
+        self.connection.execute(
+            "UPDATE messages SET unpinned = 1 WHERE message_id = ?1",
+            params![message_id],
+        )?;
+        Ok(())
+    }
+
+    // dates the posts archived before posted_at was stored, or with an unparsed timestamp
+    fn fill_posted_at(&self) -> Result<()> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, timestamp FROM posts WHERE posted_at IS NULL")?;
+        let undated = statement
+            .query_map(NO_PARAMS, |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for (id, timestamp) in undated {
+            if let Some(posted_at) = parse_timestamp(&timestamp) {
+                self.connection.execute(
+                    "UPDATE posts SET posted_at = ?2 WHERE id = ?1",
+                    params![id, posted_at],
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // deletes the posts matching both conditions and the messages they were sent as,
+    // returns how many posts were deleted
+    pub fn purge(&self, author: Option<&str>, before: Option<i64>) -> Result<usize> {
+        self.fill_posted_at()?;
+        // posts still without a date can't be shown to be newer, so they are purged too
+        let condition = "(?1 IS NULL OR author = ?1 COLLATE NOCASE) \
+            AND (?2 IS NULL OR posted_at IS NULL OR posted_at < ?2)";
+        for table in ["messages", "tracked_messages"] {
+            self.connection.execute(
+                &format!(
+                    "DELETE FROM {table} WHERE post_id IN (SELECT id FROM posts WHERE {condition})"
+                ),
+                params![author, before],
+            )?;
+        }
+        let purged = self.connection.execute(
+            &format!("DELETE FROM posts WHERE {condition}"),
+            params![author, before],
+        )?;
+        // leaves no deleted data behind in the search index or the database file
+        self.connection
+            .execute_batch("INSERT INTO posts_fts (posts_fts) VALUES ('optimize'); VACUUM;")?;
+        Ok(purged)
+    }
+
+    // most recent matches first
+    pub fn search(&self, query: &str, limit: u32) -> Result<Vec<SearchResult>> {
+        let mut statement = self.connection.prepare(
+            "SELECT posts.title, posts.url, posts.price, posts.timestamp
+                FROM posts_fts JOIN posts ON posts.id = posts_fts.rowid
+                WHERE posts_fts MATCH ?1 ORDER BY posts.id DESC LIMIT ?2",
+        )?;
+        let rows = statement.query_map(params![fts_query(query), limit], |row| {
+            Ok(SearchResult {
+                title: row.get(0)?,
+                url: row.get(1)?,
+                price: row.get(2)?,
+                timestamp: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open() -> Archive {
+        let connection = Connection::open_in_memory().unwrap();
+        migrate(&connection).unwrap();
+        Archive { connection }
+    }
+
+    fn post<'a>(title: &'a str, content: &'a str) -> ArchivedPost<'a> {
+        ArchivedPost {
+            id: 1,
+            author: "kirjoittaja",
+            title,
+            product_key: None,
+            price: Some(99.0),
+            url: "https://bbs.io-tech.fi/posts/1/",
+            timestamp: "2024-01-01T12:00:00+0000",
+            content,
+            category: None,
+            store: None,
+            store_url: None,
+            typical_price: None,
+        }
+    }
+
+    fn search_titles(archive: &Archive, query: &str) -> Vec<String> {
+        archive
+            .search(query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.title)
+            .collect()
+    }
+
+    #[test]
+    fn updates_the_search_index_when_sent_again() {
+        let archive = open();
+        archive
+            .insert_post(&post("Näyttö", "halpa näyttö"))
+            .unwrap();
+        archive.set_reactions(1, 3).unwrap();
+        archive.insert_post(&post("Hiiri", "halpa hiiri")).unwrap();
+
+        assert!(search_titles(&archive, "näyttö").is_empty());
+        assert_eq!(search_titles(&archive, "hiiri"), ["Hiiri"]);
+        assert_eq!(search_titles(&archive, "halpa"), ["Hiiri"]);
+        let reactions: u32 = archive
+            .connection
+            .query_row(
+                "SELECT reactions FROM posts WHERE id = 1",
+                NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(reactions, 3);
+    }
+
+    #[test]
+    fn keeps_the_forwarded_post_on_import() {
+        let archive = open();
+        archive
+            .insert_post(&post("Näyttö", "halpa näyttö"))
+            .unwrap();
+        archive.import_post(&post("Hiiri", "halpa hiiri")).unwrap();
+
+        assert_eq!(search_titles(&archive, "halpa"), ["Näyttö"]);
+        assert!(search_titles(&archive, "hiiri").is_empty());
+    }
+
+    #[test]
+    fn purges_the_author_in_any_case() {
+        let archive = open();
+        archive
+            .insert_post(&post("Näytönohjain", "hinta 99 €"))
+            .unwrap();
+        assert_eq!(archive.purge(Some("Kirjoittaja"), None).unwrap(), 1);
+        assert!(search_titles(&archive, "näytönohjain").is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use reqwest::blocking::Client;
+use tracing::debug;
+
+use crate::error::Result;
+use crate::read_image;
+
+// downloaded avatars are kept on disk, forum avatar urls tend to expire
+pub struct AvatarCache {
+    directory: PathBuf,
+}
+
+fn cache_key(url: &str) -> String {
+    url.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// keeps the extension of the url, e.g. avatar.png
+pub fn attachment_filename(name: &str, url: &str) -> String {
+    let path = url.split('?').next().unwrap_or(url);
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("jpg");
+    format!("{name}.{extension}")
+}
+
+impl AvatarCache {
+    pub fn new(state_directory: &Path) -> Self {
+        Self {
+            directory: state_directory.join("avatars"),
+        }
+    }
+
+    // the cached avatars aren't linked to their users, so all of them are removed
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_dir_all(&self.directory) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // none when the avatar is over the image size limit
+    pub fn get(&self, client: &Client, url: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.directory.join(cache_key(url));
+        match fs::read(&path) {
+            Ok(data) => return Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        debug!("Download avatar {url}");
+        let Some(data) = read_image(client.get(url).send()?.error_for_status()?)? else {
+            return Ok(None);
+        };
+        fs::create_dir_all(&self.directory)?;
+        fs::write(&path, &data)?;
+        Ok(Some(data))
+    }
+}
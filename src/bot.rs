@@ -0,0 +1,357 @@
+use std::fmt::Write;
+
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::retry::RetryConfig;
+use crate::webhook::{
+    multipart_form, send_retrying, AllowedMentions, Attachment, Embed, EmbedBuilder,
+    SUPPRESS_NOTIFICATIONS,
+};
+
+static API_BASE_URL: &str = "https://discord.com/api/v10";
+
+const ANNOUNCEMENT_CHANNEL_TYPE: u8 = 5;
+
+// emojis are given as unicode or name:id for custom ones
+fn encode_emoji(emoji: &str) -> String {
+    let mut encoded = String::new();
+    for byte in emoji.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-' | b':') {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+pub struct Bot<'a> {
+    client: &'a Client,
+    token: &'a str,
+    retry: RetryConfig,
+}
+
+impl<'a> Bot<'a> {
+    pub fn with_client(client: &'a Client, token: &'a str) -> Self {
+        Self {
+            client,
+            token,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Channel {
+    #[serde(rename = "type")]
+    kind: u8,
+    pub guild_id: Option<String>,
+}
+
+impl Channel {
+    // messages in announcement channels can be published to following servers
+    pub fn is_announcement(&self) -> bool {
+        self.kind == ANNOUNCEMENT_CHANNEL_TYPE
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreatedMessage {
+    pub id: String,
+}
+
+#[derive(Deserialize)]
+pub struct Reaction {
+    pub count: u32,
+    // whether the bot itself reacted
+    #[serde(default)]
+    pub me: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ReceivedEmbed {
+    pub url: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReceivedMessage {
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub embeds: Vec<ReceivedEmbed>,
+    #[serde(default)]
+    pub reactions: Vec<Reaction>,
+}
+
+impl ReceivedMessage {
+    // leaves out the reactions added by the bot
+    pub fn reaction_count(&self) -> u32 {
+        self.reactions
+            .iter()
+            .map(|reaction| reaction.count.saturating_sub(u32::from(reaction.me)))
+            .sum()
+    }
+}
+
+#[derive(Serialize, Default)]
+struct CreateMessage<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    embeds: Vec<&'a Embed<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+    // none by default, discord would parse every mention in the content
+    allowed_mentions: AllowedMentions,
+}
+
+#[derive(Serialize)]
+struct StartThread<'a> {
+    name: &'a str,
+    auto_archive_duration: u32,
+}
+
+#[derive(Serialize)]
+struct StartForumThread<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    applied_tags: &'a [&'a str],
+    message: &'a CreateMessage<'a>,
+}
+
+pub struct MessageBuilder<'a> {
+    bot: &'a Bot<'a>,
+    channel_id: &'a str,
+    payload: CreateMessage<'a>,
+    forum_thread: Option<(&'a str, &'a [&'a str])>,
+    attachments: Vec<Attachment<'a>>,
+}
+
+impl<'a> MessageBuilder<'a> {
+    pub fn content(&mut self, content: &'a str) -> &mut Self {
+        self.payload.content = Some(content);
+        self
+    }
+
+    pub fn allowed_mentions(&mut self, allowed_mentions: AllowedMentions) -> &mut Self {
+        self.payload.allowed_mentions = allowed_mentions;
+        self
+    }
+
+    // delivers the message without push notifications
+    pub fn silent(&mut self) -> &mut Self {
+        self.payload.flags = Some(SUPPRESS_NOTIFICATIONS);
+        self
+    }
+
+    pub fn embed(&mut self, embed: &'a EmbedBuilder) -> &mut Self {
+        self.payload.embeds.push(&embed.embed);
+        self
+    }
+
+    pub fn attachment(
+        &mut self,
+        filename: &'a str,
+        data: &'a [u8],
+        description: Option<&'a str>,
+    ) -> &mut Self {
+        self.attachments.push(Attachment {
+            filename,
+            data,
+            description,
+        });
+        self
+    }
+
+    // posts the message as a new thread in a forum channel
+    pub fn forum_thread(&mut self, name: &'a str, applied_tags: &'a [&'a str]) -> &mut Self {
+        self.forum_thread = Some((name, applied_tags));
+        self
+    }
+
+    pub fn send(&self) -> reqwest::Result<reqwest::blocking::Response> {
+        send_retrying(&self.bot.retry, || self.request())
+    }
+
+    fn request(&self) -> RequestBuilder {
+        let request = if let Some((name, applied_tags)) = self.forum_thread {
+            let url = format!("{API_BASE_URL}/channels/{}/threads", self.channel_id);
+            let payload = StartForumThread {
+                name,
+                applied_tags,
+                message: &self.payload,
+            };
+            let request = self.bot.client.post(&url);
+            if self.attachments.is_empty() {
+                request.json(&payload)
+            } else {
+                request.multipart(multipart_form(&payload, &self.attachments))
+            }
+        } else {
+            let url = format!("{API_BASE_URL}/channels/{}/messages", self.channel_id);
+            let request = self.bot.client.post(&url);
+            if self.attachments.is_empty() {
+                request.json(&self.payload)
+            } else {
+                request.multipart(multipart_form(&self.payload, &self.attachments))
+            }
+        };
+        request.header("Authorization", format!("Bot {}", self.bot.token))
+    }
+}
+
+impl<'a> Bot<'a> {
+    // creating a command with an existing name overwrites it
+    pub fn create_global_command<T: Serialize>(
+        &self,
+        application_id: &str,
+        command: &T,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/applications/{application_id}/commands");
+        self.client
+            .post(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .json(command)
+            .send()
+    }
+
+    pub fn get_channel(&self, channel_id: &str) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}");
+        self.client
+            .get(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .send()
+    }
+
+    // publishes a message in an announcement channel to the following servers
+    pub fn crosspost_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/messages/{message_id}/crosspost");
+        self.client
+            .post(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .send()
+    }
+
+    pub fn get_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/messages/{message_id}");
+        self.client
+            .get(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .send()
+    }
+
+    pub fn create_reaction(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        emoji: &str,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!(
+            "{API_BASE_URL}/channels/{channel_id}/messages/{message_id}/reactions/{}/@me",
+            encode_emoji(emoji)
+        );
+        send_retrying(&self.retry, || {
+            self.client
+                .put(&url)
+                .header("Authorization", format!("Bot {}", self.token))
+                .header("Content-Length", "0")
+        })
+    }
+
+    // starts a thread from an existing message
+    pub fn start_thread(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+        name: &str,
+        auto_archive_duration: u32,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/messages/{message_id}/threads");
+        self.client
+            .post(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .json(&StartThread {
+                name,
+                auto_archive_duration,
+            })
+            .send()
+    }
+
+    // the most recent messages of the channel, newest first
+    pub fn get_messages(
+        &self,
+        channel_id: &str,
+        limit: u32,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/messages?limit={limit}");
+        self.client
+            .get(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .send()
+    }
+
+    pub fn pin_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/pins/{message_id}");
+        self.client
+            .put(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .header("Content-Length", "0")
+            .send()
+    }
+
+    pub fn unpin_message(
+        &self,
+        channel_id: &str,
+        message_id: &str,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/pins/{message_id}");
+        self.client
+            .delete(&url)
+            .header("Authorization", format!("Bot {}", self.token))
+            .send()
+    }
+
+    // sends an already formatted message payload as is
+    pub fn create_message_json<T: Serialize>(
+        &self,
+        channel_id: &str,
+        payload: &T,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = format!("{API_BASE_URL}/channels/{channel_id}/messages");
+        send_retrying(&self.retry, || {
+            self.client
+                .post(&url)
+                .header("Authorization", format!("Bot {}", self.token))
+                .json(payload)
+        })
+    }
+
+    pub fn create_message(&'a self, channel_id: &'a str) -> MessageBuilder<'a> {
+        MessageBuilder {
+            bot: self,
+            channel_id,
+            payload: CreateMessage::default(),
+            forum_thread: None,
+            attachments: Vec::new(),
+        }
+    }
+}
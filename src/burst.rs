@@ -0,0 +1,63 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use serde::Deserialize;
+
+use crate::error::Result;
+
+#[derive(Deserialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+// tighter polling and a higher catch-up cap during sale events
+#[derive(Deserialize)]
+pub struct BurstConfig {
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    pub max_posts_per_run: Option<usize>,
+    #[serde(default)]
+    pub windows: Vec<DateRange>,
+}
+
+impl Default for BurstConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: default_poll_interval(),
+            max_posts_per_run: None,
+            windows: Vec::new(),
+        }
+    }
+}
+
+fn default_poll_interval() -> u64 {
+    60
+}
+
+fn marker_path(state_directory: &Path) -> std::path::PathBuf {
+    state_directory.join("burst")
+}
+
+pub fn is_active(config: &BurstConfig, state_directory: &Path, today: NaiveDate) -> bool {
+    config
+        .windows
+        .iter()
+        .any(|window| window.start <= today && today <= window.end)
+        || marker_path(state_directory).exists()
+}
+
+// toggled manually by the admin with `tarjousbot burst on|off`
+pub fn set_manual(state_directory: &Path, active: bool) -> Result<()> {
+    let path = marker_path(state_directory);
+    if active {
+        fs::write(path, b"")?;
+    } else if let Err(err) = fs::remove_file(path) {
+        if err.kind() != io::ErrorKind::NotFound {
+            return Err(err.into());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use serde::Deserialize;
+
+// off until keywords are configured, e.g. "alv-päivät", "kaikesta" or "black friday"
+#[derive(Deserialize, Default)]
+pub struct CampaignConfig {
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    pub color: Option<i32>,
+    // name of the category campaigns are routed as
+    pub category: Option<String>,
+}
+
+// returns the line announcing the campaign if the post is a store-wide campaign
+pub fn detect<'a>(config: &CampaignConfig, title: &str, content: &'a str) -> Option<&'a str> {
+    if config.keywords.is_empty() {
+        return None;
+    }
+    let title = title.to_lowercase();
+    let keywords: Vec<String> = config
+        .keywords
+        .iter()
+        .map(|keyword| keyword.to_lowercase())
+        .collect();
+    let content_line = content.lines().find(|line| {
+        let line = line.to_lowercase();
+        keywords.iter().any(|keyword| line.contains(keyword))
+    });
+    if content_line.is_some() {
+        return content_line;
+    }
+    if keywords.iter().any(|keyword| title.contains(keyword)) {
+        return content.lines().next();
+    }
+    None
+}
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::config::Target;
+use crate::error::Result;
+use crate::webhook::EmbedBuilder;
+use crate::{get_page_url, send_message, Context, Message};
+
+// the selectors every post needs, the next page one isn't found on the last page
+static REQUIRED_SELECTORS: &[&str] = &["post", "time", "username", "content", "permalink"];
+
+#[derive(Deserialize)]
+pub struct CanaryConfig {
+    // the admin alerted when a selector stops matching
+    #[serde(flatten)]
+    pub target: Target,
+    // selector name to the selectors tried in order when it stops matching
+    #[serde(default)]
+    pub fallbacks: HashMap<String, Vec<String>>,
+    // seconds between checking the selectors against the newest page
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    60 * 60
+}
+
+// kept over client rebuilds so that each breakage is alerted once
+pub struct Canary {
+    checked: Option<Instant>,
+    broken: HashSet<String>,
+}
+
+fn matches(fragment: &Html, selector: &Selector) -> bool {
+    fragment.select(selector).next().is_some()
+}
+
+fn find_fallback(fragment: &Html, fallbacks: &[String]) -> Option<(String, Selector)> {
+    fallbacks
+        .iter()
+        .find_map(|fallback| match Selector::parse(fallback) {
+            Ok(selector) if matches(fragment, &selector) => Some((fallback.clone(), selector)),
+            Ok(_) => None,
+            Err(err) => {
+                warn!("invalid fallback selector {fallback}: {err:?}");
+                None
+            }
+        })
+}
+
+fn alert(ctx: &Context, target: &Target, text: &str) -> Result<()> {
+    warn!("{text}");
+    let mut embed = EmbedBuilder::new();
+    embed.title("Valitsinvaroitus").description(text);
+    let message = Message {
+        content: None,
+        embed: &embed,
+        attachments: &[],
+        buttons: &[],
+        title: "Valitsinvaroitus",
+        category: None,
+        data: None,
+        silent: false,
+    };
+    send_message(ctx, target, &message)?;
+    Ok(())
+}
+
+impl Canary {
+    pub fn new() -> Self {
+        Self {
+            checked: None,
+            broken: HashSet::new(),
+        }
+    }
+
+    // switches selectors not matching the newest page to the first working fallback
+    pub fn run(&mut self, ctx: &Context) -> Result<()> {
+        let Some(config) = &ctx.config.canary else {
+            return Ok(());
+        };
+        if self
+            .checked
+            .is_some_and(|checked| checked.elapsed() < Duration::from_secs(config.interval))
+        {
+            return Ok(());
+        }
+        self.checked = Some(Instant::now());
+
+        let page_url = get_page_url(ctx, u32::MAX);
+        let body = ctx
+            .config
+            .retry
+            .send(|| ctx.forum_client.get(&page_url))?
+            .error_for_status()?
+            .text()?;
+        let fragment = Html::parse_document(&body);
+        let names = REQUIRED_SELECTORS
+            .iter()
+            .copied()
+            .chain(config.fallbacks.keys().map(String::as_str))
+            .filter(|&name| name != "next_page")
+            .collect::<HashSet<_>>();
+
+        for name in names {
+            let mut selectors = ctx.selectors.borrow_mut();
+            let Some(selector) = selectors.get_mut(name) else {
+                continue;
+            };
+            if matches(&fragment, selector) {
+                self.broken.remove(name);
+                continue;
+            }
+            let fallbacks = config.fallbacks.get(name).map_or(&[][..], Vec::as_slice);
+            match find_fallback(&fragment, fallbacks) {
+                Some((fallback, fallback_selector)) => {
+                    *selector = fallback_selector;
+                    drop(selectors);
+                    self.broken.remove(name);
+                    alert(
+                        ctx,
+                        &config.target,
+                        &format!("The {name} selector stopped matching, switched to {fallback}"),
+                    )?;
+                }
+                None if self.broken.insert(name.to_owned()) => {
+                    drop(selectors);
+                    alert(
+                        ctx,
+                        &config.target,
+                        &format!("The {name} selector stopped matching and no fallback works"),
+                    )?;
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct Category {
+    pub name: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    pub color: Option<i32>,
+    pub emoji: Option<String>,
+}
+
+impl Category {
+    fn matches(&self, text: &str) -> bool {
+        self.keywords
+            .iter()
+            .any(|keyword| text.contains(&keyword.to_lowercase()))
+    }
+
+    pub fn decorate_title(&self, title: &str) -> String {
+        match &self.emoji {
+            Some(emoji) => format!("{emoji} {title}"),
+            None => title.to_owned(),
+        }
+    }
+}
+
+fn category(name: &str, keywords: &[&str], color: i32) -> Category {
+    Category {
+        name: name.to_owned(),
+        keywords: keywords.iter().map(|&keyword| keyword.to_owned()).collect(),
+        color: Some(color),
+        emoji: None,
+    }
+}
+
+// used when none are configured, graphics cards go before monitors so that
+// "näytönohjain" isn't taken for a monitor
+pub fn defaults() -> Vec<Category> {
+    vec![
+        category(
+            "Näytönohjaimet",
+            &[
+                "näytönohjain",
+                "geforce",
+                "rtx ",
+                "radeon",
+                "rx 6",
+                "rx 7",
+                "rx 9",
+            ],
+            0x0076_b900,
+        ),
+        category(
+            "Prosessorit",
+            &[
+                "prosessori",
+                "ryzen",
+                "core i",
+                "core ultra",
+                "threadripper",
+            ],
+            0x00ed_1c24,
+        ),
+        category("SSD-levyt", &["ssd", "nvme", "m.2"], 0x0034_98db),
+        category("Näytöt", &["näyttö", "monitori", "monitor"], 0x009b_59b6),
+    ]
+}
+
+// the first category with a keyword matching the title or content wins
+pub fn classify<'a>(
+    categories: &'a [Category],
+    title: &str,
+    content: &str,
+) -> Option<&'a Category> {
+    let title = title.to_lowercase();
+    let content = content.to_lowercase();
+    categories
+        .iter()
+        .find(|category| category.matches(&title) || category.matches(&content))
+}
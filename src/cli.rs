@@ -0,0 +1,203 @@
+use std::path::{Path, PathBuf};
+
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+
+use crate::config::{Config, Target};
+use crate::error::{Error, Result};
+use crate::logging::LogFormat;
+use crate::outbox::Outbox;
+use crate::pipeline::Pipeline;
+use crate::state::State;
+use crate::webhook::EmbedBuilder;
+use crate::{send_message, Context, Message};
+
+#[derive(Parser)]
+#[command(
+    name = "tarjousbot",
+    version,
+    about = "Forwards deals from the io-tech forum"
+)]
+pub struct Cli {
+    /// Config file, defaults to config.toml in the state directory
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Directory for the state, archive and caches
+    #[arg(long, global = true, env = "TARJOUSBOT_STATE_DIR")]
+    pub state_dir: Option<PathBuf>,
+    /// More logging, repeat for even more
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Less logging, repeat for even less
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "verbose")]
+    pub quiet: u8,
+    /// Format of the log lines
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+    // the daemon flag from before the subcommands
+    #[arg(long, hide = true)]
+    pub daemon: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    pub fn verbosity(&self) -> i16 {
+        i16::from(self.verbose) - i16::from(self.quiet)
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BurstMode {
+    On,
+    Off,
+}
+
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    #[command(about = "Forward the new posts once, or keep polling with --daemon")]
+    Run {
+        /// Keep polling for new posts
+        #[arg(long)]
+        daemon: bool,
+        /// Print the messages instead of sending them and leave the state as is
+        #[arg(long)]
+        dry_run: bool,
+    },
+    #[command(about = "Send the newest posts of every thread again")]
+    Backfill {
+        /// How many of the newest posts to send
+        count: usize,
+        /// Print the messages instead of sending them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    #[command(about = "Print where every thread left off")]
+    Status,
+    #[command(
+        about = "Forget where the threads left off, the next run starts from the newest post"
+    )]
+    Reset,
+    #[command(about = "Send a test message to every destination")]
+    TestWebhook,
+    #[command(about = "Print the new posts as JSON lines without forwarding them")]
+    Scrape,
+    #[command(about = "Send the messages read from stdin")]
+    Send,
+    #[command(about = "Archive the whole thread")]
+    Import,
+    #[command(about = "Check the state, destinations and selectors")]
+    Doctor,
+    #[command(about = "Update to the latest signed release")]
+    SelfUpdate {
+        /// Update with only the checksum when no public key is configured
+        #[arg(long)]
+        insecure: bool,
+    },
+    #[command(about = "Turn the burst mode on or off")]
+    Burst { mode: BurstMode },
+    #[command(
+        about = "Remove posts and personal data from the archive, outboxes, digests and feeds"
+    )]
+    Purge {
+        /// Forum username whose posts are removed
+        #[arg(long)]
+        author: Option<String>,
+        /// Remove the posts from before this date, as yyyy-mm-dd
+        #[arg(long)]
+        before: Option<String>,
+    },
+}
+
+impl Command {
+    pub fn is_daemon(&self) -> bool {
+        matches!(self, Self::Run { daemon: true, .. })
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(
+            self,
+            Self::Run { dry_run: true, .. } | Self::Backfill { dry_run: true, .. }
+        )
+    }
+}
+
+pub fn print_status(config: &Config, state_directory: &Path) -> Result<()> {
+    for spec in config.pipelines(state_directory) {
+        let state = State::load(&spec.state_directory)?;
+        let queued = Outbox::load(&spec.state_directory)?.len();
+        let show =
+            |value: Option<u32>| value.map_or_else(|| "-".to_owned(), |value| value.to_string());
+        println!(
+            "{}: last page {}, last post {}, {queued} queued",
+            spec.name,
+            show(state.last_page),
+            show(state.last_post)
+        );
+        for (name, count) in &state.missing_selectors {
+            println!(
+                "  {name} selector missed {count} of {} posts",
+                state.parsed_posts
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn reset(config: &Config, state_directory: &Path) -> Result<()> {
+    for spec in config.pipelines(state_directory) {
+        if spec.state_directory.is_dir() {
+            State::update(&spec.state_directory, |state| {
+                state.last_page = None;
+                state.last_post = None;
+                state.last_thread = None;
+            })?;
+        }
+        println!("Reset {}", spec.name);
+    }
+    Ok(())
+}
+
+pub fn backfill(ctx: &Context, pipelines: &mut [Pipeline], count: usize) -> Result<()> {
+    for pipeline in pipelines {
+        pipeline.backfill(ctx, count)?;
+    }
+    Ok(())
+}
+
+fn send_test_message(ctx: &Context, target: &Target) -> Result<()> {
+    let mut embed = EmbedBuilder::new();
+    embed
+        .title("Testiviesti")
+        .description("Tarjousbotti lähettää tarjoukset tänne.");
+    let message = Message {
+        content: None,
+        embed: &embed,
+        attachments: &[],
+        buttons: &[],
+        title: "Testiviesti",
+        category: None,
+        data: None,
+        silent: false,
+    };
+    send_message(ctx, target, &message)?;
+    Ok(())
+}
+
+// prints a line per destination, fails if sending to any did
+pub fn test_webhooks(ctx: &Context) -> Result<()> {
+    let mut failed = 0;
+    for destination in ctx.config.all_destinations() {
+        let target = &destination.target;
+        match send_test_message(ctx, target) {
+            Ok(()) => println!("[PASS] {}", target.kind()),
+            Err(err) => {
+                println!("[FAIL] {}: {err}", target.kind());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Diagnostics(failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::archive;
+use crate::error::Result;
+use crate::{get_store_url, Context, ScrapedPost};
+
+// the same deal is often posted again with the same store link
+#[derive(Deserialize)]
+pub struct DedupConfig {
+    // how long after the earlier post a link counts as already seen
+    #[serde(default = "default_window_hours")]
+    pub window_hours: i64,
+    // duplicates are sent silently with a link to the earlier post instead of dropped
+    #[serde(default)]
+    pub collapse: bool,
+}
+
+fn default_window_hours() -> i64 {
+    24
+}
+
+// the url of a forwarded post with the same store link within the window
+pub fn earlier_post(
+    ctx: &Context,
+    config: &DedupConfig,
+    post: &ScrapedPost,
+) -> Result<Option<String>> {
+    let Some(store_url) = get_store_url(&post.links) else {
+        return Ok(None);
+    };
+    let posted_at =
+        archive::parse_timestamp(&post.timestamp).unwrap_or_else(|| Utc::now().timestamp());
+    ctx.archive
+        .earlier_post(post.id, store_url, posted_at - config.window_hours * 3600)
+}
@@ -0,0 +1,164 @@
+use std::borrow::Cow;
+
+use serde_json::json;
+use tracing::info;
+
+use crate::archive::{parse_timestamp, ArchivedPost};
+use crate::category::Category;
+use crate::config::Target;
+use crate::error::Result;
+use crate::grpc::Deal;
+use crate::throttle::{self, DigestEntry};
+use crate::webhook::EmbedBuilder;
+use crate::{
+    category, favicon, get_store_url, notifier, price, product, truncate, truncate_with_read_more,
+    Context, Message, ScrapedPost,
+};
+
+// shorter than in single posts to fit more posts in a message
+const DESCRIPTION_LIMIT: usize = 300;
+
+// what the embed of a post in a digest shows
+struct DigestPost<'a> {
+    post: &'a ScrapedPost,
+    title: Cow<'a, str>,
+    description: Cow<'a, str>,
+    price: Option<f64>,
+    category: Option<&'a Category>,
+    highlight: Option<i32>,
+}
+
+impl<'a> DigestPost<'a> {
+    fn new(ctx: &'a Context, post: &'a ScrapedPost) -> Self {
+        let category = category::classify(&ctx.config.categories, &post.title, &post.content);
+        let price = price::parse_price(&post.content);
+        Self {
+            post,
+            title: category.map_or(Cow::Borrowed(post.title.as_str()), |category| {
+                Cow::Owned(category.decorate_title(&post.title))
+            }),
+            description: truncate_with_read_more(&post.content, DESCRIPTION_LIMIT, &post.url),
+            price,
+            category,
+            highlight: ctx.config.posters.highlight(&post.username),
+        }
+    }
+
+    fn embed(&self) -> EmbedBuilder<'_> {
+        let post = self.post;
+        let mut embed = EmbedBuilder::new();
+        embed
+            .timestamp(&post.timestamp)
+            .author(
+                Some(truncate(&post.username, 256)),
+                Some(&post.user_url),
+                post.avatar_url.as_deref(),
+            )
+            .title(truncate(&self.title, 256))
+            .url(&post.url)
+            .description(self.description.as_ref());
+        if let Some(price) = self.price {
+            embed.field("Hinta", price::format_price(price), Some(true));
+        }
+        if let Some(category) = self.category {
+            embed.field("Kategoria", truncate(&category.name, 1024), Some(true));
+        }
+        let color = self
+            .highlight
+            .or_else(|| self.category.and_then(|category| category.color));
+        if let Some(color) = color {
+            embed.color(color);
+        }
+        embed
+    }
+
+    fn entry(&self) -> DigestEntry {
+        DigestEntry {
+            title: self.title.to_string(),
+            url: self.post.url.clone(),
+            price: self.price,
+            author: Some(self.post.username.clone()),
+            posted_at: parse_timestamp(&self.post.timestamp),
+        }
+    }
+}
+
+// sends the posts as few messages as possible, discord destinations get an embed
+// per post and the others a list of them
+pub fn send(ctx: &Context, target: &Target, posts: &[&ScrapedPost]) -> Result<()> {
+    info!("Send digest of {} posts", posts.len());
+    let digest_posts: Vec<DigestPost> = posts
+        .iter()
+        .map(|post| DigestPost::new(ctx, post))
+        .collect();
+    let embeds: Vec<EmbedBuilder> = digest_posts.iter().map(DigestPost::embed).collect();
+    if ctx.dry_run {
+        let payload = json!({
+            "destination": target.kind(),
+            "embeds": embeds.iter().map(|embed| &embed.embed).collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string(&payload)?);
+        return Ok(());
+    }
+
+    let entries: Vec<DigestEntry> = digest_posts.iter().map(DigestPost::entry).collect();
+    let title = format!("Uudet tarjoukset ({})", posts.len());
+    let description = throttle::digest_description(&entries);
+    let mut summary = EmbedBuilder::new();
+    summary.title(&title).description(&description);
+    let message = Message {
+        content: None,
+        embed: &summary,
+        attachments: &[],
+        buttons: &[],
+        title: &title,
+        category: None,
+        data: None,
+        silent: false,
+    };
+    ctx.rate_limiter.acquire(target.key());
+    notifier::for_target(target).notify_digest(ctx, &message, &embeds)
+}
+
+// what forwarding a post records besides sending it, without the slower details
+pub fn record(ctx: &Context, post: &ScrapedPost) -> Result<()> {
+    if ctx.dry_run {
+        return Ok(());
+    }
+    ctx.metrics.increment("posts.sent");
+    let category = category::classify(&ctx.config.categories, &post.title, &post.content);
+    let price = price::parse_price(&post.content);
+    let store = get_store_url(&post.links).and_then(favicon::store_domain);
+    if let Some(deals) = ctx.deals {
+        deals.publish(Deal {
+            id: post.id,
+            title: post.title.clone(),
+            content: post.content.clone(),
+            url: post.url.clone(),
+            author: post.username.clone(),
+            timestamp: post.timestamp.clone(),
+            price,
+            typical_price: None,
+            store: store.clone(),
+            category: category.map(|category| category.name.clone()),
+            links: post.links.clone(),
+        });
+    }
+    let product_key = Some(post.title.as_str())
+        .filter(|_| post.has_title())
+        .map(|title| product::identity_key(title, &post.links));
+    ctx.archive.insert_post(&ArchivedPost {
+        id: post.id,
+        author: &post.username,
+        title: &post.title,
+        product_key: product_key.as_deref(),
+        price,
+        url: &post.url,
+        timestamp: &post.timestamp,
+        content: &post.content,
+        category: category.map(|category| category.name.as_str()),
+        store: store.as_deref(),
+        store_url: get_store_url(&post.links),
+        typical_price: None,
+    })
+}
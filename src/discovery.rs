@@ -0,0 +1,184 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use tarjousbot::forum::FORUM_URL;
+use tracing::info;
+
+use crate::config::Target;
+use crate::error::Result;
+use crate::webhook::EmbedBuilder;
+use crate::{send_message, Context, Message};
+
+const STATE_FILE: &str = "discovery.json";
+
+#[derive(Deserialize)]
+pub struct DiscoveryConfig {
+    // the admin new threads are proposed to
+    #[serde(flatten)]
+    pub target: Target,
+    // subforum listings or search result pages to look for threads in
+    pub pages: Vec<String>,
+    // case insensitive, a thread matches if its title contains any, or all if empty
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    // forward the matching threads too, starting from the next start
+    #[serde(default)]
+    pub auto_subscribe: bool,
+    // seconds between searches
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    60 * 60
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DiscoveryState {
+    // urls of the matching threads found so far
+    seen: Vec<String>,
+    subscribed: Vec<String>,
+}
+
+impl DiscoveryState {
+    fn load(state_directory: &Path) -> Result<Option<Self>> {
+        match fs::read_to_string(state_directory.join(STATE_FILE)) {
+            Ok(s) => Ok(Some(serde_json::from_str(&s)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self, state_directory: &Path) -> Result<()> {
+        fs::write(
+            state_directory.join(STATE_FILE),
+            serde_json::to_string(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+// the automatically subscribed thread urls, forwarded like configured pipelines
+pub fn subscribed_threads(state_directory: &Path) -> Result<Vec<String>> {
+    Ok(DiscoveryState::load(state_directory)?
+        .map(|state| state.subscribed)
+        .unwrap_or_default())
+}
+
+// e.g. some-deals.123 from https://bbs.io-tech.fi/threads/some-deals.123/page-2
+pub fn thread_key(thread_url: &str) -> Option<&str> {
+    let (_, rest) = thread_url.split_once("/threads/")?;
+    rest.split('/').next().filter(|key| !key.is_empty())
+}
+
+// the numeric part of the key, which links without the title also use
+pub fn thread_id(thread_url: &str) -> Option<&str> {
+    thread_key(thread_url)?.rsplit('.').next()
+}
+
+// title and url of the threads listed on the page
+pub fn find_threads(body: &str) -> Vec<(String, String)> {
+    let selector = Selector::parse(
+        ".structItem-title a[href*=\"/threads/\"], .contentRow-title a[href*=\"/threads/\"]",
+    )
+    .unwrap();
+    Html::parse_document(body)
+        .select(&selector)
+        .filter_map(|element| {
+            let href = element.value().attr("href")?;
+            let key = thread_key(href)?;
+            let title = element.text().collect::<String>().trim().to_owned();
+            Some((title, format!("{FORUM_URL}/threads/{key}/")))
+        })
+        .collect()
+}
+
+fn matches(config: &DiscoveryConfig, title: &str) -> bool {
+    let title = title.to_lowercase();
+    config.patterns.is_empty()
+        || config
+            .patterns
+            .iter()
+            .any(|pattern| title.contains(&pattern.to_lowercase()))
+}
+
+fn propose(ctx: &Context, config: &DiscoveryConfig, title: &str, url: &str) -> Result<()> {
+    let mut embed = EmbedBuilder::new();
+    embed
+        .title(title)
+        .url(url)
+        .description(if config.auto_subscribe {
+            "Uusi tarjousketju löytyi ja se tilataan seuraavasta käynnistyksestä alkaen."
+        } else {
+            "Uusi tarjousketju löytyi. Lisää se putkeksi asetuksiin seurataksesi sitä."
+        });
+    let message = Message {
+        content: None,
+        embed: &embed,
+        attachments: &[],
+        buttons: &[],
+        title,
+        category: None,
+        data: None,
+        silent: false,
+    };
+    send_message(ctx, &config.target, &message)?;
+    Ok(())
+}
+
+// looks for new matching threads, the first search only records the current ones
+pub fn run(ctx: &Context, checked: &mut Option<Instant>) -> Result<()> {
+    let Some(config) = &ctx.config.discovery else {
+        return Ok(());
+    };
+    if checked.is_some_and(|checked| checked.elapsed() < Duration::from_secs(config.interval)) {
+        return Ok(());
+    }
+    *checked = Some(Instant::now());
+
+    let mut threads = Vec::new();
+    for page in &config.pages {
+        let body = ctx
+            .config
+            .retry
+            .send(|| ctx.forum_client.get(page))?
+            .error_for_status()?
+            .text()?;
+        threads.extend(
+            find_threads(&body)
+                .into_iter()
+                .filter(|(title, _)| matches(config, title)),
+        );
+    }
+
+    let Some(mut state) = DiscoveryState::load(&ctx.state_directory)? else {
+        let state = DiscoveryState {
+            seen: threads.into_iter().map(|(_, url)| url).collect(),
+            subscribed: Vec::new(),
+        };
+        return state.save(&ctx.state_directory);
+    };
+    let pipelines = ctx.config.pipelines(&ctx.state_directory);
+    let configured = pipelines
+        .iter()
+        .filter_map(|pipeline| thread_id(&pipeline.thread_url))
+        .collect::<Vec<_>>();
+    for (title, url) in threads {
+        if state.seen.contains(&url) || thread_id(&url).is_some_and(|id| configured.contains(&id)) {
+            continue;
+        }
+        info!("Discovered thread {title}: {url}");
+        propose(ctx, config, &title, &url)?;
+        if config.auto_subscribe {
+            state.subscribed.push(url.clone());
+        }
+        state.seen.push(url);
+        // saved after each one so that nothing is proposed twice
+        state.save(&ctx.state_directory)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use serde::Deserialize;
+
+use crate::archive::SentMessage;
+use crate::error::Result;
+use crate::pin::channel_bot;
+use crate::{truncate, Context};
+
+// a thread is started from every deal sent by a bot
+#[derive(Deserialize)]
+pub struct DiscussionConfig {
+    // minutes of inactivity before the thread is archived, 60, 1440, 4320 or 10080
+    #[serde(default = "default_auto_archive_duration")]
+    pub auto_archive_duration: u32,
+}
+
+fn default_auto_archive_duration() -> u32 {
+    1440
+}
+
+pub fn start_thread(ctx: &Context, message: &SentMessage, name: &str) -> Result<()> {
+    let Some(config) = &ctx.config.discussion else {
+        return Ok(());
+    };
+    let Some(bot) = channel_bot(ctx, &message.channel_id) else {
+        return Ok(());
+    };
+    bot.start_thread(
+        &message.channel_id,
+        &message.message_id,
+        truncate(name, 100),
+        config.auto_archive_duration,
+    )?
+    .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use crate::error::Result;
+
+const RECORD_A: u16 = 1;
+const RECORD_AAAA: u16 = 28;
+
+#[derive(Deserialize)]
+pub struct DnsConfig {
+    // DNS-over-HTTPS endpoint supporting the JSON API, e.g. https://1.1.1.1/dns-query
+    pub doh_url: Option<String>,
+    // plain DNS server, e.g. 9.9.9.9:53
+    pub server: Option<String>,
+    // seconds resolved addresses are kept, also how often clients are rebuilt
+    #[serde(default = "default_cache_ttl")]
+    pub cache_ttl: u64,
+}
+
+fn default_cache_ttl() -> u64 {
+    600
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+pub struct Resolver {
+    client: Client,
+    doh_url: Option<String>,
+    server: Option<String>,
+    ttl: Duration,
+    cache: HashMap<String, (Vec<IpAddr>, Instant)>,
+}
+
+fn build_query(id: u16, host: &str, record_type: u16) -> Vec<u8> {
+    let mut query = Vec::with_capacity(host.len() + 18);
+    query.extend_from_slice(&id.to_be_bytes());
+    // recursion desired, one question
+    query.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+    for label in host.split('.').filter(|label| !label.is_empty()) {
+        query.push(u8::try_from(label.len()).unwrap_or(63));
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&1_u16.to_be_bytes());
+    query
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *packet.get(offset)?,
+        *packet.get(offset + 1)?,
+    ]))
+}
+
+// returns the offset after a possibly compressed name
+fn skip_name(packet: &[u8], mut offset: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(offset)?;
+        if length == 0 {
+            return Some(offset + 1);
+        }
+        if length & 0xc0 == 0xc0 {
+            return Some(offset + 2);
+        }
+        offset += usize::from(length) + 1;
+    }
+}
+
+fn parse_response(packet: &[u8], id: u16) -> Option<Vec<IpAddr>> {
+    if read_u16(packet, 0)? != id {
+        return None;
+    }
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = skip_name(packet, offset)? + 4;
+    }
+
+    let mut addrs = Vec::new();
+    for _ in 0..answers {
+        offset = skip_name(packet, offset)?;
+        let record_type = read_u16(packet, offset)?;
+        let length = usize::from(read_u16(packet, offset + 8)?);
+        let data = packet.get(offset + 10..offset + 10 + length)?;
+        match (record_type, data.len()) {
+            (RECORD_A, 4) => addrs.push(IpAddr::V4(Ipv4Addr::new(
+                data[0], data[1], data[2], data[3],
+            ))),
+            (RECORD_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addrs.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+        offset += 10 + length;
+    }
+    Some(addrs)
+}
+
+fn query_server(server: &str, host: &str, record_type: u16) -> Result<Vec<IpAddr>> {
+    let socket = UdpSocket::bind(if server.starts_with('[') {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    })?;
+    socket.set_read_timeout(Some(Duration::from_secs(5)))?;
+    socket.connect(server)?;
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    let id = u16::try_from(nanos & 0xffff).unwrap_or(0);
+    socket.send(&build_query(id, host, record_type))?;
+    let mut buffer = [0; 1232];
+    let length = socket.recv(&mut buffer)?;
+    parse_response(&buffer[..length], id)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid DNS response").into())
+}
+
+impl Resolver {
+    pub fn new(config: Option<&DnsConfig>) -> Result<Self> {
+        Ok(Self {
+            client: Client::builder().build()?,
+            doh_url: config.and_then(|config| config.doh_url.clone()),
+            server: config.and_then(|config| config.server.clone()),
+            ttl: Duration::from_secs(
+                config.map_or_else(default_cache_ttl, |config| config.cache_ttl),
+            ),
+            cache: HashMap::new(),
+        })
+    }
+
+    // whether lookups go somewhere else than the system resolver
+    pub fn is_custom(&self) -> bool {
+        self.doh_url.is_some() || self.server.is_some()
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn query_doh(&self, doh_url: &str, host: &str, record_type: u16) -> Result<Vec<IpAddr>> {
+        let response: DohResponse = self
+            .client
+            .get(doh_url)
+            .query(&[("name", host), ("type", &record_type.to_string())])
+            .header("Accept", "application/dns-json")
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(response
+            .answer
+            .iter()
+            .filter(|answer| answer.record_type == record_type)
+            .filter_map(|answer| answer.data.parse().ok())
+            .collect())
+    }
+
+    fn query(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let mut addrs = Vec::new();
+        for &record_type in &[RECORD_A, RECORD_AAAA] {
+            if let Some(doh_url) = &self.doh_url {
+                addrs.extend(self.query_doh(doh_url, host, record_type)?);
+            } else if let Some(server) = &self.server {
+                addrs.extend(query_server(server, host, record_type)?);
+            }
+        }
+        if !self.is_custom() {
+            addrs.extend((host, 0).to_socket_addrs()?.map(|addr| addr.ip()));
+        }
+        Ok(addrs)
+    }
+
+    pub fn lookup(&mut self, host: &str) -> Result<Vec<IpAddr>> {
+        if let Some((addrs, resolved_at)) = self.cache.get(host) {
+            if resolved_at.elapsed() < self.ttl {
+                return Ok(addrs.clone());
+            }
+        }
+        let addrs = self.query(host)?;
+        self.cache
+            .insert(host.to_owned(), (addrs.clone(), Instant::now()));
+        Ok(addrs)
+    }
+}
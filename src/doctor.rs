@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use scraper::{Html, Selector};
+
+use crate::archive::Archive;
+use crate::bot::Bot;
+use crate::config::{Config, Target};
+use crate::email::Mailer;
+use crate::error::{Error, Result};
+use crate::favicon::FaviconCache;
+use crate::matrix::Matrix;
+use crate::ntfy::Ntfy;
+use crate::session;
+use crate::state::State;
+use crate::telegram::Telegram;
+use crate::throttle::Throttler;
+use crate::{get_page_url, Context};
+
+fn report(name: &str, result: Result<()>) -> bool {
+    match result {
+        Ok(()) => {
+            println!("[PASS] {name}");
+            true
+        }
+        Err(err) => {
+            println!("[FAIL] {name}: {err}");
+            false
+        }
+    }
+}
+
+fn check_state(state_directory: &Path) -> Result<()> {
+    State::load(state_directory)?;
+    let path = state_directory.join("doctor_check");
+    fs::write(&path, b"ok")?;
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+// the files opened before the rest of the checks can run, fails if any of them did
+pub fn check_files(config: &Config, state_directory: &Path) -> Result<()> {
+    let mut checks = vec![
+        ("state directory", check_state(state_directory)),
+        ("archive", Archive::open(state_directory).map(drop)),
+        (
+            "store icon cache",
+            FaviconCache::load(state_directory).map(drop),
+        ),
+        (
+            "throttled posts",
+            Throttler::load(state_directory).map(drop),
+        ),
+    ];
+    if let Some(login) = &config.login {
+        let cookies = session::load_cookies(state_directory, login).map(drop);
+        checks.push(("session cookies", cookies));
+    }
+    let failed = checks
+        .into_iter()
+        .map(|(name, result)| report(name, result))
+        .filter(|passed| !passed)
+        .count();
+    if failed > 0 {
+        return Err(Error::Diagnostics(failed));
+    }
+    Ok(())
+}
+
+fn check_destination(ctx: &Context, target: &Target) -> Result<()> {
+    let response = match target {
+        Target::Webhook { webhook_url, .. } => ctx.webhook.get(webhook_url)?,
+        Target::Bot {
+            bot_token,
+            channel_id,
+            ..
+        } => Bot::with_client(ctx.webhook.client(), bot_token).get_channel(channel_id)?,
+        Target::Matrix {
+            homeserver_url,
+            access_token,
+            ..
+        } => Matrix::with_client(ctx.webhook.client(), homeserver_url, access_token).whoami()?,
+        Target::Telegram { telegram_token, .. } => {
+            Telegram::with_client(ctx.webhook.client(), telegram_token).get_me()?
+        }
+        // can't be checked without running the command or posting a message
+        Target::Ntfy {
+            ntfy_url,
+            ntfy_token,
+            ..
+        } => Ntfy::with_client(ctx.webhook.client(), ntfy_url, ntfy_token).health()?,
+        Target::Email { smtp_url, from, to } => {
+            if !Mailer::new(smtp_url, from, to).test_connection()? {
+                return Err(Error::Email("SMTP server not responding".to_owned()));
+            }
+            return Ok(());
+        }
+        Target::Exec { .. } | Target::Slack { .. } => return Ok(()),
+        Target::Feed { feed_path, .. } => {
+            let directory = feed_path.parent().unwrap_or(feed_path);
+            if !directory.as_os_str().is_empty() && !directory.is_dir() {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            return Ok(());
+        }
+    };
+    response.error_for_status()?;
+    Ok(())
+}
+
+fn check_selector(fragment: &Html, selector: &Selector) -> Result<()> {
+    if fragment.select(selector).next().is_none() {
+        return Err(Error::Scraping);
+    }
+    Ok(())
+}
+
+// prints a pass/fail line per check, fails if any check did
+pub fn run(ctx: &Context) -> Result<()> {
+    let mut failed = 0;
+
+    for (index, destination) in ctx.config.destinations.iter().enumerate() {
+        let name = format!("destination {} ({})", index + 1, destination.target.kind());
+        if !report(&name, check_destination(ctx, &destination.target)) {
+            failed += 1;
+        }
+    }
+
+    let page = ctx
+        .forum_client
+        .get(get_page_url(ctx, u32::MAX))
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .and_then(reqwest::blocking::Response::text);
+    match page {
+        Ok(body) => {
+            report("forum connectivity", Ok(()));
+            let fragment = Html::parse_document(&body);
+            let selectors = ctx.selectors.borrow();
+            let checks = [
+                ("post", &selectors.post),
+                ("time", &selectors.time),
+                ("username", &selectors.username),
+                ("content", &selectors.content),
+                ("permalink", &selectors.permalink),
+            ];
+            for (name, selector) in checks {
+                if !report(
+                    &format!("{name} selector"),
+                    check_selector(&fragment, selector),
+                ) {
+                    failed += 1;
+                }
+            }
+        }
+        Err(err) => {
+            report("forum connectivity", Err(err.into()));
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Diagnostics(failed));
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// messages sent by webhooks are updated when the forum post is edited
+#[derive(Deserialize)]
+pub struct EditTrackingConfig {
+    // the newest already forwarded posts checked for edits on every run
+    #[serde(default = "default_posts")]
+    pub posts: usize,
+}
+
+fn default_posts() -> usize {
+    20
+}
+
+// compared to the hash stored when the post was sent
+pub fn content_hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content))
+}
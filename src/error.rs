@@ -1,29 +1,120 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::process;
 use std::result;
 
+use tarjousbot::forum;
+
+use crate::webhook::LimitExceeded;
+
 #[derive(Debug)]
 pub enum Error {
     Io(io::Error),
     Reqwest(reqwest::Error),
+    Config(toml::de::Error),
+    InvalidConfig(String),
+    InvalidState(String),
+    Json(serde_json::Error),
+    Sqlite(rusqlite::Error),
     Scraping,
+    Diagnostics(usize),
+    Exec(process::ExitStatus),
+    Plugin(String),
+    Script(String),
+    Update(String),
+    Email(String),
+    EmbedLimit(LimitExceeded),
+    Login(String),
+    Usage,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Io(err) => write!(f, "IO error: {}", err),
-            Self::Reqwest(err) => write!(f, "Web request error: {}", err),
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::Reqwest(err) => write!(f, "Web request error: {err}"),
+            Self::Config(err) => write!(f, "Config error: {err}"),
+            Self::InvalidConfig(message) => write!(f, "Invalid config: {message}"),
+            Self::InvalidState(message) => write!(f, "Invalid state: {message}"),
+            Self::Json(err) => write!(f, "JSON error: {err}"),
+            Self::Sqlite(err) => write!(f, "Database error: {err}"),
             Self::Scraping => f.write_str("Error scraping webpage"),
+            Self::Diagnostics(failed) => write!(f, "{failed} diagnostic checks failed"),
+            Self::Exec(status) => write!(f, "Command failed: {status}"),
+            Self::Plugin(message) => write!(f, "Plugin error: {message}"),
+            Self::Script(message) => write!(f, "Script error: {message}"),
+            Self::Update(message) => write!(f, "Self-update failed: {message}"),
+            Self::Email(message) => write!(f, "Email error: {message}"),
+            Self::EmbedLimit(err) => write!(f, "Invalid embed: {err}"),
+            Self::Login(message) => write!(f, "Forum login failed: {message}"),
+            Self::Usage => f.write_str("Invalid arguments, see tarjousbot --help"),
         }
     }
 }
 
 impl error::Error for Error {}
 
+impl Error {
+    // retrying won't help, e.g. a deleted webhook or a message the destination rejects
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Reqwest(err) => err.status().is_some_and(|status| {
+                status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+            }),
+            Self::EmbedLimit(_) => true,
+            _ => false,
+        }
+    }
+}
+
 pub type Result<T> = result::Result<T, Error>;
 
+impl From<wasmtime::Error> for Error {
+    fn from(err: wasmtime::Error) -> Self {
+        Self::Plugin(err.to_string())
+    }
+}
+
+impl From<Box<rhai::EvalAltResult>> for Error {
+    fn from(err: Box<rhai::EvalAltResult>) -> Self {
+        Self::Script(err.to_string())
+    }
+}
+
+impl From<lettre::error::Error> for Error {
+    fn from(err: lettre::error::Error) -> Self {
+        Self::Email(err.to_string())
+    }
+}
+
+impl From<lettre::address::AddressError> for Error {
+    fn from(err: lettre::address::AddressError) -> Self {
+        Self::Email(err.to_string())
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for Error {
+    fn from(err: lettre::transport::smtp::Error) -> Self {
+        Self::Email(err.to_string())
+    }
+}
+
+impl From<LimitExceeded> for Error {
+    fn from(err: LimitExceeded) -> Self {
+        Self::EmbedLimit(err)
+    }
+}
+
+impl From<forum::Error> for Error {
+    fn from(err: forum::Error) -> Self {
+        match err {
+            forum::Error::Reqwest(err) => Self::Reqwest(err),
+            forum::Error::Scraping => Self::Scraping,
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
         Self::Io(err)
@@ -35,3 +126,21 @@ impl From<reqwest::Error> for Error {
         Self::Reqwest(err)
     }
 }
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Config(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Sqlite(err)
+    }
+}
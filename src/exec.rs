@@ -0,0 +1,47 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+// longer values are only available through stdin
+const MAX_ENV_VALUE_LENGTH: usize = 4096;
+
+// top level strings and numbers are also passed as TARJOUSBOT_<KEY> variables
+fn env_vars(input: &Value) -> Vec<(String, String)> {
+    let Some(object) = input.as_object() else {
+        return Vec::new();
+    };
+    object
+        .iter()
+        .filter_map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Number(value) => value.to_string(),
+                _ => return None,
+            };
+            Some((format!("TARJOUSBOT_{}", key.to_uppercase()), value))
+        })
+        .filter(|(_, value)| value.len() <= MAX_ENV_VALUE_LENGTH)
+        .collect()
+}
+
+// runs the command through the shell with the json on stdin
+pub fn run(command: &str, input: &Value) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env_vars(input))
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        serde_json::to_writer(&mut stdin, input)?;
+        stdin.write_all(b"\n")?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(Error::Exec(status));
+    }
+    Ok(())
+}
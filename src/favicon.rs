@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use reqwest::blocking::Client;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use tracing::{debug, warn};
+
+use crate::error::Result;
+
+// maps store domains to icon urls, None meaning no icon could be found
+pub struct FaviconCache {
+    path: PathBuf,
+    icons: HashMap<String, Option<String>>,
+    dirty: bool,
+}
+
+pub fn store_domain(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(host.strip_prefix("www.").unwrap_or(host).to_owned())
+}
+
+fn fetch_favicon(client: &Client, domain: &str) -> Option<String> {
+    let base_url = Url::parse(&format!("https://{domain}/")).ok()?;
+    let icon_selector = Selector::parse(r#"link[rel~="icon"]"#).unwrap();
+
+    let from_page = client
+        .get(base_url.clone())
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .and_then(reqwest::blocking::Response::text)
+        .ok()
+        .and_then(|body| {
+            Html::parse_document(&body)
+                .select(&icon_selector)
+                .find_map(|element| element.value().attr("href").map(str::to_owned))
+        })
+        .and_then(|href| base_url.join(&href).ok());
+    if let Some(icon_url) = from_page {
+        return Some(icon_url.to_string());
+    }
+
+    let fallback = base_url.join("/favicon.ico").ok()?;
+    client
+        .head(fallback.clone())
+        .send()
+        .ok()
+        .filter(|response| response.status().is_success())
+        .map(|_| fallback.to_string())
+}
+
+impl FaviconCache {
+    pub fn load(state_directory: &Path) -> Result<Self> {
+        let path = state_directory.join("favicons.json");
+        let icons = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            icons,
+            dirty: false,
+        })
+    }
+
+    // the client is only built when the icon has to be fetched
+    pub fn icon_url(
+        &mut self,
+        client: impl FnOnce() -> Result<Client>,
+        overrides: &HashMap<String, String>,
+        fetch: bool,
+        domain: &str,
+    ) -> Option<String> {
+        if let Some(icon_url) = overrides.get(domain) {
+            return Some(icon_url.clone());
+        }
+        if let Some(icon_url) = self.icons.get(domain) {
+            return icon_url.clone();
+        }
+        if !fetch {
+            return None;
+        }
+        debug!("Fetch favicon for {domain}");
+        let client = match client() {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("building the client for the favicon of {domain} failed: {err}");
+                return None;
+            }
+        };
+        let icon_url = fetch_favicon(&client, domain);
+        self.icons.insert(domain.to_owned(), icon_url.clone());
+        self.dirty = true;
+        icon_url
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if self.dirty {
+            fs::write(&self.path, serde_json::to_string(&self.icons)?)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::html::escape;
+use crate::state::write_atomically;
+use crate::{Context, Message};
+
+#[derive(Serialize, Deserialize)]
+struct FeedItem {
+    title: String,
+    link: Option<String>,
+    description: String,
+    author: Option<String>,
+    published: DateTime<Utc>,
+}
+
+// the items are kept next to the feed, so that it never has to be parsed back
+fn items_path(feed_path: &Path) -> PathBuf {
+    let mut path = feed_path.as_os_str().to_owned();
+    path.push(".items.json");
+    PathBuf::from(path)
+}
+
+fn load_items(path: &Path) -> Result<Vec<FeedItem>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(serde_json::from_str(&s)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn render(link: &str, items: &[FeedItem]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<rss version=\"2.0\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n",
+    );
+    xml.push_str("<title>Tarjousbotti</title>\n");
+    let _ = writeln!(xml, "<link>{}</link>", escape(link));
+    xml.push_str("<description>Uudet tarjoukset</description>\n");
+    for item in items {
+        xml.push_str("<item>\n");
+        let _ = writeln!(xml, "<title>{}</title>", escape(&item.title));
+        if let Some(link) = &item.link {
+            let _ = writeln!(xml, "<link>{0}</link>\n<guid>{0}</guid>", escape(link));
+        }
+        let _ = writeln!(
+            xml,
+            "<description>{}</description>",
+            escape(&item.description)
+        );
+        if let Some(author) = &item.author {
+            let _ = writeln!(xml, "<dc:creator>{}</dc:creator>", escape(author));
+        }
+        let _ = writeln!(xml, "<pubDate>{}</pubDate>", item.published.to_rfc2822());
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+// the newest item goes first, the oldest are dropped past max_items
+fn append(ctx: &Context, feed_path: &Path, max_items: usize, item: FeedItem) -> Result<()> {
+    let items_path = items_path(feed_path);
+    let mut items = load_items(&items_path)?;
+    items.insert(0, item);
+    items.truncate(max_items);
+    write_atomically(&items_path, &serde_json::to_string(&items)?)?;
+    write_atomically(feed_path, &render(&ctx.config.thread_url, &items))
+}
+
+// drops the items matching by author and publication time, returns how many were dropped
+pub fn purge(
+    link: &str,
+    feed_path: &Path,
+    matches: impl Fn(Option<&str>, i64) -> bool,
+) -> Result<usize> {
+    let items_path = items_path(feed_path);
+    let mut items = load_items(&items_path)?;
+    let count = items.len();
+    items.retain(|item| !matches(item.author.as_deref(), item.published.timestamp()));
+    let purged = count - items.len();
+    if purged > 0 {
+        write_atomically(&items_path, &serde_json::to_string(&items)?)?;
+        write_atomically(feed_path, &render(link, &items))?;
+    }
+    Ok(purged)
+}
+
+pub fn add_message(
+    ctx: &Context,
+    feed_path: &Path,
+    max_items: usize,
+    message: &Message,
+) -> Result<()> {
+    let embed = &message.embed.embed;
+    let mut description = embed.description.as_deref().unwrap_or_default().to_owned();
+    for field in &embed.fields {
+        let _ = write!(description, "\n{}: {}", field.name, field.value);
+    }
+    let published = embed
+        .timestamp
+        .as_deref()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map_or_else(Utc::now, |published| published.with_timezone(&Utc));
+    let item = FeedItem {
+        title: embed.title.as_deref().unwrap_or(message.title).to_owned(),
+        link: embed.url.as_deref().map(str::to_owned),
+        description,
+        author: embed.author_name().map(str::to_owned),
+        published,
+    };
+    append(ctx, feed_path, max_items, item)
+}
+
+pub fn add_text(
+    ctx: &Context,
+    feed_path: &Path,
+    max_items: usize,
+    title: &str,
+    text: &str,
+) -> Result<()> {
+    let item = FeedItem {
+        title: title.to_owned(),
+        link: None,
+        description: text.to_owned(),
+        author: None,
+        published: Utc::now(),
+    };
+    append(ctx, feed_path, max_items, item)
+}
@@ -0,0 +1,592 @@
+use std::collections::VecDeque;
+use std::error;
+use std::fmt::{self, Write};
+use std::result;
+
+use chrono::DateTime;
+use reqwest::blocking::Client;
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+
+pub static FORUM_URL: &str = "https://bbs.io-tech.fi";
+
+#[derive(Debug)]
+pub enum Error {
+    Reqwest(reqwest::Error),
+    Scraping,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reqwest(err) => write!(f, "Web request error: {err}"),
+            Self::Scraping => f.write_str("Error scraping webpage"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Reqwest(err)
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+pub struct Selectors {
+    pub post: Selector,
+    pub next_page: Selector,
+    pub time: Selector,
+    pub username: Selector,
+    pub avatar: Selector,
+    pub content: Selector,
+    pub permalink: Selector,
+    pub link: Selector,
+    pub image: Selector,
+    pub quote: Selector,
+}
+
+impl Selectors {
+    pub fn new() -> Self {
+        Self {
+            post: Selector::parse(".message").unwrap(),
+            next_page: Selector::parse(".pageNav-page--current+ .pageNav-page").unwrap(),
+            time: Selector::parse(".u-dt").unwrap(),
+            username: Selector::parse(".username").unwrap(),
+            avatar: Selector::parse(".avatar img").unwrap(),
+            content: Selector::parse(".bbWrapper").unwrap(),
+            permalink: Selector::parse(".message-attribution-main a").unwrap(),
+            link: Selector::parse(".bbWrapper a[href]").unwrap(),
+            image: Selector::parse(".bbWrapper img.bbImage").unwrap(),
+            quote: Selector::parse(".bbWrapper blockquote[data-source]").unwrap(),
+        }
+    }
+}
+
+impl Selectors {
+    // by the field name, for replacing selectors at runtime
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Selector> {
+        match name {
+            "post" => Some(&mut self.post),
+            "next_page" => Some(&mut self.next_page),
+            "time" => Some(&mut self.time),
+            "username" => Some(&mut self.username),
+            "avatar" => Some(&mut self.avatar),
+            "content" => Some(&mut self.content),
+            "permalink" => Some(&mut self.permalink),
+            "link" => Some(&mut self.link),
+            "image" => Some(&mut self.image),
+            "quote" => Some(&mut self.quote),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Selectors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct Post {
+    pub id: u32,
+    pub url: String,
+    pub author: String,
+    pub author_url: String,
+    pub avatar_url: Option<String>,
+    pub timestamp: String,
+    pub html: String,
+    // discord flavored markdown
+    pub markdown: String,
+    pub urls: Vec<String>,
+    pub images: Vec<String>,
+    // ids of the posts quoted in the post
+    pub quoted: Vec<u32>,
+}
+
+// ids of the posts linked in the text, e.g. .../threads/151/post-123 or .../posts/123/
+pub fn linked_post_ids(text: &str) -> Vec<u32> {
+    let parse_id = |rest: &str| -> Option<u32> {
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    };
+    text.split(|c: char| c.is_whitespace() || "()<>[]\"".contains(c))
+        .filter_map(|word| word.strip_prefix(FORUM_URL))
+        .filter_map(|path| {
+            if let Some((_, rest)) = path.split_once("/posts/") {
+                return parse_id(rest);
+            }
+            // only the last segment, thread slugs like repost-4070 aren't post ids
+            let segment = path.trim_end_matches('/').rsplit(['/', '#']).next()?;
+            parse_id(segment.strip_prefix("post-")?)
+        })
+        .collect()
+}
+
+pub fn page_url(thread_url: &str, page: u32) -> String {
+    format!("{}/page-{page}", thread_url.trim_end_matches('/'))
+}
+
+pub fn get_post_id(post: ElementRef) -> Result<u32> {
+    post.value()
+        .attr("data-content")
+        .ok_or(Error::Scraping)?
+        .strip_prefix("post-")
+        .ok_or(Error::Scraping)?
+        .parse()
+        .or(Err(Error::Scraping))
+}
+
+pub fn get_post_url(post: ElementRef, permalink_selector: &Selector, post_id: u32) -> String {
+    match post
+        .select(permalink_selector)
+        .next()
+        .and_then(|element| element.value().attr("href"))
+    {
+        Some(href) => format!("{FORUM_URL}{href}"),
+        None => format!("{FORUM_URL}/posts/{post_id}/"),
+    }
+}
+
+fn get_content_element<'a>(
+    post: ElementRef<'a>,
+    content_selector: &Selector,
+) -> Result<ElementRef<'a>> {
+    post.select(content_selector).next().ok_or(Error::Scraping)
+}
+
+// wraps the text in the markers, keeping surrounding whitespace outside them
+fn push_emphasized(out: &mut String, text: &str, marker: &str) {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        out.push_str(text);
+        return;
+    }
+    let start = text.len() - text.trim_start().len();
+    let end = text.trim_end().len();
+    out.push_str(&text[..start]);
+    out.push_str(marker);
+    out.push_str(trimmed);
+    out.push_str(marker);
+    out.push_str(&text[end..]);
+}
+
+fn push_line_break(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn children_markdown(element: ElementRef) -> String {
+    let mut out = String::new();
+    for child in element.children() {
+        match child.value() {
+            scraper::Node::Text(text) => out.push_str(text),
+            scraper::Node::Element(_) => {
+                write_markdown(ElementRef::wrap(child).unwrap(), &mut out);
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+fn write_list(element: ElementRef, out: &mut String, ordered: bool) {
+    push_line_break(out);
+    let items = element
+        .children()
+        .filter_map(ElementRef::wrap)
+        .filter(|child| child.value().name() == "li");
+    for (index, item) in items.enumerate() {
+        if ordered {
+            let _ = write!(out, "{}. ", index + 1);
+        } else {
+            out.push_str("- ");
+        }
+        out.push_str(children_markdown(item).trim());
+        out.push('\n');
+    }
+}
+
+// discord flavored markdown of the element
+fn write_markdown(element: ElementRef, out: &mut String) {
+    let value = element.value();
+    // link previews are replaced by the link itself
+    if let Some(url) = value
+        .attr("data-url")
+        .filter(|_| value.classes().any(|class| class == "bbCodeBlock--unfurl"))
+    {
+        push_line_break(out);
+        out.push_str(url);
+        out.push('\n');
+        return;
+    }
+    match value.name() {
+        "br" => out.push('\n'),
+        "b" | "strong" => push_emphasized(out, &children_markdown(element), "**"),
+        "i" | "em" => push_emphasized(out, &children_markdown(element), "*"),
+        "u" => push_emphasized(out, &children_markdown(element), "__"),
+        "s" | "strike" | "del" => push_emphasized(out, &children_markdown(element), "~~"),
+        "a" => {
+            let text = children_markdown(element);
+            match value.attr("href") {
+                Some(href) if text.trim().is_empty() || text.trim() == href => out.push_str(href),
+                Some(href) => {
+                    let _ = write!(out, "[{}]({href})", text.trim());
+                }
+                None => out.push_str(&text),
+            }
+        }
+        "ul" => write_list(element, out, false),
+        "ol" => write_list(element, out, true),
+        "blockquote" => {
+            push_line_break(out);
+            for line in children_markdown(element).trim().lines() {
+                let _ = writeln!(out, "> {}", line.trim());
+            }
+        }
+        "img" | "script" | "style" => {}
+        "div" | "p" => {
+            push_line_break(out);
+            out.push_str(&children_markdown(element));
+            push_line_break(out);
+        }
+        _ => out.push_str(&children_markdown(element)),
+    }
+}
+
+pub fn get_content(post: ElementRef, content_selector: &Selector) -> Result<String> {
+    Ok(children_markdown(get_content_element(
+        post,
+        content_selector,
+    )?))
+}
+
+pub fn get_links(post: ElementRef, link_selector: &Selector) -> Vec<String> {
+    post.select(link_selector)
+        .filter_map(|element| element.value().attr("href"))
+        .map(str::to_owned)
+        .collect()
+}
+
+// quotes refer to their source like data-source="post: 123"
+pub fn get_quoted_post_ids(post: ElementRef, quote_selector: &Selector) -> Vec<u32> {
+    post.select(quote_selector)
+        .filter_map(|element| element.value().attr("data-source"))
+        .filter_map(|source| source.strip_prefix("post:"))
+        .filter_map(|id| id.trim().parse().ok())
+        .collect()
+}
+
+// proxied images keep the original url in data-url and lazy loaded ones in data-src
+pub fn get_image_urls(post: ElementRef, image_selector: &Selector) -> Vec<String> {
+    post.select(image_selector)
+        .filter_map(|element| {
+            let element = element.value();
+            element
+                .attr("data-url")
+                .or_else(|| element.attr("data-src"))
+                .or_else(|| element.attr("src"))
+        })
+        // relative to the forum, including protocol relative ones like //host/image.jpg
+        .filter_map(|src| Url::parse(FORUM_URL).ok()?.join(src).ok())
+        .map(String::from)
+        .collect()
+}
+
+pub fn get_avatar_url(post: ElementRef, avatar_selector: &Selector) -> Result<Option<String>> {
+    let avatar_url = post
+        .select(avatar_selector)
+        .next()
+        .map(|element| {
+            element
+                .value()
+                .attr("src")
+                .ok_or(Error::Scraping)
+                .map(|s| format!("{FORUM_URL}{s}"))
+        })
+        .transpose()?;
+    Ok(avatar_url)
+}
+
+pub fn get_user_url(username_element: ElementRef) -> Result<String> {
+    let user_url = format!(
+        "{FORUM_URL}{}",
+        username_element
+            .value()
+            .attr("href")
+            .ok_or(Error::Scraping)?
+    );
+    Ok(user_url)
+}
+
+pub fn get_username_str(username_element: ElementRef<'_>) -> Result<&str> {
+    let username = username_element.text().next().ok_or(Error::Scraping)?;
+    Ok(username)
+}
+
+pub fn get_username_element<'a>(
+    post: ElementRef<'a>,
+    username_selector: &Selector,
+) -> Result<ElementRef<'a>> {
+    let username_element = post
+        .select(username_selector)
+        .next()
+        .ok_or(Error::Scraping)?;
+    Ok(username_element)
+}
+
+pub fn get_timestamp<'a>(post: ElementRef<'a>, time_selector: &Selector) -> Result<&'a str> {
+    let timestamp = post
+        .select(time_selector)
+        .next()
+        .ok_or(Error::Scraping)?
+        .value()
+        .attr("datetime")
+        .ok_or(Error::Scraping)?;
+    Ok(timestamp)
+}
+
+// names of the selectors matching nothing in the post, for noticing markup changes
+pub fn missing_selectors(post: ElementRef, selectors: &Selectors) -> Vec<&'static str> {
+    [
+        ("username", &selectors.username),
+        ("avatar", &selectors.avatar),
+        ("timestamp", &selectors.time),
+        ("content", &selectors.content),
+    ]
+    .iter()
+    .filter(|(_, selector)| post.select(selector).next().is_none())
+    .map(|(name, _)| *name)
+    .collect()
+}
+
+pub fn parse_post(post: ElementRef, selectors: &Selectors) -> Result<Post> {
+    let id = get_post_id(post)?;
+    let username_element = get_username_element(post, &selectors.username)?;
+    Ok(Post {
+        id,
+        url: get_post_url(post, &selectors.permalink, id),
+        author: get_username_str(username_element)?.to_owned(),
+        author_url: get_user_url(username_element)?,
+        avatar_url: get_avatar_url(post, &selectors.avatar)?,
+        timestamp: get_timestamp(post, &selectors.time)?.to_owned(),
+        html: get_content_element(post, &selectors.content)?.inner_html(),
+        markdown: get_content(post, &selectors.content)?,
+        urls: get_links(post, &selectors.link),
+        images: get_image_urls(post, &selectors.image),
+        quoted: get_quoted_post_ids(post, &selectors.quote),
+    })
+}
+
+// e.g. https://bbs.io-tech.fi/threads/151/index.rss, listing the newest posts
+pub fn feed_url(thread_url: &str) -> String {
+    format!("{}/index.rss", thread_url.trim_end_matches('/'))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#039;", "'")
+        .replace("&amp;", "&")
+}
+
+// the text of the first element with the name, from a CDATA section or unescaped
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let rest = &xml[xml.find(&format!("<{name}>"))? + name.len() + 2..];
+    let text = rest[..rest.find(&format!("</{name}>"))?].trim();
+    Some(
+        match text
+            .strip_prefix("<![CDATA[")
+            .and_then(|text| text.strip_suffix("]]>"))
+        {
+            Some(text) => text.to_owned(),
+            None => unescape_xml(text),
+        },
+    )
+}
+
+fn parse_feed_item(item: &str, selectors: &Selectors) -> Result<Post> {
+    let url = element_text(item, "link").ok_or(Error::Scraping)?;
+    let id = linked_post_ids(&url)
+        .into_iter()
+        .next()
+        .ok_or(Error::Scraping)?;
+    let timestamp = element_text(item, "pubDate")
+        .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+        .ok_or(Error::Scraping)?
+        .format("%Y-%m-%dT%H:%M:%S%z")
+        .to_string();
+    let html = element_text(item, "content:encoded")
+        .or_else(|| element_text(item, "description"))
+        .ok_or(Error::Scraping)?;
+    // the content is given the markup of the pages for the selectors
+    let fragment = Html::parse_fragment(&format!("<div class=\"bbWrapper\">{html}</div>"));
+    let post = fragment.root_element();
+    Ok(Post {
+        id,
+        author: element_text(item, "dc:creator").ok_or(Error::Scraping)?,
+        // the feed has no profile links
+        author_url: url.clone(),
+        url,
+        avatar_url: None,
+        timestamp,
+        html: get_content_element(post, &selectors.content)?.inner_html(),
+        markdown: get_content(post, &selectors.content)?,
+        urls: get_links(post, &selectors.link),
+        images: get_image_urls(post, &selectors.image),
+        quoted: get_quoted_post_ids(post, &selectors.quote),
+    })
+}
+
+// the posts of a thread feed oldest first, the feed has no avatars
+pub fn parse_feed(xml: &str, selectors: &Selectors) -> Result<Vec<Post>> {
+    let mut posts = xml
+        .split("<item>")
+        .skip(1)
+        .map(|item| parse_feed_item(item.split("</item>").next().unwrap_or(item), selectors))
+        .collect::<Result<Vec<_>>>()?;
+    posts.sort_unstable_by_key(|post| post.id);
+    Ok(posts)
+}
+
+// iterates the posts of a thread page by page, fetching pages as needed
+#[must_use]
+pub struct ThreadScraper<'a> {
+    client: &'a Client,
+    thread_url: String,
+    selectors: Selectors,
+    next_page: Option<u32>,
+    after: Option<u32>,
+    posts: VecDeque<Post>,
+}
+
+impl<'a> ThreadScraper<'a> {
+    // e.g. https://bbs.io-tech.fi/threads/151
+    pub fn new(client: &'a Client, thread_url: &str) -> Self {
+        Self {
+            client,
+            thread_url: thread_url.to_owned(),
+            selectors: Selectors::new(),
+            next_page: Some(1),
+            after: None,
+            posts: VecDeque::new(),
+        }
+    }
+
+    pub fn from_page(mut self, page: u32) -> Self {
+        self.next_page = Some(page);
+        self
+    }
+
+    // skips posts up to and including the given id
+    pub fn after(mut self, post_id: u32) -> Self {
+        self.after = Some(post_id);
+        self
+    }
+
+    fn fetch_page(&mut self, page: u32) -> Result<()> {
+        let body = self
+            .client
+            .get(page_url(&self.thread_url, page))
+            .send()?
+            .error_for_status()?
+            .text()?;
+        let fragment = Html::parse_document(&body);
+        for element in fragment.select(&self.selectors.post) {
+            let post = parse_post(element, &self.selectors)?;
+            if self.after.is_none_or(|after| post.id > after) {
+                self.posts.push_back(post);
+            }
+        }
+        self.next_page = fragment
+            .select(&self.selectors.next_page)
+            .next()
+            .and_then(|element| element.text().next())
+            .and_then(|text| text.trim().parse().ok());
+        Ok(())
+    }
+}
+
+impl Iterator for ThreadScraper<'_> {
+    type Item = Result<Post>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.posts.is_empty() {
+            let page = self.next_page.take()?;
+            if let Err(err) = self.fetch_page(page) {
+                return Some(Err(err));
+            }
+        }
+        self.posts.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+  <channel>
+    <title>Tarjoukset</title>
+    <item>
+      <title>Tarjoukset</title>
+      <pubDate>Tue, 02 Jan 2024 12:00:00 +0000</pubDate>
+      <link>https://bbs.io-tech.fi/threads/151/post-1002</link>
+      <dc:creator>toinen</dc:creator>
+      <content:encoded><![CDATA[<b>Hinta: 99 €</b> <a href="https://kauppa.fi/tuote">kauppa</a>]]></content:encoded>
+    </item>
+    <item>
+      <title>Tarjoukset</title>
+      <pubDate>Mon, 01 Jan 2024 12:00:00 +0000</pubDate>
+      <link>https://bbs.io-tech.fi/threads/151/post-1001</link>
+      <dc:creator>Tom &amp; Jerry</dc:creator>
+      <description>Halpa &lt;i&gt;näyttö&lt;/i&gt;</description>
+    </item>
+  </channel>
+</rss>"#;
+
+    #[test]
+    fn parses_feed_items_oldest_first() {
+        let posts = parse_feed(FEED, &Selectors::new()).unwrap();
+        assert_eq!(
+            posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+            [1001, 1002]
+        );
+        assert_eq!(posts[0].author, "Tom & Jerry");
+        assert_eq!(posts[0].timestamp, "2024-01-01T12:00:00+0000");
+        assert_eq!(posts[0].avatar_url, None);
+        assert!(posts[0].html.contains("<i>näyttö</i>"));
+        assert_eq!(posts[1].url, "https://bbs.io-tech.fi/threads/151/post-1002");
+        assert_eq!(posts[1].urls, ["https://kauppa.fi/tuote"]);
+        assert!(posts[1].markdown.contains("Hinta: 99 €"));
+    }
+
+    #[test]
+    fn rejects_items_without_a_post_link() {
+        let feed = FEED.replace("post-1001", "");
+        assert!(parse_feed(&feed, &Selectors::new()).is_err());
+    }
+
+    #[test]
+    fn parses_an_empty_feed() {
+        let posts = parse_feed("<rss><channel></channel></rss>", &Selectors::new()).unwrap();
+        assert!(posts.is_empty());
+    }
+
+    #[test]
+    fn finds_linked_post_ids() {
+        let text = "https://bbs.io-tech.fi/threads/repost-4070.123/post-999 \
+            (https://bbs.io-tech.fi/posts/1001/) \
+            https://bbs.io-tech.fi/threads/151/page-3#post-1002 \
+            https://bbs.io-tech.fi/threads/repost-4070.123/";
+        assert_eq!(linked_post_ids(text), vec![999, 1001, 1002]);
+    }
+}
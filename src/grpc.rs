@@ -0,0 +1,94 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener as StdTcpListener};
+use std::pin::Pin;
+use std::thread;
+
+use serde::Deserialize;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+#[allow(clippy::all, clippy::pedantic)]
+mod proto {
+    tonic::include_proto!("tarjousbot");
+}
+
+use proto::deals_server::{Deals, DealsServer};
+pub use proto::Deal;
+use proto::SubscribeRequest;
+
+// deals a slow client can fall behind by before missing some
+const BUFFER_SIZE: usize = 64;
+
+#[derive(Deserialize)]
+pub struct GrpcConfig {
+    // e.g. 127.0.0.1:50051
+    pub address: String,
+}
+
+struct DealsService {
+    sender: broadcast::Sender<Deal>,
+}
+
+#[tonic::async_trait]
+impl Deals for DealsService {
+    type SubscribeStream = Pin<Box<dyn Stream<Item = std::result::Result<Deal, Status>> + Send>>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<SubscribeRequest>,
+    ) -> std::result::Result<Response<Self::SubscribeStream>, Status> {
+        // lagging clients skip the deals they missed
+        let stream =
+            BroadcastStream::new(self.sender.subscribe()).filter_map(|deal| deal.ok().map(Ok));
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+pub struct DealFeed {
+    sender: broadcast::Sender<Deal>,
+}
+
+impl DealFeed {
+    pub fn publish(&self, deal: Deal) {
+        // fails only when nobody is connected
+        let _ = self.sender.send(deal);
+    }
+}
+
+// serves the deal stream from a separate thread
+pub fn start(config: &GrpcConfig) -> Result<DealFeed> {
+    let address: SocketAddr = config
+        .address
+        .parse()
+        .map_err(|_| Error::InvalidConfig(format!("invalid grpc address {}", config.address)))?;
+    let (sender, _) = broadcast::channel(BUFFER_SIZE);
+    let service = DealsService {
+        sender: sender.clone(),
+    };
+    // bound here so that an address in use fails the start instead of a background thread
+    let listener = StdTcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let incoming = {
+        let _context = runtime.enter();
+        TcpIncoming::from_listener(TcpListener::from_std(listener)?, false, None)
+            .map_err(io::Error::other)?
+    };
+    thread::spawn(move || {
+        let server = Server::builder()
+            .add_service(DealsServer::new(service))
+            .serve_with_incoming(incoming);
+        if let Err(err) = runtime.block_on(server) {
+            warn!("gRPC server failed: {err}");
+        }
+    });
+    Ok(DealFeed { sender })
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
+use reqwest::blocking::{Client, ClientBuilder};
+use reqwest::{Certificate, Proxy};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::dns::Resolver;
+use crate::error::Result;
+
+static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IpFamily {
+    #[default]
+    Any,
+    PreferIpv4,
+    PreferIpv6,
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Deserialize, Default)]
+pub struct HttpConfig {
+    // http://, https:// or socks5:// proxy used for fetching forum pages
+    pub forum_proxy: Option<String>,
+    // proxy used for notifications and other outgoing requests
+    pub notification_proxy: Option<String>,
+    // additional trusted root certificates in PEM format
+    #[serde(default)]
+    pub ca_certificates: Vec<PathBuf>,
+    // disables certificate validation, only meant for debugging
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+    #[serde(default)]
+    pub ip_family: IpFamily,
+}
+
+fn resolved_addrs(resolver: &mut Resolver, host: &str, ip_family: IpFamily) -> Vec<SocketAddr> {
+    let mut addrs = match resolver.lookup(host) {
+        Ok(addrs) => addrs,
+        Err(err) => {
+            warn!("resolving {host} failed: {err}");
+            return Vec::new();
+        }
+    };
+    match ip_family {
+        IpFamily::PreferIpv4 => addrs.sort_by_key(|addr| !addr.is_ipv4()),
+        IpFamily::PreferIpv6 => addrs.sort_by_key(IpAddr::is_ipv4),
+        _ => {}
+    }
+    // the port is taken from the url anyway
+    addrs
+        .into_iter()
+        .map(|addr| SocketAddr::new(addr, 0))
+        .collect()
+}
+
+// hosts are resolved up front when using a custom resolver or preferring an address family
+pub fn pins_addresses(config: &HttpConfig, resolver: &Resolver) -> bool {
+    resolver.is_custom()
+        || matches!(
+            config.ip_family,
+            IpFamily::PreferIpv4 | IpFamily::PreferIpv6
+        )
+}
+
+pub fn build_client(
+    config: &HttpConfig,
+    proxy: Option<&str>,
+    hosts: &[&str],
+    resolver: &mut Resolver,
+) -> Result<Client> {
+    Ok(client_builder(config, proxy, hosts, resolver)?.build()?)
+}
+
+// for clients needing more than the configured settings
+pub fn client_builder(
+    config: &HttpConfig,
+    proxy: Option<&str>,
+    hosts: &[&str],
+    resolver: &mut Resolver,
+) -> Result<ClientBuilder> {
+    let mut builder = Client::builder().user_agent(APP_USER_AGENT);
+    // connections only use addresses of the same family as the local address
+    match config.ip_family {
+        IpFamily::Ipv4 => builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpFamily::Ipv6 => builder = builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        _ => {}
+    }
+    if pins_addresses(config, resolver) {
+        for host in hosts {
+            let addrs = resolved_addrs(resolver, host, config.ip_family);
+            if !addrs.is_empty() {
+                builder = builder.resolve_to_addrs(host, &addrs);
+            }
+        }
+    }
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    for path in &config.ca_certificates {
+        let certificate = Certificate::from_pem(&fs::read(path)?)?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if config.danger_accept_invalid_certs {
+        warn!("TLS certificate validation is disabled");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder)
+}
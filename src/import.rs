@@ -0,0 +1,78 @@
+use std::thread;
+use std::time::Duration;
+
+use scraper::Html;
+use tracing::{debug, info};
+
+use crate::archive::ArchivedPost;
+use crate::error::{Error, Result};
+use crate::source;
+use crate::state::State;
+use crate::{category, favicon, get_page_url, get_store_url, price, product, Context, ScrapedPost};
+
+// goes easy on the forum, the whole thread is thousands of pages
+const PAGE_DELAY: Duration = Duration::from_secs(2);
+
+// archives what can be derived without external lookups
+fn import_post(ctx: &Context, post: &ScrapedPost) -> Result<()> {
+    let category = category::classify(&ctx.config.categories, &post.title, &post.content);
+    let store = get_store_url(&post.links).and_then(favicon::store_domain);
+    let product_key = Some(post.title.as_str())
+        .filter(|_| post.has_title())
+        .map(|title| product::identity_key(title, &post.links));
+    ctx.archive.import_post(&ArchivedPost {
+        id: post.id,
+        author: &post.username,
+        title: &post.title,
+        product_key: product_key.as_deref(),
+        price: price::parse_price(&post.content),
+        url: &post.url,
+        timestamp: &post.timestamp,
+        content: &post.content,
+        category: category.map(|category| category.name.as_str()),
+        store: store.as_deref(),
+        store_url: get_store_url(&post.links),
+        typical_price: None,
+    })
+}
+
+// archives the whole thread from the first page without sending anything,
+// continuing from the last imported page when run again
+pub fn run(ctx: &Context) -> Result<()> {
+    let mut page = State::load(&ctx.state_directory)?.import_page.unwrap_or(1);
+
+    loop {
+        debug!("Import page {page}");
+        let page_url = get_page_url(ctx, page);
+        let body = ctx
+            .config
+            .retry
+            .send(|| ctx.forum_client.get(&page_url))?
+            .error_for_status()?
+            .text()?;
+        let fragment = Html::parse_document(&body);
+        let selectors = ctx.selectors.borrow();
+        for element in fragment.select(&selectors.post) {
+            let Some(post) = source::parse_or_warn(element, &selectors) else {
+                continue;
+            };
+            import_post(ctx, &ScrapedPost::from(post))?;
+        }
+
+        let next_page = fragment.select(&selectors.next_page).next();
+        let Some(next_page) = next_page else {
+            break;
+        };
+        page = next_page
+            .text()
+            .next()
+            .ok_or(Error::Scraping)?
+            .trim()
+            .parse()
+            .or(Err(Error::Scraping))?;
+        State::update(&ctx.state_directory, |state| state.import_page = Some(page))?;
+        thread::sleep(PAGE_DELAY);
+    }
+    info!("Imported the thread up to page {page}");
+    Ok(())
+}
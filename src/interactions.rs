@@ -0,0 +1,157 @@
+use std::convert::TryInto;
+use std::io;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Request, Response, Server};
+use tracing::warn;
+
+use crate::archive::Archive;
+use crate::error::{Error, Result};
+use crate::price;
+use crate::truncate;
+use crate::webhook::EmbedBuilder;
+
+static COMMAND_NAME: &str = "tarjous";
+
+#[derive(Deserialize, Clone)]
+pub struct InteractionsConfig {
+    // address of the interactions endpoint http server
+    pub listen: String,
+    pub application_id: String,
+    pub public_key: String,
+    pub bot_token: String,
+}
+
+pub fn command_definition() -> Value {
+    json!({
+        "name": COMMAND_NAME,
+        "description": "Tarjousarkisto",
+        "options": [{
+            "type": 1,
+            "name": "search",
+            "description": "Hae tarjouksia",
+            "options": [{
+                "type": 3,
+                "name": "query",
+                "description": "Hakusanat",
+                "required": true,
+            }],
+        }],
+    })
+}
+
+fn parse_public_key(public_key: &str) -> Result<VerifyingKey> {
+    let invalid = || Error::InvalidConfig("invalid interactions public key".to_owned());
+    let bytes: [u8; 32] = hex::decode(public_key)
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| invalid())
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str())
+}
+
+// discord signs the timestamp followed by the body
+fn verify(request: &Request, body: &str, public_key: &VerifyingKey) -> bool {
+    let signature = header(request, "X-Signature-Ed25519")
+        .and_then(|signature| hex::decode(signature).ok())
+        .and_then(|signature| signature.try_into().ok())
+        .map(|signature: [u8; 64]| Signature::from_bytes(&signature));
+    let timestamp = header(request, "X-Signature-Timestamp");
+    match (signature, timestamp) {
+        (Some(signature), Some(timestamp)) => {
+            let message = format!("{timestamp}{body}");
+            public_key.verify(message.as_bytes(), &signature).is_ok()
+        }
+        _ => false,
+    }
+}
+
+fn search_query(interaction: &Value) -> Option<&str> {
+    let subcommand = interaction.pointer("/data/options/0")?;
+    if subcommand.get("name")?.as_str()? != "search" {
+        return None;
+    }
+    subcommand.pointer("/options/0/value")?.as_str()
+}
+
+fn search_response(archive: &Archive, query: &str) -> Result<Value> {
+    let results = archive.search(query, 10)?;
+    let description = results
+        .iter()
+        .map(|result| {
+            let price = result.price.map(price::format_price).unwrap_or_default();
+            let date = result.timestamp.get(..10).unwrap_or(&result.timestamp);
+            format!("[{}]({}) {} {}", result.title, result.url, price, date)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let title = format!("Haku: {query}");
+    let mut embed = EmbedBuilder::new();
+    // a long query or result titles could go over the embed limits
+    embed.title(truncate(&title, 256));
+    if description.is_empty() {
+        embed.description("Ei tuloksia");
+    } else {
+        embed.description(truncate(&description, 4096));
+    }
+    Ok(json!({
+        "type": 4,
+        "data": { "embeds": [serde_json::to_value(&embed.embed)?] },
+    }))
+}
+
+fn handle(request: &mut Request, public_key: &VerifyingKey, archive: &Archive) -> (u16, Value) {
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        return (400, Value::Null);
+    }
+    if !verify(request, &body, public_key) {
+        return (401, Value::Null);
+    }
+    let interaction: Value = match serde_json::from_str(&body) {
+        Ok(interaction) => interaction,
+        Err(_) => return (400, Value::Null),
+    };
+    match interaction.get("type").and_then(Value::as_u64) {
+        // ping
+        Some(1) => (200, json!({ "type": 1 })),
+        Some(2) => match search_query(&interaction).map(|query| search_response(archive, query)) {
+            Some(Ok(response)) => (200, response),
+            Some(Err(err)) => {
+                warn!("archive search failed: {err}");
+                (500, Value::Null)
+            }
+            None => (400, Value::Null),
+        },
+        _ => (400, Value::Null),
+    }
+}
+
+// serves discord interactions until the server fails
+pub fn serve(config: &InteractionsConfig, state_directory: &Path) -> Result<()> {
+    let public_key = parse_public_key(&config.public_key)?;
+    let archive = Archive::open(state_directory)?;
+    let server = Server::http(&config.listen).map_err(io::Error::other)?;
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+    for mut request in server.incoming_requests() {
+        let (status, body) = handle(&mut request, &public_key, &archive);
+        let response = Response::from_string(body.to_string())
+            .with_status_code(status)
+            .with_header(content_type.clone());
+        if let Err(err) = request.respond(response) {
+            warn!("responding to interaction failed: {err}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,9 @@
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+
+// scraping io-tech threads, usable without the notification half of the bot
+pub mod forum;
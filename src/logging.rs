@@ -0,0 +1,32 @@
+use std::io::{self, IsTerminal};
+
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    // one object per line, for journald or loki
+    Json,
+}
+
+// verbosity is the -v count minus the -q count, RUST_LOG overrides it
+pub fn init(verbosity: i16, format: LogFormat) {
+    let level = match verbosity {
+        i16::MIN..=-2 => "error",
+        -1 => "warn",
+        0 => "info",
+        1 => "debug",
+        2..=i16::MAX => "trace",
+    };
+    // the dependencies only get to warn
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,tarjousbot={level}")));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    match format {
+        LogFormat::Text => builder.with_ansi(io::stderr().is_terminal()).init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
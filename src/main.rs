@@ -1,301 +1,1521 @@
 #![warn(clippy::all, clippy::pedantic)]
 
-use std::fs::File;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
 use std::io;
-use std::io::prelude::*;
-use std::path;
-use std::path::Path;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
+mod archive;
+mod avatar;
+mod bot;
+mod burst;
+mod campaign;
+mod canary;
+mod category;
+mod cli;
+mod config;
+mod dedup;
+mod digest;
+mod discovery;
+mod discussion;
+mod dns;
+mod doctor;
+mod edits;
+mod email;
 mod error;
+mod exec;
+mod favicon;
+mod feed;
+mod grpc;
+mod html;
+mod http;
+mod import;
+mod interactions;
+mod logging;
+mod matcher;
+mod matrix;
+mod metrics;
+mod notifier;
+mod ntfy;
+mod outbox;
+mod pin;
+mod pipeline;
+mod plugin;
+mod post;
+mod posters;
+mod price;
+mod price_comparison;
+mod product;
+mod purge;
+mod rate_limit;
+mod reactions;
+mod retry;
+mod rewrite;
+mod screenshot;
+mod script;
+mod secrets;
+mod session;
+mod slack;
+mod source;
+mod state;
+mod stats;
+mod telegram;
+mod telemetry;
+mod throttle;
+mod update;
+mod wayback;
 mod webhook;
+use crate::archive::{Archive, ArchivedPost, PriceHistory};
+use crate::avatar::AvatarCache;
+use crate::bot::{Bot, Channel};
+use crate::canary::Canary;
+use crate::category::Category;
+use crate::cli::{BurstMode, Cli, Command};
+use crate::config::{Config, PostImages, Target};
+use crate::dns::Resolver;
 use crate::error::Error;
 use crate::error::Result;
+use crate::favicon::FaviconCache;
+use crate::grpc::{Deal, DealFeed};
+use crate::interactions::InteractionsConfig;
+use crate::matcher::Keywords;
+use crate::metrics::Metrics;
+use crate::pipeline::{Pipeline, Sink};
+use crate::plugin::Plugins;
+use crate::post::DealFields;
+use crate::rate_limit::RateLimiter;
+use crate::rewrite::Rewrites;
+use crate::script::Scripts;
+use crate::telemetry::Tracer;
+use crate::throttle::Throttler;
+use crate::webhook::Attachment;
 use crate::webhook::EmbedBuilder;
+use crate::webhook::LinkButton;
 use crate::webhook::Webhook;
 
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use scraper::{ElementRef, Html, Selector};
+use chrono::Local;
+use clap::Parser;
+use reqwest::blocking::{Client, Response};
+use reqwest::cookie::Jar;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tarjousbot::forum::{self, Post, Selectors};
+use tracing::{debug, error, info, info_span, warn};
 
-static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"),);
 static APP_STATE_DIRECTORY: &str = "/etc/tarjousbot";
+static FORUM_HOST: &str = "bbs.io-tech.fi";
+static THREAD_URL: &str = "https://bbs.io-tech.fi/threads/151";
+static DEFAULT_TITLE: &str = "Uusi tarjous";
+static SCREENSHOT_ATTACHMENT_URL: &str = "attachment://screenshot.png";
+// leaves room for the other attachments under the upload limit
+const MAX_IMAGE_SIZE: u64 = 8 * 1024 * 1024;
 
-fn get_webhook_url() -> Result<String> {
-    let webhook_url_path = Path::new(APP_STATE_DIRECTORY).join("webhook.conf");
-    let mut s = String::new();
-    File::open(webhook_url_path)?.read_to_string(&mut s)?;
-    Ok(s)
+fn get_page_url(ctx: &Context, page: u32) -> String {
+    forum::page_url(&ctx.config.thread_url, page)
 }
 
-fn try_read_u32(path: path::PathBuf) -> Result<Option<u32>> {
-    let mut file = match File::open(path) {
-        Ok(f) => f,
-        Err(err) => {
-            if let io::ErrorKind::NotFound = err.kind() {
-                return Ok(None);
-            }
-            return Err(err.into());
+// the product of a templated first line, without the markdown emphasis
+fn get_title(content: &str, default_title: &str) -> String {
+    content
+        .lines()
+        .next()
+        .and_then(|line| post::labeled(line, "Tuote"))
+        .map_or_else(
+            || default_title.to_owned(),
+            |title| title.replace(['*', '~'], "").replace("__", ""),
+        )
+}
+
+// the first link pointing outside the forum is assumed to be the store link
+fn get_store_url(links: &[String]) -> Option<&str> {
+    links
+        .iter()
+        .map(String::as_str)
+        .find(|link| link.starts_with("http") && !link.contains("bbs.io-tech.fi"))
+}
+
+fn truncate(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        None => s,
+        Some((idx, _)) => &s[..idx],
+    }
+}
+
+fn truncate_with_read_more<'a>(s: &'a str, max_chars: usize, post_url: &str) -> Cow<'a, str> {
+    if s.chars().nth(max_chars).is_none() {
+        return Cow::Borrowed(s);
+    }
+    let read_more = format!("… [Lue lisää]({post_url})");
+    let max_content_chars = max_chars.saturating_sub(read_more.chars().count());
+    Cow::Owned(format!("{}{}", truncate(s, max_content_chars), read_more))
+}
+
+// splits at whitespace into parts of at most max_chars, marking all but the last
+// as continuing
+fn split_with_continuation(s: &str, max_chars: usize) -> Vec<Cow<'_, str>> {
+    const CONTINUES: &str = " (jatkuu…)";
+    let max_part_chars = max_chars.saturating_sub(CONTINUES.chars().count()).max(1);
+    let mut parts = Vec::new();
+    let mut rest = s;
+    while rest.chars().nth(max_chars).is_some() {
+        let part = truncate(rest, max_part_chars);
+        // words longer than a part are cut anyway
+        let end = part
+            .rfind(char::is_whitespace)
+            .filter(|&end| end > 0)
+            .unwrap_or(part.len());
+        parts.push(Cow::Owned(format!("{}{CONTINUES}", rest[..end].trim_end())));
+        rest = rest[end..].trim_start();
+    }
+    parts.push(Cow::Borrowed(rest));
+    parts
+}
+
+struct Message<'a> {
+    content: Option<&'a str>,
+    embed: &'a EmbedBuilder<'a>,
+    attachments: &'a [Attachment<'a>],
+    // only shown by webhook destinations
+    buttons: &'a [LinkButton<'a>],
+    title: &'a str,
+    category: Option<&'a Category>,
+    // structured form of the message for exec destinations
+    data: Option<&'a Value>,
+    // sent without push notifications
+    silent: bool,
+}
+
+// returns the id of the message when sent to a bot channel or a webhook
+fn send_message(ctx: &Context, target: &Target, message: &Message) -> Result<Option<String>> {
+    if ctx.dry_run {
+        print_payload(target, message)?;
+        return Ok(None);
+    }
+    let notifier = notifier::for_target(target);
+    ctx.rate_limiter.acquire(target.key());
+    match notifier.notify(ctx, message) {
+        Err(Error::Reqwest(err)) if err.status() == Some(StatusCode::BAD_REQUEST) => {
+            warn!("embed rejected, sending as plain text: {err}");
+            ctx.rate_limiter.acquire(target.key());
+            notifier.notify_plain(
+                ctx,
+                &plain_content(message),
+                message.content,
+                message.title,
+                message.silent,
+            )
         }
-    };
-    Ok(file.read_u32::<LittleEndian>().ok())
+        result => result,
+    }
 }
 
-fn write_u32(path: path::PathBuf, u: u32) -> Result<()> {
-    let mut file = File::create(path)?;
-    file.write_u32::<LittleEndian>(u)?;
+// prints what would be sent as a json line
+fn print_payload(target: &Target, message: &Message) -> Result<()> {
+    let embeds = message.embed.split();
+    let attachments: Vec<&str> = message
+        .attachments
+        .iter()
+        .map(|attachment| attachment.filename)
+        .collect();
+    let payload = json!({
+        "destination": target.kind(),
+        "content": message.content,
+        "embeds": embeds.iter().map(|embed| &embed.embed).collect::<Vec<_>>(),
+        "attachments": attachments,
+        "buttons": message.buttons,
+        "silent": message.silent,
+    });
+    println!("{}", serde_json::to_string(&payload)?);
     Ok(())
 }
 
-fn get_last_page() -> Result<Option<u32>> {
-    let last_page_path = Path::new(APP_STATE_DIRECTORY).join("last_page");
-    try_read_u32(last_page_path)
+// the title, price and link of the message, used when discord rejects the embed
+fn plain_content(message: &Message) -> String {
+    let mut lines: Vec<Cow<str>> = message.content.map(Cow::Borrowed).into_iter().collect();
+    lines.push(Cow::Borrowed(message.title));
+    if let Some(data) = message.data {
+        if let Some(price) = data["price"].as_f64() {
+            lines.push(Cow::Owned(price::format_price(price)));
+        }
+        if let Some(url) = data["url"].as_str() {
+            lines.push(Cow::Borrowed(url));
+        }
+    }
+    truncate(&lines.join("\n"), 2000).to_owned()
 }
 
-fn set_last_page(page: u32) -> Result<()> {
-    let last_page_path = Path::new(APP_STATE_DIRECTORY).join("last_page");
-    write_u32(last_page_path, page)
+fn is_announcement_channel(ctx: &Context, bot: &Bot, channel_id: &str) -> Result<bool> {
+    let cached = ctx.announcement_channels.borrow().get(channel_id).copied();
+    if let Some(announcement) = cached {
+        return Ok(announcement);
+    }
+    let channel: Channel = bot.get_channel(channel_id)?.error_for_status()?.json()?;
+    let announcement = channel.is_announcement();
+    ctx.announcement_channels
+        .borrow_mut()
+        .insert(channel_id.to_owned(), announcement);
+    Ok(announcement)
 }
 
-fn get_last_sent_post() -> Result<Option<u32>> {
-    let last_post_path = Path::new(APP_STATE_DIRECTORY).join("last_post");
-    try_read_u32(last_post_path)
+// crossposts a sent message if it was sent to an announcement channel
+fn publish(ctx: &Context, bot: &Bot, channel_id: &str, message_id: &str) -> Result<()> {
+    if !is_announcement_channel(ctx, bot, channel_id)? {
+        return Ok(());
+    }
+    let response = bot.crosspost_message(channel_id, message_id)?;
+    if response.status() == StatusCode::FORBIDDEN {
+        // the bot needs the send messages permission in the channel, not tried again
+        warn!("Missing permission to publish messages in channel {channel_id}");
+        ctx.announcement_channels
+            .borrow_mut()
+            .insert(channel_id.to_owned(), false);
+        return Ok(());
+    }
+    response.error_for_status()?;
+    Ok(())
 }
 
-fn set_last_sent_post(post: u32) -> Result<()> {
-    let last_page_path = Path::new(APP_STATE_DIRECTORY).join("last_post");
-    write_u32(last_page_path, post)
+fn send_payload(ctx: &Context, target: &Target, payload: &Value) -> Result<()> {
+    let webhook = &ctx.webhook;
+    ctx.rate_limiter.acquire(target.key());
+    match target {
+        Target::Webhook { webhook_url, .. } => {
+            webhook
+                .execute_json(webhook_url, payload)?
+                .error_for_status()?;
+        }
+        Target::Bot {
+            bot_token,
+            channel_id,
+            ..
+        } => {
+            Bot::with_client(webhook.client(), bot_token)
+                .with_retry(webhook.retry())
+                .create_message_json(channel_id, payload)?
+                .error_for_status()?;
+        }
+        Target::Exec { command } => exec::run(command, payload)?,
+        // only the content of the discord payload can be sent elsewhere
+        _ => {
+            let content = payload["content"].as_str().unwrap_or_default();
+            notifier::for_target(target).notify_plain(ctx, content, None, DEFAULT_TITLE, false)?;
+        }
+    }
+    Ok(())
 }
 
-fn get_page_url(page: u32) -> String {
-    format!("https://bbs.io-tech.fi/threads/151/page-{}", page)
+// sends every json line read from stdin to all destinations
+fn send_stdin(ctx: &Context) -> Result<()> {
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let payload: Value = serde_json::from_str(&line)?;
+        for destination in &ctx.config.destinations {
+            send_payload(ctx, &destination.target, &payload)?;
+        }
+    }
+    Ok(())
 }
 
-fn get_post_id(post: ElementRef) -> Result<u32> {
-    post.value()
-        .attr("data-content")
-        .ok_or(Error::Scraping)?
-        .strip_prefix("post-")
-        .ok_or(Error::Scraping)?
-        .parse()
-        .or(Err(Error::Scraping))
+struct Context<'a> {
+    state_directory: PathBuf,
+    forum_client: &'a Client,
+    // of the forum session when logging in
+    cookies: Option<&'a Jar>,
+    webhook: Webhook<'a>,
+    config: &'a Config,
+    // replaced by the canary when the forum markup changes
+    selectors: &'a RefCell<Selectors>,
+    favicons: RefCell<FaviconCache>,
+    // the store icons are looked up from hosts only known once a deal links to them
+    resolver: &'a RefCell<Resolver>,
+    avatars: AvatarCache,
+    archive: Archive,
+    tracer: Tracer,
+    metrics: Metrics,
+    rate_limiter: &'a RateLimiter,
+    throttler: &'a Throttler,
+    deals: Option<&'a DealFeed>,
+    // whether messages sent to a bot channel get published
+    announcement_channels: RefCell<HashMap<String, bool>>,
+    // the guild of each bot channel, for linking to messages
+    guilds: RefCell<HashMap<String, Option<String>>>,
+    // messages are printed instead of sent and the state isn't updated
+    dry_run: bool,
 }
 
-fn get_title<'a>(content: &'a str, default_title: &'a str) -> &'a str {
-    let title = content
-        .strip_prefix("Tuote:")
-        .unwrap_or(default_title)
-        .split('\n')
-        .next()
-        .unwrap_or(default_title);
-    title
+// resolved the same way as the other hosts, which are known before any deal is seen
+fn store_client(ctx: &Context, domain: &str) -> Result<Client> {
+    if !http::pins_addresses(&ctx.config.http, &ctx.resolver.borrow()) {
+        return Ok(ctx.webhook.client().clone());
+    }
+    let www = format!("www.{domain}");
+    http::build_client(
+        &ctx.config.http,
+        ctx.config.http.notification_proxy.as_deref(),
+        &[domain, &www],
+        &mut ctx.resolver.borrow_mut(),
+    )
 }
 
-fn get_content(post: ElementRef, content_selector: &Selector) -> Result<String> {
-    let content: String = post
-        .select(&content_selector)
-        .next()
-        .ok_or(Error::Scraping)?
-        .children()
-        .map(|child| match child.value() {
-            scraper::Node::Text(text) => text,
-            scraper::Node::Element(element) => match element.name() {
-                "br" => "\n",
-                "a" => element.attr("href").unwrap_or(""),
-                _ => ElementRef::wrap(child).unwrap().text().next().unwrap_or(""),
-            },
-            _ => "",
-        })
-        .collect();
-    Ok(content)
+fn get_typical_price(ctx: &Context, title: &str) -> Option<f64> {
+    let price_comparison = ctx.config.price_comparison.as_ref()?;
+    match price_comparison::typical_price(ctx.webhook.client(), price_comparison, title) {
+        Ok(price) => price,
+        Err(err) => {
+            warn!("price comparison failed: {err}");
+            None
+        }
+    }
 }
 
-fn get_avatar_url(post: ElementRef, avatar_selector: &Selector) -> Result<Option<String>> {
-    let avatar_url = post
-        .select(&avatar_selector)
-        .next()
-        .map(|element| {
-            element
-                .value()
-                .attr("src")
-                .ok_or(Error::Scraping)
-                .map(|s| format!("https://bbs.io-tech.fi{}", s))
-        })
-        .transpose()?;
-    Ok(avatar_url)
+fn get_canonical_name(ctx: &Context, ean: &str) -> Option<String> {
+    let product_database = ctx.config.product_database.as_ref()?;
+    match product::canonical_name(ctx.webhook.client(), product_database, ean) {
+        Ok(name) => name,
+        Err(err) => {
+            warn!("product lookup failed: {err}");
+            None
+        }
+    }
+}
+
+fn get_rehosted_avatar(ctx: &Context, avatar_url: &str) -> Option<(String, Vec<u8>)> {
+    match ctx.avatars.get(ctx.forum_client, avatar_url) {
+        Ok(Some(data)) => Some((avatar::attachment_filename("avatar", avatar_url), data)),
+        Ok(None) => {
+            warn!("avatar too large to rehost: {avatar_url}");
+            None
+        }
+        Err(err) => {
+            warn!("rehosting avatar failed: {err}");
+            None
+        }
+    }
+}
+
+// the size is checked before downloading, and the download stops once it's over the limit
+fn read_image(response: Response) -> Result<Option<Vec<u8>>> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_IMAGE_SIZE)
+    {
+        return Ok(None);
+    }
+    let mut data = Vec::new();
+    response.take(MAX_IMAGE_SIZE + 1).read_to_end(&mut data)?;
+    if u64::try_from(data.len()).unwrap_or(u64::MAX) > MAX_IMAGE_SIZE {
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+fn download_image(ctx: &Context, image_url: &str) -> Result<Option<Vec<u8>>> {
+    read_image(ctx.forum_client.get(image_url).send()?.error_for_status()?)
+}
+
+fn get_rehosted_image(ctx: &Context, image_url: &str) -> Option<(String, Vec<u8>)> {
+    match download_image(ctx, image_url) {
+        Ok(Some(data)) => Some((avatar::attachment_filename("image", image_url), data)),
+        Ok(None) => {
+            warn!("image too large to rehost: {image_url}");
+            None
+        }
+        Err(err) => {
+            warn!("rehosting image failed: {err}");
+            None
+        }
+    }
+}
+
+// as many as fit in an embed field, numbered by their position in the post
+fn image_links(config: &Config, images: &[String], skip: usize) -> Option<String> {
+    if config.post_images == PostImages::Hide {
+        return None;
+    }
+    let mut links = String::new();
+    for (index, url) in images.iter().enumerate().skip(skip) {
+        let link = format!("[Kuva {}]({url})\n", index + 1);
+        if links.len() + link.len() > 1024 {
+            break;
+        }
+        links.push_str(&link);
+    }
+    Some(links.trim_end().to_owned()).filter(|links| !links.is_empty())
 }
 
-fn get_user_url(username_element: ElementRef) -> Result<String> {
-    let user_url = format!(
-        "https://bbs.io-tech.fi{}",
-        username_element
-            .value()
-            .attr("href")
-            .ok_or(Error::Scraping)?
+fn get_guild_id(ctx: &Context, bot: &Bot, channel_id: &str) -> Result<Option<String>> {
+    if let Some(guild_id) = ctx.guilds.borrow().get(channel_id) {
+        return Ok(guild_id.clone());
+    }
+    let channel: Channel = bot.get_channel(channel_id)?.error_for_status()?.json()?;
+    ctx.guilds
+        .borrow_mut()
+        .insert(channel_id.to_owned(), channel.guild_id.clone());
+    Ok(channel.guild_id)
+}
+
+fn find_reply_link(ctx: &Context, quoted: &[u32]) -> Result<Option<String>> {
+    for &post_id in quoted {
+        let Some(message) = ctx.archive.forwarded_message(post_id)? else {
+            continue;
+        };
+        let Some(bot) = pin::channel_bot(ctx, &message.channel_id) else {
+            continue;
+        };
+        if let Some(guild_id) = get_guild_id(ctx, &bot, &message.channel_id)? {
+            return Ok(Some(format!(
+                "[{}](https://discord.com/channels/{guild_id}/{}/{})",
+                truncate(&message.title, 200),
+                message.channel_id,
+                message.message_id
+            )));
+        }
+    }
+    Ok(None)
+}
+
+// only deals sent by a bot can be linked to
+fn get_reply_link(ctx: &Context, quoted: &[u32]) -> Option<String> {
+    match find_reply_link(ctx, quoted) {
+        Ok(link) => link,
+        Err(err) => {
+            warn!("finding the quoted deal failed: {err}");
+            None
+        }
+    }
+}
+
+fn get_screenshot(ctx: &Context, post: &ScrapedPost) -> Option<Vec<u8>> {
+    let screenshot_config = ctx.config.screenshot.as_ref().filter(|_| !ctx.dry_run)?;
+    let url = get_store_url(&post.links)?;
+    match screenshot::capture(ctx.webhook.client(), screenshot_config, url) {
+        Ok(Some(data)) => Some(data),
+        Ok(None) => {
+            warn!("screenshot too large to attach: {url}");
+            None
+        }
+        Err(err) => {
+            warn!("capturing screenshot failed: {err}");
+            None
+        }
+    }
+}
+
+fn get_duplicate_of(ctx: &Context, post: &ScrapedPost) -> Result<Option<String>> {
+    match ctx.config.dedup.as_ref().filter(|dedup| dedup.collapse) {
+        Some(dedup) => dedup::earlier_post(ctx, dedup, post),
+        None => Ok(None),
+    }
+}
+
+// matched by the product or the store link, only needed for posts with a price
+fn get_price_history(
+    ctx: &Context,
+    post: &ScrapedPost,
+    product_key: Option<&str>,
+    price: Option<f64>,
+) -> Result<Option<PriceHistory>> {
+    let store_url = get_store_url(&post.links);
+    if price.is_none() || (product_key.is_none() && store_url.is_none()) {
+        return Ok(None);
+    }
+    ctx.archive.price_history(post.id, product_key, store_url)
+}
+
+// announces the drop and mentions subscribers if the product was archived at a higher price
+fn get_price_drop_notice(
+    ctx: &Context,
+    title: &str,
+    price: f64,
+    price_history: Option<&PriceHistory>,
+) -> Option<String> {
+    let previous_price = match price_history {
+        Some(history) if history.previous > price => history.previous,
+        _ => return None,
+    };
+    let lowercase_title = title.to_lowercase();
+    let mut notice: Vec<&str> = ctx
+        .config
+        .price_drop_subscriptions
+        .iter()
+        .filter(|subscription| lowercase_title.contains(&subscription.keyword.to_lowercase()))
+        .map(|subscription| subscription.mention.as_str())
+        .collect();
+    let price_drop = format!(
+        "Price drop: {} → {}",
+        price::format_price(previous_price),
+        price::format_price(price)
     );
-    Ok(user_url)
+    notice.push(&price_drop);
+    Some(notice.join(" "))
 }
 
-fn get_username_str(username_element: ElementRef) -> Result<&str> {
-    let username = username_element.text().next().ok_or(Error::Scraping)?;
-    Ok(username)
+#[derive(Serialize, Deserialize, Clone)]
+struct ScrapedPost {
+    id: u32,
+    timestamp: String,
+    username: String,
+    user_url: String,
+    avatar_url: Option<String>,
+    content: String,
+    title: String,
+    url: String,
+    links: Vec<String>,
+    images: Vec<String>,
+    quoted: Vec<u32>,
 }
 
-fn get_username_element<'a>(
-    post: ElementRef<'a>,
-    username_selector: &Selector,
-) -> Result<ElementRef<'a>> {
-    let username_element = post
-        .select(&username_selector)
-        .next()
-        .ok_or(Error::Scraping)?;
-    Ok(username_element)
+impl ScrapedPost {
+    fn has_title(&self) -> bool {
+        self.title != DEFAULT_TITLE
+    }
 }
 
-fn get_timestamp<'a>(post: ElementRef<'a>, time_selector: &Selector) -> Result<&'a str> {
-    let timestamp = post
-        .select(&time_selector)
-        .next()
-        .ok_or(Error::Scraping)?
-        .value()
-        .attr("datetime")
-        .ok_or(Error::Scraping)?;
-    Ok(timestamp)
+impl From<Post> for ScrapedPost {
+    fn from(post: Post) -> Self {
+        let title = get_title(&post.markdown, DEFAULT_TITLE);
+        Self {
+            id: post.id,
+            timestamp: post.timestamp,
+            username: post.author,
+            user_url: post.author_url,
+            avatar_url: post.avatar_url,
+            content: post.markdown,
+            title,
+            url: post.url,
+            links: post.urls,
+            images: post.images,
+            quoted: post.quoted,
+        }
+    }
 }
 
-fn truncate(s: &str, max_chars: usize) -> &str {
-    match s.char_indices().nth(max_chars) {
-        None => s,
-        Some((idx, _)) => &s[..idx],
+// everything derived from the scraped post that doesn't depend on the destination
+struct PostDetails<'a> {
+    campaign: Option<&'a str>,
+    category: Option<&'a Category>,
+    color: Option<i32>,
+    // posted by a highlighted poster
+    highlighted: bool,
+    display_title: Cow<'a, str>,
+    store_icon_url: Option<String>,
+    store: Option<String>,
+    typical_price: Option<f64>,
+    typical_price_text: Option<String>,
+    price: Option<f64>,
+    product_key: Option<String>,
+    // of the same product in earlier posts
+    price_history: Option<PriceHistory>,
+    price_drop_notice: Option<String>,
+    avatar: Option<(String, Vec<u8>)>,
+    snapshot_url: Option<String>,
+    screenshot: Option<Vec<u8>>,
+    // the first image of the post, uploaded as an attachment
+    image: Option<(String, Vec<u8>)>,
+    canonical_name: Option<String>,
+    // a link to the message of the quoted deal
+    reply_link: Option<String>,
+    // the earlier post with the same store link, when duplicates are collapsed
+    duplicate_of: Option<String>,
+    // links to the images not shown in the embed
+    other_images: Option<String>,
+    // shown as embed fields instead of in the description
+    fields: Option<DealFields<'a>>,
+}
+
+fn get_post_details<'a>(post: &'a ScrapedPost, ctx: &'a Context) -> Result<PostDetails<'a>> {
+    let config = ctx.config;
+    let campaign = campaign::detect(&config.campaigns, &post.title, &post.content);
+    let campaign_category = config.campaigns.category.as_ref().and_then(|name| {
+        config
+            .categories
+            .iter()
+            .find(|category| &category.name == name)
+    });
+    let category = match campaign {
+        Some(_) if campaign_category.is_some() => campaign_category,
+        _ => category::classify(&config.categories, &post.title, &post.content),
+    };
+    if let Some(category) = category {
+        debug!("Category: {}", category.name);
     }
+    // highlighted posters stand out over campaigns and categories
+    let highlight = config.posters.highlight(&post.username);
+    let color = highlight
+        .or_else(|| campaign.and(config.campaigns.color))
+        .or_else(|| category.and_then(|category| category.color));
+    let display_title = category.map_or(Cow::Borrowed(post.title.as_str()), |category| {
+        Cow::Owned(category.decorate_title(&post.title))
+    });
+    let store = get_store_url(&post.links).and_then(favicon::store_domain);
+    let store_icon_url = store.as_deref().and_then(|domain| {
+        ctx.favicons.borrow_mut().icon_url(
+            || store_client(ctx, domain),
+            &config.store_icons,
+            config.fetch_store_icons,
+            domain,
+        )
+    });
+    let typical_price = if post.has_title() {
+        get_typical_price(ctx, &post.title)
+    } else {
+        None
+    };
+    let avatar = post
+        .avatar_url
+        .as_deref()
+        .filter(|_| config.rehost_avatars)
+        .and_then(|url| get_rehosted_avatar(ctx, url));
+    let image = post
+        .images
+        .first()
+        .filter(|_| config.post_images == PostImages::Rehost)
+        .and_then(|url| get_rehosted_image(ctx, url));
+    let price = price::parse_price(&post.content);
+    let product_key = Some(post.title.as_str())
+        .filter(|_| post.has_title())
+        .map(|title| product::identity_key(title, &post.links));
+    let canonical_name = product_key
+        .as_deref()
+        .and_then(|key| key.strip_prefix("ean:"))
+        .and_then(|ean| get_canonical_name(ctx, ean));
+    let price_history = get_price_history(ctx, post, product_key.as_deref(), price)?;
+    let price_drop_notice = price
+        .and_then(|price| get_price_drop_notice(ctx, &post.title, price, price_history.as_ref()));
+    let snapshot_url = get_store_url(&post.links)
+        .filter(|_| config.wayback_snapshots && !ctx.dry_run)
+        .and_then(|url| match wayback::save(ctx.webhook.client(), url) {
+            Ok(snapshot_url) => Some(snapshot_url),
+            Err(err) => {
+                warn!("saving wayback snapshot failed: {err}");
+                None
+            }
+        });
+    let screenshot = get_screenshot(ctx, post);
+
+    // the screenshot takes the place of the first image
+    let other_images = image_links(config, &post.images, usize::from(screenshot.is_none()));
+    Ok(PostDetails {
+        campaign,
+        category,
+        color,
+        highlighted: highlight.is_some(),
+        display_title,
+        store_icon_url,
+        store,
+        typical_price,
+        typical_price_text: typical_price.map(price_comparison::format_typical_price),
+        price,
+        product_key,
+        price_history,
+        price_drop_notice,
+        avatar,
+        snapshot_url,
+        screenshot,
+        image,
+        canonical_name,
+        reply_link: get_reply_link(ctx, &post.quoted),
+        duplicate_of: get_duplicate_of(ctx, post)?,
+        other_images,
+        fields: post::parse(&post.content),
+    })
 }
 
-fn send_message(webhook: &Webhook, webhook_url: &str, embed: &EmbedBuilder) -> reqwest::Result<()> {
-    webhook
-        .execute(&webhook_url)
-        .embed(embed)
-        .send()?
-        .error_for_status()?;
-    Ok(())
+fn build_embed<'a>(
+    post: &'a ScrapedPost,
+    details: &'a PostDetails,
+    description: Cow<'a, str>,
+    avatar_icon_url: Option<&'a str>,
+    image_url: Option<&'a str>,
+) -> EmbedBuilder<'a> {
+    let mut embed = EmbedBuilder::new();
+    embed
+        .timestamp(&post.timestamp)
+        .author(
+            Some(truncate(&post.username, 256)),
+            Some(&post.user_url),
+            avatar_icon_url,
+        )
+        .description(description)
+        .title(truncate(&details.display_title, 256))
+        // the title links to the post itself
+        .url(&post.url);
+    if let Some(color) = details.color {
+        embed.color(color);
+    }
+    if let Some(campaign) = details.campaign {
+        embed.field("Kampanja", truncate(campaign, 1024), Some(false));
+    }
+    if let Some(icon_url) = &details.store_icon_url {
+        embed.thumbnail(icon_url);
+    }
+    if let Some(canonical_name) = &details.canonical_name {
+        embed.field("Tuote", truncate(canonical_name, 1024), Some(false));
+    }
+    if let Some(price) = details.fields.as_ref().and_then(|fields| fields.price) {
+        embed.field("Hinta", truncate(price, 1024), Some(true));
+    }
+    if let Some(typical_price) = &details.typical_price_text {
+        embed.field("Normaalihinta", typical_price, Some(true));
+    }
+    if let (Some(price), Some(history)) = (details.price, &details.price_history) {
+        if (history.previous - price).abs() >= 0.01 {
+            embed.field(
+                "Aiempi hinta",
+                price::format_price(history.previous),
+                Some(true),
+            );
+        }
+        if price < history.lowest {
+            embed.field(
+                "Halvin tähän mennessä",
+                format!("Aiempi alin {}", price::format_price(history.lowest)),
+                Some(true),
+            );
+        }
+    }
+    if let Some(category) = details.category {
+        embed.field("Kategoria", truncate(&category.name, 1024), Some(true));
+    }
+    if let Some(link) = details.fields.as_ref().and_then(|fields| fields.link) {
+        embed.field("Linkki", truncate(link, 1024), Some(false));
+    }
+    if let Some(reply_link) = &details.reply_link {
+        embed.field("Vastaus tarjoukseen", reply_link, Some(false));
+    }
+    if let Some(duplicate_of) = &details.duplicate_of {
+        embed.field("Jaettu aiemmin", duplicate_of, Some(false));
+    }
+    if let Some(other_images) = &details.other_images {
+        embed.field("Muut kuvat", other_images, Some(false));
+    }
+    let footer: Vec<&str> = details
+        .highlighted
+        .then_some("Luotettu jakaja")
+        .into_iter()
+        .chain(details.snapshot_url.as_deref())
+        .collect();
+    if !footer.is_empty() {
+        embed.footer(footer.join(" · "), None::<&str>);
+    }
+    if details.screenshot.is_some() {
+        embed.image(SCREENSHOT_ATTACHMENT_URL);
+    } else if let Some(image_url) = image_url {
+        embed.image(image_url);
+    }
+    embed
 }
 
-fn run() -> Result<()> {
-    let mut page_number = get_last_page()?.unwrap_or(u32::MAX);
-    let last_sent_post = get_last_sent_post()?;
+// a post ready to be sent, shared by all sinks
+struct Outgoing<'a> {
+    post: &'a ScrapedPost,
+    details: &'a PostDetails<'a>,
+    attachments: &'a [Attachment<'a>],
+    avatar_icon_url: Option<&'a str>,
+    image_url: Option<&'a str>,
+    data: &'a Value,
+}
 
-    let client = reqwest::blocking::Client::builder()
-        .user_agent(APP_USER_AGENT)
-        .build()?;
-    let webhook = Webhook::with_client(&client);
-    let webhook_url = get_webhook_url()?;
+// the structured form of the post for exec destinations
+fn post_data(post: &ScrapedPost, details: &PostDetails) -> Value {
+    json!({
+        "id": post.id,
+        "title": post.title,
+        "content": post.content,
+        "url": post.url,
+        "author": post.username,
+        "author_url": post.user_url,
+        "timestamp": post.timestamp,
+        "links": post.links,
+        "price": details.price,
+        "typical_price": details.typical_price,
+        "store": details.store,
+        "category": details.category.map(|category| &category.name),
+        "campaign": details.campaign,
+    })
+}
 
-    let post_selector = Selector::parse(".message").unwrap();
-    let next_page_selector = Selector::parse(".pageNav-page--current+ .pageNav-page").unwrap();
+// returns the indices of the sinks sending the post to failed
+// fetches the details of the post once and builds what the sinks need from them
+fn with_outgoing<T>(
+    post: &ScrapedPost,
+    ctx: &Context,
+    f: impl FnOnce(&Outgoing) -> Result<T>,
+) -> Result<T> {
+    let details = {
+        let _span = ctx.tracer.span("post details");
+        get_post_details(post, ctx)?
+    };
 
-    let time_selector = Selector::parse(".u-dt").unwrap();
-    let username_selector = Selector::parse(".username").unwrap();
-    let avatar_selector = Selector::parse(".avatar img").unwrap();
-    let content_selector = Selector::parse(".bbWrapper").unwrap();
+    let mut attachments = Vec::new();
+    let mut avatar_icon_url = post.avatar_url.clone();
+    let avatar_description = format!("Profiilikuva: {}", post.username);
+    if let Some((filename, data)) = &details.avatar {
+        attachments.push(Attachment {
+            filename,
+            data,
+            description: Some(&avatar_description),
+        });
+        avatar_icon_url = Some(format!("attachment://{filename}"));
+    }
+    if let Some(data) = &details.screenshot {
+        attachments.push(Attachment {
+            filename: screenshot::SCREENSHOT_FILENAME,
+            data,
+            description: Some(screenshot::SCREENSHOT_DESCRIPTION),
+        });
+    }
+    let mut image_url = post
+        .images
+        .first()
+        .filter(|_| ctx.config.post_images == PostImages::Link)
+        .cloned();
+    if let Some((filename, data)) = &details.image {
+        attachments.push(Attachment {
+            filename,
+            data,
+            description: None,
+        });
+        image_url = Some(format!("attachment://{filename}"));
+    }
 
-    let mut last_id;
-    let mut failed = false;
+    let data = post_data(post, &details);
 
-    loop {
-        eprintln!("Get page {}", page_number);
-        let response = client
-            .get(&get_page_url(page_number))
-            .send()?
-            .error_for_status()?;
-        if page_number == u32::MAX {
-            // figure out the actual page from the url
-            page_number = response
-                .url()
-                .path_segments()
-                .ok_or(Error::Scraping)?
-                .last()
-                .ok_or(Error::Scraping)?
-                .strip_prefix("page-")
-                .ok_or(Error::Scraping)?
-                .parse()
-                .or(Err(Error::Scraping))?;
-        }
-
-        let body = response.text()?;
-        let fragment = Html::parse_document(&body);
-
-        let posts = fragment.select(&post_selector);
-
-        if let Some(last_sent_id) = last_sent_post {
-            let mut last_id_temp = last_sent_id;
-
-            for post in posts {
-                let post_id = get_post_id(post)?;
-                if post_id > last_sent_id {
-                    eprintln!("New message: id {}", post_id);
-
-                    let timestamp = get_timestamp(post, &time_selector)?;
-
-                    let username_element = get_username_element(post, &username_selector)?;
-                    let username = get_username_str(username_element)?;
-                    let user_url = get_user_url(username_element)?;
-                    let avatar_url = get_avatar_url(post, &avatar_selector)?;
-                    let content = get_content(post, &content_selector)?;
-                    let default_title = "Uusi tarjous";
-                    let title = get_title(&content, default_title);
-
-                    eprintln!(
-                        "Username: {}, Title: {}, Content: {}",
-                        username, title, content
-                    );
-                    let mut embed = EmbedBuilder::new();
-                    embed
-                        .timestamp(timestamp)
-                        .author(
-                            Some(truncate(username, 256)),
-                            Some(&user_url),
-                            avatar_url.as_deref(),
-                        )
-                        .description(truncate(&content, 2048))
-                        .title(truncate(title, 256));
-                    let result = send_message(&webhook, &webhook_url, &embed);
-
-                    if let Err(err) = result {
-                        eprintln!("sending message failed: {}", err);
-                        failed = true;
-                        break;
-                    }
-
-                    last_id_temp = post_id;
-                }
+    f(&Outgoing {
+        post,
+        details: &details,
+        attachments: &attachments,
+        avatar_icon_url: avatar_icon_url.as_deref(),
+        image_url: image_url.as_deref(),
+        data: &data,
+    })
+}
+
+fn forward_post(
+    post: &ScrapedPost,
+    sinks: &[&dyn Sink],
+    ctx: &Context,
+) -> Result<Vec<(usize, Error)>> {
+    let _post = info_span!("post", id = post.id).entered();
+    debug!(username = %post.username, title = %post.title, content = %post.content, "Forward");
+    with_outgoing(post, ctx, |outgoing| {
+        let failed = send_outgoing(outgoing, sinks, ctx);
+        if !sinks.is_empty() && failed.len() == sinks.len() {
+            return Ok(failed);
+        }
+        record_forwarded(outgoing, ctx)?;
+        Ok(failed)
+    })
+}
+
+// returns the indices of the sinks that failed with the errors
+fn send_outgoing(outgoing: &Outgoing, sinks: &[&dyn Sink], ctx: &Context) -> Vec<(usize, Error)> {
+    let mut failed = Vec::new();
+    for (index, sink) in sinks.iter().enumerate() {
+        let mut span = ctx.tracer.span("webhook send");
+        span.attribute("destination", &sink.kind());
+        let _send = info_span!("send", destination = sink.kind()).entered();
+        let started = Instant::now();
+        let result = sink.send(ctx, outgoing);
+        ctx.metrics.timing("send.latency", started.elapsed());
+
+        if let Err(err) = result {
+            warn!("sending message failed: {err}");
+            span.error(&err);
+            ctx.metrics.increment("errors");
+            failed.push((index, err));
+        }
+    }
+    failed
+}
+
+fn record_forwarded(outgoing: &Outgoing, ctx: &Context) -> Result<()> {
+    if ctx.dry_run {
+        return Ok(());
+    }
+    let post = outgoing.post;
+    let details = outgoing.details;
+    ctx.metrics.increment("posts.sent");
+
+    if let Some(deals) = ctx.deals {
+        deals.publish(Deal {
+            id: post.id,
+            title: post.title.clone(),
+            content: post.content.clone(),
+            url: post.url.clone(),
+            author: post.username.clone(),
+            timestamp: post.timestamp.clone(),
+            price: details.price,
+            typical_price: details.typical_price,
+            store: details.store.clone(),
+            category: details.category.map(|category| category.name.clone()),
+            links: post.links.clone(),
+        });
+    }
+    ctx.archive.insert_post(&ArchivedPost {
+        id: post.id,
+        author: &post.username,
+        title: &post.title,
+        product_key: details.product_key.as_deref(),
+        price: details.price,
+        url: &post.url,
+        timestamp: &post.timestamp,
+        content: &post.content,
+        category: details.category.map(|category| category.name.as_str()),
+        store: details.store.as_deref(),
+        store_url: get_store_url(&post.links),
+        typical_price: details.typical_price,
+    })
+}
+
+// updates the messages the edited post was sent as earlier
+fn update_post(post: &ScrapedPost, sinks: &[&dyn Sink], ctx: &Context) -> Result<()> {
+    let _post = info_span!("post", id = post.id).entered();
+    debug!(title = %post.title, content = %post.content, "Update");
+    with_outgoing(post, ctx, |outgoing| {
+        for sink in sinks {
+            let _send = info_span!("send", destination = sink.kind()).entered();
+            if let Err(err) = sink.update(ctx, outgoing) {
+                warn!("updating message failed: {err}");
+                ctx.metrics.increment("errors");
             }
-            last_id = last_id_temp;
-        } else {
-            last_id = get_post_id(posts.last().ok_or(Error::Scraping)?)?;
-        }
-
-        if !failed {
-            if let Some(next_page) = fragment.select(&next_page_selector).next() {
-                page_number = next_page
-                    .text()
-                    .next()
-                    .ok_or(Error::Scraping)?
-                    .parse()
-                    .or(Err(Error::Scraping))?;
-                continue;
+        }
+        Ok(())
+    })?;
+    if ctx.dry_run {
+        return Ok(());
+    }
+    ctx.archive
+        .update_post_content(post.id, &post.title, &post.content)
+}
+
+// forwards new posts of every pipeline, at most max_posts of them each if given
+fn poll(ctx: &Context, pipelines: &mut [Pipeline], max_posts: Option<usize>) -> Result<()> {
+    ctx.tracer.start_trace();
+    let started = Instant::now();
+    // public threads can still be read without the session
+    let mut result = session::ensure_logged_in(ctx);
+    if let Err(err) = &result {
+        warn!("{err}");
+        ctx.metrics.increment("errors");
+    }
+    for pipeline in pipelines {
+        let mut span = ctx.tracer.span("poll");
+        span.attribute("pipeline", &pipeline.name);
+        let _poll = info_span!("poll", pipeline = %pipeline.name).entered();
+        if let Err(err) = pipeline.run(ctx, max_posts) {
+            span.error(&err);
+            ctx.metrics.increment("errors");
+            // the first error is returned, the rest only logged
+            if result.is_ok() {
+                result = Err(err);
+            } else {
+                error!("pipeline failed: {err}");
             }
         }
+    }
+    ctx.metrics.timing("poll.latency", started.elapsed());
+    if let Err(err) = ctx.tracer.flush(ctx.webhook.client()) {
+        warn!("exporting traces failed: {err}");
+    }
+    result
+}
+
+fn is_burst_active(ctx: &Context) -> bool {
+    burst::is_active(
+        &ctx.config.burst,
+        &ctx.state_directory,
+        Local::now().date_naive(),
+    )
+}
+
+// the posts held back during ended windows, kept for the next try if sending fails
+fn send_digests(ctx: &Context) {
+    for destination in ctx.config.all_destinations() {
+        if let Err(err) = throttle::send_digest(ctx, destination) {
+            warn!("sending digest failed: {err}");
+        }
+    }
+}
+
+// the poll interval and catch-up cap currently in effect
+fn poll_settings(ctx: &Context) -> (u64, Option<usize>) {
+    if is_burst_active(ctx) {
+        (
+            ctx.config.burst.poll_interval,
+            ctx.config.burst.max_posts_per_run,
+        )
+    } else {
+        (ctx.config.poll_interval, ctx.config.max_posts_per_run)
+    }
+}
+
+fn send_weekly_stats(ctx: &Context) -> Result<()> {
+    let Some(weekly_stats) = &ctx.config.weekly_stats else {
+        return Ok(());
+    };
+    let now = Local::now();
+    if !stats::is_weekly_stats_due(weekly_stats, &ctx.state_directory, now)? {
+        return Ok(());
+    }
+
+    info!("Send weekly stats");
+    let stats = stats::collect(&ctx.archive, now)?;
+    let mut embed = EmbedBuilder::new();
+    embed.title("Viikon tarjoukset");
+    for (name, value) in &[
+        ("Kategoriat", &stats.categories),
+        ("Kaupat", &stats.stores),
+        ("Suurimmat alennukset", &stats.discounts),
+        ("Eniten reaktioita", &stats.most_reacted),
+    ] {
+        if !value.is_empty() {
+            embed.field(*name, truncate(value, 1024), Some(false));
+        }
+    }
+    let message = Message {
+        content: None,
+        embed: &embed,
+        attachments: &[],
+        buttons: &[],
+        title: "Viikon tarjoukset",
+        category: None,
+        data: None,
+        silent: false,
+    };
+    send_message(ctx, &weekly_stats.target, &message)?;
+    stats::mark_sent(&ctx.state_directory, "weekly_stats", now)
+}
+
+fn send_leaderboard(ctx: &Context) -> Result<()> {
+    let Some(leaderboard_config) = &ctx.config.leaderboard else {
+        return Ok(());
+    };
+    let now = Local::now();
+    if !stats::is_leaderboard_due(leaderboard_config, &ctx.state_directory, now)? {
+        return Ok(());
+    }
+
+    info!("Send leaderboard");
+    let leaderboard = stats::collect_leaderboard(&ctx.archive, now)?;
+    let title = format!("Kuukauden tarjoajat {}", leaderboard.month);
+    let mut embed = EmbedBuilder::new();
+    embed.title(&title);
+    for (name, value) in &[
+        ("Aktiivisimmat", &leaderboard.most_active),
+        ("Tykätyimmät", &leaderboard.most_liked),
+    ] {
+        if !value.is_empty() {
+            embed.field(*name, truncate(value, 1024), Some(true));
+        }
+    }
+    let message = Message {
+        content: None,
+        embed: &embed,
+        attachments: &[],
+        buttons: &[],
+        title: &title,
+        category: None,
+        data: None,
+        silent: false,
+    };
+    send_message(ctx, &leaderboard_config.target, &message)?;
+    stats::mark_sent(&ctx.state_directory, "leaderboard", now)
+}
+
+// registers the slash command and answers it from a separate thread
+fn start_interactions(ctx: &Context, interactions_config: &InteractionsConfig) {
+    let bot = Bot::with_client(ctx.webhook.client(), &interactions_config.bot_token);
+    if let Err(err) = bot
+        .create_global_command(
+            &interactions_config.application_id,
+            &interactions::command_definition(),
+        )
+        .and_then(reqwest::blocking::Response::error_for_status)
+    {
+        warn!("registering slash command failed: {err}");
+    }
+
+    let interactions_config = interactions_config.clone();
+    let state_directory = ctx.state_directory.clone();
+    thread::spawn(move || {
+        if let Err(err) = interactions::serve(&interactions_config, &state_directory) {
+            warn!("interactions endpoint failed: {err}");
+        }
+    });
+}
+
+// set on SIGTERM or SIGINT, the daemons stop before their next poll
+static SHUTDOWN: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+fn handle_shutdown_signals() -> Result<()> {
+    let flag = SHUTDOWN.get_or_init(|| Arc::new(AtomicBool::new(false)));
+    for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(flag))?;
+    }
+    Ok(())
+}
+
+fn is_shutting_down() -> bool {
+    SHUTDOWN
+        .get()
+        .is_some_and(|flag| flag.load(Ordering::Relaxed))
+}
+
+// sleeps in short steps to notice a shutdown soon
+fn sleep_until_shutdown(duration: Duration) {
+    let until = Instant::now() + duration;
+    while !is_shutting_down() {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(Duration::from_secs(1)));
+    }
+}
+
+// runs until the deadline when the clients need to be rebuilt, or a shutdown
+fn run_daemon(
+    ctx: &Context,
+    pipelines: &mut [Pipeline],
+    canary: &mut Canary,
+    until: Option<Instant>,
+) {
+    let mut pins_checked = None;
+    let mut reactions_recorded = None;
+    let mut threads_discovered = None;
+    while !is_shutting_down() && until.is_none_or(|until| Instant::now() < until) {
+        let (poll_interval, max_posts) = poll_settings(ctx);
+        if let Err(err) = canary.run(ctx) {
+            warn!("checking selectors failed: {err}");
+        }
+        if let Err(err) = poll(ctx, pipelines, max_posts) {
+            error!("{err}");
+        }
+        if let Err(err) = send_weekly_stats(ctx) {
+            warn!("sending weekly stats failed: {err}");
+        }
+        if let Err(err) = send_leaderboard(ctx) {
+            warn!("sending leaderboard failed: {err}");
+        }
+        send_digests(ctx);
+        if let Err(err) = pin::update(ctx, &mut pins_checked) {
+            warn!("updating pins failed: {err}");
+        }
+        if let Err(err) = reactions::record(ctx, &mut reactions_recorded) {
+            warn!("recording reactions failed: {err}");
+        }
+        if let Err(err) = discovery::run(ctx, &mut threads_discovered) {
+            warn!("discovering threads failed: {err}");
+        }
+        sleep_until_shutdown(Duration::from_secs(poll_interval));
+    }
+}
 
-        break;
+// the legacy location while it exists, otherwise the data directory of the platform
+fn default_state_directory() -> PathBuf {
+    let legacy = Path::new(APP_STATE_DIRECTORY);
+    if legacy.is_dir() {
+        return legacy.to_owned();
     }
+    dirs::data_dir().map_or_else(|| legacy.to_owned(), |data| data.join("tarjousbot"))
+}
 
-    set_last_page(page_number)?;
-    set_last_sent_post(last_id)?;
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    logging::init(cli.verbosity(), cli.log_format);
+    let command = match cli.command {
+        Some(command) => command,
+        None => Command::Run {
+            daemon: cli.daemon,
+            dry_run: false,
+        },
+    };
+    let state_dir = cli.state_dir;
+    let config_path = cli.config.unwrap_or_else(|| {
+        state_dir
+            .clone()
+            .unwrap_or_else(default_state_directory)
+            .join("config.toml")
+    });
+    // given explicitly it takes precedence over the one in the config
+    let state_directory = &match state_dir {
+        Some(state_directory) => state_directory,
+        None => Config::state_directory(&config_path)?,
+    };
 
+    match &command {
+        Command::Burst { mode } => {
+            return burst::set_manual(state_directory, matches!(mode, BurstMode::On))
+        }
+        Command::Purge { author, before } => {
+            return purge::run(
+                &config_path,
+                state_directory,
+                author.as_deref(),
+                before.as_deref(),
+            )
+        }
+        Command::Run { daemon: true, .. } => {
+            handle_shutdown_signals()?;
+            let tenants = tenant_directories(state_directory)?;
+            if !tenants.is_empty() {
+                return run_tenants(&command, &config_path, state_directory, tenants);
+            }
+        }
+        _ => {}
+    }
+    run_instance(&command, &config_path, state_directory)
+}
+
+// every directory under tenants is a state directory of its own, with a config.toml
+fn tenant_directories(state_directory: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(state_directory.join("tenants")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut tenants = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.join("config.toml").is_file() {
+            tenants.push(path);
+        }
+    }
+    tenants.sort();
+    Ok(tenants)
+}
+
+// runs a daemon per tenant in its own thread, next to the top level one if configured
+fn run_tenants(
+    command: &Command,
+    config_path: &Path,
+    state_directory: &Path,
+    tenants: Vec<PathBuf>,
+) -> Result<()> {
+    let handles: Vec<_> = tenants
+        .into_iter()
+        .map(|tenant| {
+            let command = command.clone();
+            thread::spawn(move || {
+                info!("Start tenant {}", tenant.display());
+                let config_path = tenant.join("config.toml");
+                let result = Config::state_directory(&config_path).and_then(|state_directory| {
+                    run_instance(&command, &config_path, &state_directory)
+                });
+                if let Err(err) = result {
+                    error!("tenant {} failed: {err}", tenant.display());
+                }
+            })
+        })
+        .collect();
+    if config_path.is_file() {
+        run_instance(command, config_path, state_directory)?;
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
     Ok(())
 }
 
+// the session cookies are sent with every forum request when logging in
+fn build_forum_client(
+    config: &Config,
+    resolver: &mut Resolver,
+    cookies: Option<&Arc<Jar>>,
+) -> Result<Client> {
+    let mut builder = http::client_builder(
+        &config.http,
+        config.http.forum_proxy.as_deref(),
+        &[FORUM_HOST],
+        resolver,
+    )?;
+    if let Some(cookies) = cookies {
+        builder = builder.cookie_provider(Arc::clone(cookies));
+    }
+    Ok(builder.build()?)
+}
+
+fn self_update(config: &Config, resolver: &mut Resolver, insecure: bool) -> Result<()> {
+    let client = http::build_client(
+        &config.http,
+        config.http.notification_proxy.as_deref(),
+        update::UPDATE_HOSTS,
+        resolver,
+    )?;
+    update::self_update(&client, config.update_public_key.as_deref(), insecure)
+}
+
+// runs the commands other than the daemon, None for the daemon
+fn run_once(ctx: &Context, command: &Command, pipelines: &mut [Pipeline]) -> Option<Result<()>> {
+    Some(match command {
+        Command::Doctor => doctor::run(ctx),
+        Command::Send => send_stdin(ctx),
+        Command::Import => import::run(ctx),
+        Command::TestWebhook => cli::test_webhooks(ctx),
+        Command::Backfill { count, .. } => cli::backfill(ctx, pipelines, *count),
+        Command::Run { daemon: true, .. } => return None,
+        Command::Run { .. } => {
+            poll(ctx, pipelines, poll_settings(ctx).1).map(|()| send_digests(ctx))
+        }
+        _ => poll(ctx, pipelines, poll_settings(ctx).1),
+    })
+}
+
+fn run_instance(command: &Command, config_path: &Path, state_directory: &Path) -> Result<()> {
+    let daemon = command.is_daemon();
+    let config = Config::load(config_path, state_directory)?;
+    match command {
+        Command::Status => return cli::print_status(&config, state_directory),
+        Command::Reset => return cli::reset(&config, state_directory),
+        // before the files are opened below, so that they get reported like the other checks
+        Command::Doctor => doctor::check_files(&config, state_directory)?,
+        _ => {}
+    }
+    let resolver = RefCell::new(Resolver::new(config.dns.as_ref())?);
+    let rate_limiter = RateLimiter::new(&config.rate_limit);
+    let throttler = Throttler::load(state_directory)?;
+    let selectors = RefCell::new(Selectors::new());
+    let mut canary = Canary::new();
+
+    if let Command::SelfUpdate { insecure } = *command {
+        return self_update(&config, &mut resolver.borrow_mut(), insecure);
+    }
+    let specs = config.pipelines(state_directory);
+    let filters = specs
+        .iter()
+        .map(|spec| {
+            Ok((
+                Plugins::load(spec.plugins, config.plugin_fuel)?,
+                Scripts::load(spec.scripts)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let rewrites = Rewrites::new(&config.rewrites);
+    let keywords = Keywords::new(config.include.as_ref(), config.exclude.as_ref())?;
+    let mut pipelines = specs
+        .into_iter()
+        .zip(&filters)
+        .map(|(spec, (plugins, scripts))| {
+            if let Command::Scrape = command {
+                Ok(Pipeline::ndjson(spec))
+            } else {
+                Pipeline::new(spec, &rewrites, &keywords, plugins, scripts)
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let deals = match &config.grpc {
+        Some(grpc_config) if daemon => Some(grpc::start(grpc_config)?),
+        _ => None,
+    };
+    let mut interactions_started = false;
+
+    // kept when the clients are rebuilt
+    let cookies = config
+        .login
+        .as_ref()
+        .map(|login| session::load_cookies(state_directory, login).map(Arc::new))
+        .transpose()?;
+    // clients are rebuilt whenever the resolved addresses expire
+    loop {
+        let clients_expire = Some(Instant::now() + resolver.borrow().ttl())
+            .filter(|_| http::pins_addresses(&config.http, &resolver.borrow()));
+        let forum_client =
+            build_forum_client(&config, &mut resolver.borrow_mut(), cookies.as_ref())?;
+        let client = http::build_client(
+            &config.http,
+            config.http.notification_proxy.as_deref(),
+            &config.notification_hosts(),
+            &mut resolver.borrow_mut(),
+        )?;
+        let ctx = Context {
+            state_directory: state_directory.to_owned(),
+            forum_client: &forum_client,
+            cookies: cookies.as_deref(),
+            webhook: Webhook::with_client(&client).with_retry(config.retry),
+            config: &config,
+            selectors: &selectors,
+            favicons: RefCell::new(FaviconCache::load(state_directory)?),
+            resolver: &resolver,
+            avatars: AvatarCache::new(state_directory),
+            archive: Archive::open(state_directory)?,
+            tracer: Tracer::new(config.telemetry.clone()),
+            metrics: Metrics::new(config.metrics.as_ref())?,
+            rate_limiter: &rate_limiter,
+            throttler: &throttler,
+            deals: deals.as_ref(),
+            announcement_channels: RefCell::new(HashMap::new()),
+            guilds: RefCell::new(HashMap::new()),
+            dry_run: command.is_dry_run(),
+        };
+
+        if let Some(result) = run_once(&ctx, command, &mut pipelines) {
+            return result;
+        }
+        if !interactions_started {
+            if let Some(interactions_config) = &config.interactions {
+                start_interactions(&ctx, interactions_config);
+            }
+            interactions_started = true;
+        }
+        run_daemon(&ctx, &mut pipelines, &mut canary, clients_expire);
+        if is_shutting_down() {
+            info!("Shutting down");
+            return Ok(());
+        }
+    }
+}
+
 fn main() {
     if let Err(err) = run() {
-        eprintln!("{}", err);
+        error!("{err}");
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_short_text_in_one_part() {
+        assert_eq!(
+            split_with_continuation("Hyvä tarjous", 20),
+            ["Hyvä tarjous"]
+        );
+    }
+
+    #[test]
+    fn splits_at_whitespace_with_continuation() {
+        let text = "Näyttö halpana kaupasta tänään ja huomenna";
+        let parts = split_with_continuation(text, 30);
+        assert_eq!(
+            parts,
+            ["Näyttö halpana (jatkuu…)", "kaupasta tänään ja huomenna"]
+        );
+        assert!(parts.iter().all(|part| part.chars().count() <= 30));
+    }
+
+    #[test]
+    fn cuts_words_longer_than_a_part() {
+        let word = "a".repeat(25);
+        let parts = split_with_continuation(&word, 20);
+        assert_eq!(
+            parts,
+            [format!("{} (jatkuu…)", "a".repeat(10)), "a".repeat(15)]
+        );
+    }
+}
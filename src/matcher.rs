@@ -0,0 +1,77 @@
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+#[derive(Deserialize)]
+pub struct MatchRules {
+    // case insensitive substrings
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    // case insensitive regexes
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+// matches text containing any of the keywords or patterns
+pub struct Matcher {
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Matcher {
+    pub fn new(rules: &MatchRules) -> Result<Self> {
+        let patterns = rules
+            .patterns
+            .iter()
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|err| {
+                        Error::InvalidConfig(format!("invalid match pattern {pattern}: {err}"))
+                    })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            keywords: rules
+                .keywords
+                .iter()
+                .map(|keyword| keyword.to_lowercase())
+                .collect(),
+            patterns,
+        })
+    }
+
+    pub fn matches(&self, text: &str) -> bool {
+        let lowercase = text.to_lowercase();
+        self.keywords
+            .iter()
+            .any(|keyword| lowercase.contains(keyword))
+            || self.patterns.iter().any(|pattern| pattern.is_match(text))
+    }
+}
+
+// posts must match the include rules if given and mustn't match the exclude rules
+pub struct Keywords {
+    include: Option<Matcher>,
+    exclude: Option<Matcher>,
+}
+
+impl Keywords {
+    pub fn new(include: Option<&MatchRules>, exclude: Option<&MatchRules>) -> Result<Self> {
+        Ok(Self {
+            include: include.map(Matcher::new).transpose()?,
+            exclude: exclude.map(Matcher::new).transpose()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_none() && self.exclude.is_none()
+    }
+
+    pub fn allows(&self, title: &str, content: &str) -> bool {
+        let matches = |matcher: &Matcher| matcher.matches(title) || matcher.matches(content);
+        self.include.as_ref().is_none_or(matches) && !self.exclude.as_ref().is_some_and(matches)
+    }
+}
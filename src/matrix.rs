@@ -0,0 +1,147 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::{Client, Response};
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::html::escape;
+use crate::retry::RetryConfig;
+use crate::webhook::{send_retrying, Embed};
+use crate::{Context, Message};
+
+// makes the transaction ids unique within the process
+static TRANSACTIONS: AtomicU64 = AtomicU64::new(0);
+
+pub struct Matrix<'a> {
+    client: &'a Client,
+    homeserver_url: &'a str,
+    access_token: &'a str,
+    retry: RetryConfig,
+}
+
+#[derive(Serialize)]
+struct RoomMessage<'a> {
+    msgtype: &'a str,
+    body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    formatted_body: Option<&'a str>,
+}
+
+impl<'a> Matrix<'a> {
+    pub fn with_client(client: &'a Client, homeserver_url: &'a str, access_token: &'a str) -> Self {
+        Self {
+            client,
+            homeserver_url,
+            access_token,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn url(&self, segments: &[&str]) -> Result<Url> {
+        let mut url = Url::parse(self.homeserver_url)
+            .map_err(|err| Error::InvalidConfig(format!("invalid homeserver url: {err}")))?;
+        url.path_segments_mut()
+            .map_err(|()| Error::InvalidConfig("invalid homeserver url".to_owned()))?
+            .pop_if_empty()
+            .extend(["_matrix", "client", "v3"])
+            .extend(segments);
+        Ok(url)
+    }
+
+    // checks the access token is valid
+    pub fn whoami(&self) -> Result<Response> {
+        let url = self.url(&["account", "whoami"])?;
+        Ok(self.client.get(url).bearer_auth(self.access_token).send()?)
+    }
+
+    // the plain body is shown by clients not supporting html
+    pub fn send_message(&self, room_id: &str, body: &str, html: Option<&str>) -> Result<Response> {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // the same id on retries keeps the message from being sent twice
+        let transaction_id = format!(
+            "tarjousbot-{}-{}",
+            since_epoch.as_millis(),
+            TRANSACTIONS.fetch_add(1, Ordering::Relaxed)
+        );
+        let url = self.url(&["rooms", room_id, "send", "m.room.message", &transaction_id])?;
+        let payload = RoomMessage {
+            msgtype: "m.text",
+            body,
+            format: html.map(|_| "org.matrix.custom.html"),
+            formatted_body: html,
+        };
+        Ok(send_retrying(&self.retry, || {
+            self.client
+                .put(url.clone())
+                .bearer_auth(self.access_token)
+                .json(&payload)
+        })?)
+    }
+}
+
+fn escape_lines(text: &str) -> String {
+    escape(text).replace('\n', "<br>")
+}
+
+// the embed as html and as plain text, the mentions of the content first
+pub fn format_message(embed: &Embed, content: Option<&str>) -> (String, String) {
+    let mut plain = Vec::new();
+    let mut html = Vec::new();
+    if let Some(content) = content {
+        plain.push(content.to_owned());
+        html.push(escape_lines(content));
+    }
+    if let Some(title) = embed.title.as_deref() {
+        plain.push(title.to_owned());
+        html.push(match embed.url.as_deref() {
+            Some(url) => format!("<b><a href=\"{}\">{}</a></b>", escape(url), escape(title)),
+            None => format!("<b>{}</b>", escape(title)),
+        });
+    }
+    if let Some(description) = embed.description.as_deref() {
+        plain.push(description.to_owned());
+        html.push(escape_lines(description));
+    }
+    for field in &embed.fields {
+        plain.push(format!("{}: {}", field.name, field.value));
+        html.push(format!(
+            "<b>{}:</b> {}",
+            escape(&field.name),
+            escape_lines(&field.value)
+        ));
+    }
+    if let Some(author) = embed.author_name() {
+        plain.push(author.to_owned());
+        html.push(format!("<i>{}</i>", escape(author)));
+    }
+    if let Some(url) = embed.url.as_deref() {
+        plain.push(url.to_owned());
+    }
+    (plain.join("\n"), html.join("<br>"))
+}
+
+pub fn send(
+    ctx: &Context,
+    homeserver_url: &str,
+    access_token: &str,
+    room_id: &str,
+    message: &Message,
+) -> Result<()> {
+    let (plain, html) = format_message(&message.embed.embed, message.content);
+    Matrix::with_client(ctx.webhook.client(), homeserver_url, access_token)
+        .with_retry(ctx.webhook.retry())
+        .send_message(room_id, &plain, Some(&html))?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+#[derive(Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MetricsConfig {
+    Statsd {
+        // e.g. 127.0.0.1:8125
+        address: String,
+        #[serde(default = "default_prefix")]
+        prefix: String,
+    },
+    Dogstatsd {
+        address: String,
+        #[serde(default = "default_prefix")]
+        prefix: String,
+        // e.g. ["env:prod", "forum:io-tech"]
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+}
+
+fn default_prefix() -> String {
+    "tarjousbot".to_owned()
+}
+
+// pushes counters and timings over UDP, metrics are dropped on failure
+pub struct Metrics {
+    socket: Option<UdpSocket>,
+    prefix: String,
+    tags: String,
+}
+
+impl Metrics {
+    pub fn new(config: Option<&MetricsConfig>) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Self {
+                socket: None,
+                prefix: String::new(),
+                tags: String::new(),
+            });
+        };
+        let (address, prefix, tags) = match config {
+            MetricsConfig::Dogstatsd {
+                address,
+                prefix,
+                tags,
+            } if !tags.is_empty() => (address, prefix, format!("|#{}", tags.join(","))),
+            MetricsConfig::Statsd { address, prefix }
+            | MetricsConfig::Dogstatsd {
+                address, prefix, ..
+            } => (address, prefix, String::new()),
+        };
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::InvalidConfig(format!("metrics address {address} not found")))?;
+        // the local address has to be of the same family as the target
+        let local = if address.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        Ok(Self {
+            socket: Some(socket),
+            prefix: prefix.clone(),
+            tags,
+        })
+    }
+
+    fn send(&self, name: &str, value: &str, kind: &str) {
+        if let Some(socket) = &self.socket {
+            let line = format!("{}.{name}:{value}|{kind}{}", self.prefix, self.tags);
+            if let Err(err) = socket.send(line.as_bytes()) {
+                warn!("sending metric {name} failed: {err}");
+            }
+        }
+    }
+
+    pub fn increment(&self, name: &str) {
+        self.count(name, 1);
+    }
+
+    pub fn count(&self, name: &str, value: u64) {
+        self.send(name, &value.to_string(), "c");
+    }
+
+    pub fn timing(&self, name: &str, duration: Duration) {
+        self.send(name, &duration.as_millis().to_string(), "ms");
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use reqwest::blocking::Response;
+use serde_json::json;
+use tracing::warn;
+
+use crate::bot::{Bot, CreatedMessage};
+use crate::config::Target;
+use crate::email::{self, Mailer};
+use crate::error::Result;
+use crate::matrix::{self, Matrix};
+use crate::ntfy::{self, Notification, Ntfy};
+use crate::telegram::{self, Telegram};
+use crate::webhook::{self, AllowedMentions, EmbedBuilder, ExecutionBuilder, WebhookMessage};
+use crate::{exec, feed, publish, slack, truncate, Context, Message};
+
+// delivers messages to one destination, a new service only needs a notifier
+// and a target variant for its configuration
+pub trait Notifier {
+    // returns the id of the message when it can be referred to later
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>>;
+    // used when the service rejects the formatted message, only the mentions of the
+    // configured content are allowed to ping
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        mentions: Option<&str>,
+        title: &str,
+        silent: bool,
+    ) -> Result<Option<String>>;
+
+    // several posts at once, the summary lists them all and the embeds show one each
+    fn notify_digest(
+        &self,
+        ctx: &Context,
+        summary: &Message,
+        _embeds: &[EmbedBuilder],
+    ) -> Result<()> {
+        self.notify(ctx, summary)?;
+        Ok(())
+    }
+}
+
+pub fn for_target(target: &Target) -> Box<dyn Notifier + '_> {
+    match target {
+        Target::Webhook {
+            webhook_url,
+            thread_id,
+            forum,
+        } => Box::new(DiscordWebhook {
+            webhook_url,
+            thread_id: thread_id.as_deref(),
+            forum: *forum,
+        }),
+        Target::Bot {
+            bot_token,
+            channel_id,
+            forum,
+            forum_tags,
+        } => Box::new(DiscordBot {
+            bot_token,
+            channel_id,
+            forum: *forum,
+            forum_tags,
+        }),
+        Target::Exec { command } => Box::new(Exec { command }),
+        Target::Telegram {
+            telegram_token,
+            chat_id,
+        } => Box::new(TelegramChat {
+            token: telegram_token,
+            chat_id,
+        }),
+        Target::Matrix {
+            homeserver_url,
+            access_token,
+            room_id,
+        } => Box::new(MatrixRoom {
+            homeserver_url,
+            access_token,
+            room_id,
+        }),
+        Target::Slack { slack_webhook_url } => Box::new(SlackWebhook {
+            webhook_url: slack_webhook_url,
+        }),
+        Target::Ntfy {
+            ntfy_url,
+            topic,
+            ntfy_token,
+        } => Box::new(NtfyTopic {
+            server_url: ntfy_url,
+            topic,
+            token: ntfy_token,
+        }),
+        Target::Email { smtp_url, from, to } => Box::new(Email {
+            mailer: Mailer::new(smtp_url, from, to),
+        }),
+        Target::Feed {
+            feed_path,
+            feed_items,
+        } => Box::new(Feed {
+            path: feed_path,
+            max_items: *feed_items,
+        }),
+    }
+}
+
+struct DiscordWebhook<'a> {
+    webhook_url: &'a str,
+    thread_id: Option<&'a str>,
+    forum: bool,
+}
+
+impl DiscordWebhook<'_> {
+    fn execute<'b>(&'b self, ctx: &'b Context) -> ExecutionBuilder<'b> {
+        let mut execution = ctx.webhook.execute(self.webhook_url);
+        if let Some(thread_id) = self.thread_id.filter(|_| !self.forum) {
+            execution.thread_id(thread_id);
+        }
+        execution
+    }
+}
+
+impl Notifier for DiscordWebhook<'_> {
+    // embeds over the limits are continued in further messages, in the created
+    // post when posting in a forum, the id returned is of the first one
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        let mut message_id = None;
+        let mut forum_post: Option<String> = None;
+        let embeds = message.embed.split();
+        for embed in &embeds {
+            embed.validate()?;
+        }
+        for (index, embed) in embeds.iter().enumerate() {
+            let mut execution = self.execute(ctx);
+            execution.embed(embed);
+            if message.silent {
+                execution.silent();
+            }
+            if index > 0 {
+                ctx.rate_limiter.acquire(self.webhook_url);
+                if let Some(forum_post) = &forum_post {
+                    execution.thread_id(forum_post.as_str());
+                }
+            } else {
+                if let Some(content) = message.content {
+                    // the content comes from the config, unlike the embed
+                    execution
+                        .content(content)
+                        .allowed_mentions(AllowedMentions::from_text(content));
+                }
+                for attachment in message.attachments {
+                    execution.attachment(
+                        attachment.filename,
+                        attachment.data,
+                        attachment.description,
+                    );
+                }
+                for button in message.buttons {
+                    execution.link_button(button);
+                }
+                if self.forum {
+                    execution.thread_name(truncate(message.title, 100));
+                }
+                execution.wait();
+            }
+            let response = match execution.send().and_then(Response::error_for_status) {
+                Ok(response) => response,
+                // the post was sent, so it isn't retried over a missing continuation
+                Err(err) if index > 0 => {
+                    warn!("sending continuation failed: {err}");
+                    break;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if index == 0 {
+                let created: WebhookMessage = response.json()?;
+                if self.forum {
+                    forum_post = Some(created.channel_id);
+                }
+                message_id = Some(created.id);
+            }
+        }
+        Ok(message_id)
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        mentions: Option<&str>,
+        title: &str,
+        silent: bool,
+    ) -> Result<Option<String>> {
+        let mut execution = self.execute(ctx);
+        execution.content(content).allowed_mentions(
+            mentions.map_or_else(AllowedMentions::none, AllowedMentions::from_text),
+        );
+        if silent {
+            execution.silent();
+        }
+        if self.forum {
+            execution.thread_name(truncate(title, 100));
+        }
+        execution.send()?.error_for_status()?;
+        Ok(None)
+    }
+
+    // a forum channel gets the summary as one post instead of a post per message
+    fn notify_digest(
+        &self,
+        ctx: &Context,
+        summary: &Message,
+        embeds: &[EmbedBuilder],
+    ) -> Result<()> {
+        if self.forum {
+            self.notify(ctx, summary)?;
+            return Ok(());
+        }
+        for (index, group) in webhook::group_embeds(embeds).into_iter().enumerate() {
+            if index > 0 {
+                ctx.rate_limiter.acquire(self.webhook_url);
+            }
+            let mut execution = self.execute(ctx);
+            for embed in group {
+                embed.validate()?;
+                execution.embed(embed);
+            }
+            if summary.silent {
+                execution.silent();
+            }
+            execution.send()?.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+struct DiscordBot<'a> {
+    bot_token: &'a str,
+    channel_id: &'a str,
+    forum: bool,
+    forum_tags: &'a HashMap<String, String>,
+}
+
+impl DiscordBot<'_> {
+    fn bot<'a>(&'a self, ctx: &Context<'a>) -> Bot<'a> {
+        Bot::with_client(ctx.webhook.client(), self.bot_token).with_retry(ctx.webhook.retry())
+    }
+}
+
+impl Notifier for DiscordBot<'_> {
+    // the continuations of a forum post go in the created thread
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        let bot = self.bot(ctx);
+        let embeds = message.embed.split();
+        for embed in &embeds {
+            embed.validate()?;
+        }
+        let applied_tags: Vec<&str> = message
+            .category
+            .and_then(|category| self.forum_tags.get(&category.name))
+            .map(String::as_str)
+            .into_iter()
+            .collect();
+        let mut bot_message = bot.create_message(self.channel_id);
+        bot_message.embed(&embeds[0]);
+        if let Some(content) = message.content {
+            // the content comes from the config, unlike the embed
+            bot_message
+                .content(content)
+                .allowed_mentions(AllowedMentions::from_text(content));
+        }
+        for attachment in message.attachments {
+            bot_message.attachment(attachment.filename, attachment.data, attachment.description);
+        }
+        if message.silent {
+            bot_message.silent();
+        }
+        if self.forum {
+            bot_message.forum_thread(truncate(message.title, 100), &applied_tags);
+        }
+        // the id of the thread when created in a forum channel
+        let created: CreatedMessage = bot_message.send()?.error_for_status()?.json()?;
+        let continuation_channel = if self.forum {
+            &created.id
+        } else {
+            self.channel_id
+        };
+        for embed in &embeds[1..] {
+            ctx.rate_limiter.acquire(self.channel_id);
+            let mut continuation = bot.create_message(continuation_channel);
+            continuation.embed(embed);
+            if message.silent {
+                continuation.silent();
+            }
+            // the post was sent, so it isn't retried over a missing continuation
+            if let Err(err) = continuation.send().and_then(Response::error_for_status) {
+                warn!("sending continuation failed: {err}");
+                break;
+            }
+        }
+        // forum channels can't be announcement channels
+        if self.forum {
+            return Ok(None);
+        }
+        // the message is already sent, so it isn't retried over this
+        if let Err(err) = publish(ctx, &bot, self.channel_id, &created.id) {
+            warn!("publishing message failed: {err}");
+        }
+        Ok(Some(created.id))
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        mentions: Option<&str>,
+        title: &str,
+        silent: bool,
+    ) -> Result<Option<String>> {
+        let bot = self.bot(ctx);
+        let mut bot_message = bot.create_message(self.channel_id);
+        bot_message.content(content).allowed_mentions(
+            mentions.map_or_else(AllowedMentions::none, AllowedMentions::from_text),
+        );
+        if silent {
+            bot_message.silent();
+        }
+        if self.forum {
+            bot_message.forum_thread(truncate(title, 100), &[]);
+        }
+        let created: CreatedMessage = bot_message.send()?.error_for_status()?.json()?;
+        Ok(Some(created.id).filter(|_| !self.forum))
+    }
+
+    // a forum channel gets the summary as one post instead of a post per message
+    fn notify_digest(
+        &self,
+        ctx: &Context,
+        summary: &Message,
+        embeds: &[EmbedBuilder],
+    ) -> Result<()> {
+        if self.forum {
+            self.notify(ctx, summary)?;
+            return Ok(());
+        }
+        let bot = self.bot(ctx);
+        for (index, group) in webhook::group_embeds(embeds).into_iter().enumerate() {
+            if index > 0 {
+                ctx.rate_limiter.acquire(self.channel_id);
+            }
+            let mut bot_message = bot.create_message(self.channel_id);
+            for embed in group {
+                embed.validate()?;
+                bot_message.embed(embed);
+            }
+            if summary.silent {
+                bot_message.silent();
+            }
+            let created: CreatedMessage = bot_message.send()?.error_for_status()?.json()?;
+            if let Err(err) = publish(ctx, &bot, self.channel_id, &created.id) {
+                warn!("publishing message failed: {err}");
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Exec<'a> {
+    command: &'a str,
+}
+
+impl Notifier for Exec<'_> {
+    fn notify(&self, _ctx: &Context, message: &Message) -> Result<Option<String>> {
+        let data = match message.data {
+            Some(data) => data.clone(),
+            None => json!({
+                "title": message.title,
+                "content": message.content,
+                "embed": message.embed.embed,
+            }),
+        };
+        exec::run(self.command, &data)?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        _ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        _title: &str,
+        _silent: bool,
+    ) -> Result<Option<String>> {
+        exec::run(self.command, &json!({ "content": content }))?;
+        Ok(None)
+    }
+}
+
+struct TelegramChat<'a> {
+    token: &'a str,
+    chat_id: &'a str,
+}
+
+impl Notifier for TelegramChat<'_> {
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        telegram::send(ctx, self.token, self.chat_id, message)?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        _title: &str,
+        silent: bool,
+    ) -> Result<Option<String>> {
+        Telegram::with_client(ctx.webhook.client(), self.token)
+            .with_retry(ctx.webhook.retry())
+            .send_message(self.chat_id, content, false, silent)?;
+        Ok(None)
+    }
+}
+
+struct MatrixRoom<'a> {
+    homeserver_url: &'a str,
+    access_token: &'a str,
+    room_id: &'a str,
+}
+
+impl Notifier for MatrixRoom<'_> {
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        matrix::send(
+            ctx,
+            self.homeserver_url,
+            self.access_token,
+            self.room_id,
+            message,
+        )?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        _title: &str,
+        _silent: bool,
+    ) -> Result<Option<String>> {
+        Matrix::with_client(ctx.webhook.client(), self.homeserver_url, self.access_token)
+            .with_retry(ctx.webhook.retry())
+            .send_message(self.room_id, content, None)?
+            .error_for_status()?;
+        Ok(None)
+    }
+}
+
+struct SlackWebhook<'a> {
+    webhook_url: &'a str,
+}
+
+impl Notifier for SlackWebhook<'_> {
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        slack::send(ctx, self.webhook_url, message)?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        _title: &str,
+        _silent: bool,
+    ) -> Result<Option<String>> {
+        slack::send_json(ctx, self.webhook_url, &json!({ "text": content }))?;
+        Ok(None)
+    }
+}
+
+struct Feed<'a> {
+    path: &'a Path,
+    max_items: usize,
+}
+
+impl Notifier for Feed<'_> {
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        feed::add_message(ctx, self.path, self.max_items, message)?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        title: &str,
+        _silent: bool,
+    ) -> Result<Option<String>> {
+        feed::add_text(ctx, self.path, self.max_items, title, content)?;
+        Ok(None)
+    }
+}
+
+struct NtfyTopic<'a> {
+    server_url: &'a str,
+    topic: &'a str,
+    token: &'a str,
+}
+
+impl Notifier for NtfyTopic<'_> {
+    fn notify(&self, ctx: &Context, message: &Message) -> Result<Option<String>> {
+        ntfy::send(ctx, self.server_url, self.topic, self.token, message)?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        title: &str,
+        silent: bool,
+    ) -> Result<Option<String>> {
+        let notification = Notification {
+            title: Some(title),
+            message: content,
+            click: None,
+            priority: silent.then_some(ntfy::LOW_PRIORITY),
+        };
+        Ntfy::with_client(ctx.webhook.client(), self.server_url, self.token)
+            .with_retry(ctx.webhook.retry())
+            .publish(self.topic, &notification)?
+            .error_for_status()?;
+        Ok(None)
+    }
+}
+
+struct Email<'a> {
+    mailer: Mailer<'a>,
+}
+
+impl Notifier for Email<'_> {
+    fn notify(&self, _ctx: &Context, message: &Message) -> Result<Option<String>> {
+        email::send(&self.mailer, message)?;
+        Ok(None)
+    }
+
+    fn notify_plain(
+        &self,
+        _ctx: &Context,
+        content: &str,
+        _mentions: Option<&str>,
+        title: &str,
+        _silent: bool,
+    ) -> Result<Option<String>> {
+        self.mailer
+            .send(title, content.to_owned(), email::plain_html(content))?;
+        Ok(None)
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt::Write;
+
+use reqwest::blocking::{Client, Response};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::retry::RetryConfig;
+use crate::webhook::send_retrying;
+use crate::{truncate, Context, Message};
+
+pub static DEFAULT_SERVER_URL: &str = "https://ntfy.sh";
+
+// push notifications show only the start anyway
+const MAX_MESSAGE_LENGTH: usize = 500;
+pub const LOW_PRIORITY: u8 = 2;
+
+pub struct Ntfy<'a> {
+    client: &'a Client,
+    server_url: &'a str,
+    token: &'a str,
+    retry: RetryConfig,
+}
+
+#[derive(Serialize)]
+pub struct Notification<'a> {
+    pub title: Option<&'a str>,
+    pub message: &'a str,
+    // opened when the notification is tapped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub click: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+}
+
+#[derive(Serialize)]
+struct Publish<'a> {
+    topic: &'a str,
+    #[serde(flatten)]
+    notification: &'a Notification<'a>,
+}
+
+impl<'a> Ntfy<'a> {
+    // the token may be empty for topics without access control
+    pub fn with_client(client: &'a Client, server_url: &'a str, token: &'a str) -> Self {
+        Self {
+            client,
+            server_url: server_url.trim_end_matches('/'),
+            token,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn health(&self) -> reqwest::Result<Response> {
+        self.client
+            .get(format!("{}/v1/health", self.server_url))
+            .send()
+    }
+
+    pub fn publish(&self, topic: &str, notification: &Notification) -> reqwest::Result<Response> {
+        let payload = Publish {
+            topic,
+            notification,
+        };
+        send_retrying(&self.retry, || {
+            let request = self.client.post(self.server_url).json(&payload);
+            if self.token.is_empty() {
+                request
+            } else {
+                request.bearer_auth(self.token)
+            }
+        })
+    }
+}
+
+// the shortened description and the template fields, without markdown links
+fn format_body(message: &Message) -> String {
+    let embed = &message.embed.embed;
+    let mut body = truncate(
+        embed.description.as_deref().unwrap_or_default(),
+        MAX_MESSAGE_LENGTH,
+    )
+    .to_owned();
+    for field in &embed.fields {
+        let _ = write!(body, "\n{}: {}", field.name, field.value);
+    }
+    body
+}
+
+pub fn send(
+    ctx: &Context,
+    server_url: &str,
+    topic: &str,
+    token: &str,
+    message: &Message,
+) -> Result<()> {
+    let body = format_body(message);
+    let notification = Notification {
+        title: message.embed.embed.title.as_deref().or(Some(message.title)),
+        message: &body,
+        click: message.embed.embed.url.as_deref(),
+        priority: message.silent.then_some(LOW_PRIORITY),
+    };
+    Ntfy::with_client(ctx.webhook.client(), server_url, token)
+        .with_retry(ctx.webhook.retry())
+        .publish(topic, &notification)?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::Result;
+use crate::state::write_atomically;
+use crate::ScrapedPost;
+
+// queued posts not received by then are given up on
+const MAX_AGE_SECONDS: i64 = 3 * 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+pub struct OutboxEntry {
+    // hash of the destination key, which may be a secret webhook url
+    sink: String,
+    pub post: ScrapedPost,
+    // unix timestamp, entries queued before it was stored count from now
+    #[serde(default = "now")]
+    queued_at: i64,
+}
+
+fn now() -> i64 {
+    Utc::now().timestamp()
+}
+
+// posts a destination failed to receive, retried at the start of the next run
+pub struct Outbox {
+    path: PathBuf,
+    entries: Vec<OutboxEntry>,
+}
+
+pub fn sink_id(key: &str) -> String {
+    hex::encode(&Sha256::digest(key)[..8])
+}
+
+impl OutboxEntry {
+    pub fn is_for(&self, key: &str) -> bool {
+        self.sink == sink_id(key)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        now() - self.queued_at > MAX_AGE_SECONDS
+    }
+}
+
+impl Outbox {
+    // stored as json lines
+    pub fn load(state_directory: &Path) -> Result<Self> {
+        let path = state_directory.join("outbox.jsonl");
+        let entries = match fs::read_to_string(&path) {
+            Ok(s) => s
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<serde_json::Result<_>>()?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self { path, entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn take(&mut self) -> Vec<OutboxEntry> {
+        std::mem::take(&mut self.entries)
+    }
+
+    pub fn push(&mut self, key: &str, post: ScrapedPost) {
+        self.entries.push(OutboxEntry {
+            sink: sink_id(key),
+            post,
+            queued_at: now(),
+        });
+    }
+
+    pub fn restore(&mut self, entry: OutboxEntry) {
+        self.entries.push(entry);
+    }
+
+    // drops the queued posts matching, returns how many were dropped
+    pub fn purge(&mut self, matches: impl Fn(&ScrapedPost) -> bool) -> usize {
+        let count = self.entries.len();
+        self.entries.retain(|entry| !matches(&entry.post));
+        count - self.entries.len()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if self.entries.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            };
+        }
+        let mut lines = String::new();
+        for entry in &self.entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+        write_atomically(&self.path, &lines)
+    }
+}
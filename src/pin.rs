@@ -0,0 +1,168 @@
+use std::time::Instant;
+
+use chrono::Utc;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::archive::SentMessage;
+use crate::bot::{Bot, ReceivedMessage};
+use crate::error::Result;
+use crate::price;
+use crate::Context;
+
+pub const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// pinning only works with bot destinations
+#[derive(Deserialize)]
+pub struct PinConfig {
+    // relative to the typical price, 0.3 pins deals at least 30 % off right away
+    pub min_discount: Option<f64>,
+    // reactions of any kind on the message
+    pub min_reactions: Option<u32>,
+    // pinned messages are unpinned after this many days
+    #[serde(default = "default_days::<3>")]
+    pub days: u32,
+    // seconds between checking the reactions of recent messages
+    #[serde(default = "default_check_interval::<{ 15 * 60 }>")]
+    pub check_interval: u64,
+}
+
+// defaults shared with the reactions, which check for a different time
+pub fn default_days<const DAYS: u32>() -> u32 {
+    DAYS
+}
+
+pub fn default_check_interval<const SECONDS: u64>() -> u64 {
+    SECONDS
+}
+
+// whether the interval has passed since the last check, which then starts again
+pub fn is_due(checked: &mut Option<Instant>, interval: u64) -> bool {
+    if checked.is_some_and(|checked| checked.elapsed().as_secs() < interval) {
+        return false;
+    }
+    *checked = Some(Instant::now());
+    true
+}
+
+// the start of a window this many days long ending now
+pub fn days_ago(days: u32) -> i64 {
+    Utc::now().timestamp() - i64::from(days) * SECONDS_PER_DAY
+}
+
+fn get_message(ctx: &Context, bot: &Bot, message: &SentMessage) -> Result<Option<ReceivedMessage>> {
+    ctx.rate_limiter.acquire(&message.channel_id);
+    let response = bot.get_message(&message.channel_id, &message.message_id)?;
+    // deleted by a moderator
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(response.error_for_status()?.json()?))
+}
+
+// fetches the sent messages one by one, a message failing doesn't stop checking the rest
+pub fn check_messages(
+    ctx: &Context,
+    messages: &[SentMessage],
+    mut check: impl FnMut(&Bot, &SentMessage, &ReceivedMessage) -> Result<()>,
+) {
+    for message in messages {
+        let Some(bot) = channel_bot(ctx, &message.channel_id) else {
+            continue;
+        };
+        let result = get_message(ctx, &bot, message).and_then(|received| match received {
+            Some(received) => check(&bot, message, &received),
+            None => Ok(()),
+        });
+        if let Err(err) = result {
+            warn!("checking message of post {} failed: {err}", message.post_id);
+        }
+    }
+}
+
+fn is_discounted(config: &PinConfig, price: Option<f64>, typical_price: Option<f64>) -> bool {
+    match (config.min_discount, price::discount(price, typical_price)) {
+        (Some(min_discount), Some(discount)) => discount >= min_discount,
+        _ => false,
+    }
+}
+
+// the bot sending messages to the channel
+pub fn channel_bot<'a>(ctx: &'a Context, channel_id: &str) -> Option<Bot<'a>> {
+    ctx.config
+        .bot_token(channel_id)
+        .map(|bot_token| Bot::with_client(ctx.webhook.client(), bot_token))
+}
+
+fn pin(ctx: &Context, bot: &Bot, message: &SentMessage) -> Result<()> {
+    info!("Pin message of post {}", message.post_id);
+    bot.pin_message(&message.channel_id, &message.message_id)?
+        .error_for_status()?;
+    ctx.archive
+        .set_pinned(&message.message_id, Utc::now().timestamp())
+}
+
+// records a message sent to a bot channel and pins it if the deal is good enough
+pub fn message_sent(
+    ctx: &Context,
+    message: &SentMessage,
+    price: Option<f64>,
+    typical_price: Option<f64>,
+) -> Result<()> {
+    ctx.archive.insert_message(message)?;
+    let Some(config) = &ctx.config.pin else {
+        return Ok(());
+    };
+    if !is_discounted(config, price, typical_price) {
+        return Ok(());
+    }
+    match channel_bot(ctx, &message.channel_id) {
+        Some(bot) => pin(ctx, &bot, message),
+        None => Ok(()),
+    }
+}
+
+// pins recent messages with enough reactions and unpins the expired ones
+pub fn update(ctx: &Context, checked: &mut Option<Instant>) -> Result<()> {
+    let Some(config) = &ctx.config.pin else {
+        return Ok(());
+    };
+    if !is_due(checked, config.check_interval) {
+        return Ok(());
+    }
+    let expired = days_ago(config.days);
+
+    if let Some(min_reactions) = config.min_reactions {
+        let messages = ctx.archive.unpinned_messages(expired)?;
+        check_messages(ctx, &messages, |bot, message, received| {
+            if received.reaction_count() >= min_reactions {
+                pin(ctx, bot, message)?;
+            }
+            Ok(())
+        });
+    }
+
+    for message in ctx.archive.pinned_messages(expired)? {
+        if let Err(err) = unpin(ctx, &message) {
+            warn!(
+                "unpinning message of post {} failed: {err}",
+                message.post_id
+            );
+        }
+    }
+    Ok(())
+}
+
+fn unpin(ctx: &Context, message: &SentMessage) -> Result<()> {
+    if let Some(bot) = channel_bot(ctx, &message.channel_id) {
+        info!("Unpin message of post {}", message.post_id);
+        ctx.rate_limiter.acquire(&message.channel_id);
+        let response = bot.unpin_message(&message.channel_id, &message.message_id)?;
+        // already unpinned or deleted by someone else
+        if response.status() != StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+    }
+    ctx.archive.set_unpinned(&message.message_id)
+}
@@ -0,0 +1,903 @@
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+use chrono::Utc;
+use tarjousbot::forum::{self, Post};
+use tracing::{info, info_span, warn};
+
+use crate::archive::{parse_timestamp, SentMessage, TrackedMessage};
+use crate::bot::{Bot, ReceivedMessage};
+use crate::category::Category;
+use crate::config::{Destination, FilterErrors, PipelineSpec, Target, ThreadSource};
+use crate::error::Result;
+use crate::matcher::{Keywords, Matcher};
+use crate::outbox::{self, Outbox, OutboxEntry};
+use crate::plugin::{Decision, Plugins};
+use crate::posters::PosterConfig;
+use crate::price::PriceRule;
+use crate::rewrite::Rewrites;
+use crate::script::Scripts;
+use crate::source::{
+    Source, SourceState, XenForoFeedSource, XenForoForumSource, XenForoSearchSource, XenForoSource,
+};
+use crate::throttle::{self, DigestEntry};
+use crate::webhook::{self, EmbedBuilder, LinkButton};
+use crate::{
+    build_embed, category, dedup, digest, discussion, edits, forward_post, get_store_url,
+    is_burst_active, pin, price, print_payload, reactions, send_message, split_with_continuation,
+    update_post, Context, Message, Outgoing, ScrapedPost,
+};
+
+// returns false to drop the post, may also modify it or narrow down the sinks
+pub trait Filter {
+    fn apply(&self, post: &mut ScrapedPost, sinks: &mut Vec<usize>) -> Result<bool>;
+}
+
+pub trait Sink {
+    fn kind(&self) -> &'static str;
+    // identifies the sink for retrying posts it failed to receive
+    fn key(&self) -> &str;
+    fn send(&self, ctx: &Context, outgoing: &Outgoing) -> Result<()>;
+    // several posts together, oldest first
+    fn send_digest(&self, ctx: &Context, posts: &[&ScrapedPost]) -> Result<()>;
+
+    // whether the post was edited since it was sent, only sinks able to update it tell
+    fn is_outdated(&self, _ctx: &Context, _post: &ScrapedPost) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn update(&self, _ctx: &Context, _outgoing: &Outgoing) -> Result<()> {
+        Ok(())
+    }
+
+    // the newest post already found at the destination, checked on the first run
+    fn last_forwarded(&self, _ctx: &Context) -> Result<Option<u32>> {
+        Ok(None)
+    }
+}
+
+// returns false if the post should be dropped
+fn apply_decision(post: &mut ScrapedPost, sinks: &mut Vec<usize>, decision: Decision) -> bool {
+    match decision {
+        Decision::Forward => {}
+        Decision::Drop => return false,
+        Decision::Modify { title, content } => {
+            if let Some(title) = title {
+                post.title = title;
+            }
+            if let Some(content) = content {
+                post.content = content;
+            }
+        }
+        Decision::Route {
+            destinations: routed,
+        } => sinks.retain(|index| routed.contains(index)),
+    }
+    true
+}
+
+pub struct PluginFilter<'a> {
+    plugins: &'a Plugins,
+    index: usize,
+}
+
+impl Filter for PluginFilter<'_> {
+    fn apply(&self, post: &mut ScrapedPost, sinks: &mut Vec<usize>) -> Result<bool> {
+        let decision = self.plugins.decide(self.index, post)?;
+        Ok(apply_decision(post, sinks, decision))
+    }
+}
+
+pub struct ScriptFilter<'a> {
+    scripts: &'a Scripts,
+    index: usize,
+}
+
+impl Filter for ScriptFilter<'_> {
+    fn apply(&self, post: &mut ScrapedPost, sinks: &mut Vec<usize>) -> Result<bool> {
+        let decisions = self.scripts.decide(
+            self.index,
+            &post.title,
+            &post.content,
+            price::parse_price(&post.content),
+            &post.username,
+        )?;
+        Ok(decisions
+            .into_iter()
+            .all(|decision| apply_decision(post, sinks, decision)))
+    }
+}
+
+pub struct RewriteFilter<'a> {
+    rewrites: &'a Rewrites,
+}
+
+impl Filter for RewriteFilter<'_> {
+    fn apply(&self, post: &mut ScrapedPost, _sinks: &mut Vec<usize>) -> Result<bool> {
+        post.content = self.rewrites.apply(&post.content);
+        Ok(true)
+    }
+}
+
+pub struct KeywordFilter<'a> {
+    keywords: &'a Keywords,
+}
+
+impl Filter for KeywordFilter<'_> {
+    fn apply(&self, post: &mut ScrapedPost, _sinks: &mut Vec<usize>) -> Result<bool> {
+        Ok(self.keywords.allows(&post.title, &post.content))
+    }
+}
+
+// drops the posts of ignored posters
+pub struct PosterFilter<'a> {
+    posters: &'a PosterConfig,
+}
+
+impl Filter for PosterFilter<'_> {
+    fn apply(&self, post: &mut ScrapedPost, _sinks: &mut Vec<usize>) -> Result<bool> {
+        Ok(!self.posters.is_ignored(&post.username))
+    }
+}
+
+// narrows the sinks down to the destinations whose filter matches the post
+pub struct DestinationFilter {
+    // by sink index, None matching everything
+    matchers: Vec<Option<Matcher>>,
+}
+
+impl Filter for DestinationFilter {
+    fn apply(&self, post: &mut ScrapedPost, sinks: &mut Vec<usize>) -> Result<bool> {
+        sinks.retain(|&index| {
+            self.matchers[index].as_ref().is_none_or(|matcher| {
+                matcher.matches(&post.title) || matcher.matches(&post.content)
+            })
+        });
+        // dropped when no destination wants it
+        Ok(!sinks.is_empty())
+    }
+}
+
+// narrows the sinks down to the destinations whose price rules the post passes
+pub struct PriceFilter<'a> {
+    categories: &'a [Category],
+    // by sink index
+    rules: Vec<&'a [PriceRule]>,
+}
+
+impl Filter for PriceFilter<'_> {
+    fn apply(&self, post: &mut ScrapedPost, sinks: &mut Vec<usize>) -> Result<bool> {
+        let price = price::parse_price(&post.content);
+        let category = category::classify(self.categories, &post.title, &post.content);
+        sinks.retain(|&index| {
+            self.rules[index]
+                .iter()
+                .all(|rule| rule.allows(price, category))
+        });
+        Ok(!sinks.is_empty())
+    }
+}
+
+fn source(spec: &PipelineSpec) -> Box<dyn Source> {
+    match (&spec.forum_url, &spec.search) {
+        (Some(forum_url), _) => Box::new(XenForoForumSource::new(forum_url)),
+        (None, Some(query)) => Box::new(XenForoSearchSource::new(query)),
+        (None, None) if spec.thread_source == ThreadSource::Rss => {
+            Box::new(XenForoFeedSource::new(&spec.thread_url))
+        }
+        (None, None) => Box::new(XenForoSource::new(&spec.thread_url)),
+    }
+}
+
+pub struct DestinationSink<'a> {
+    destination: &'a Destination,
+    // the mentions of the destination with what they match
+    mentions: Vec<(Matcher, &'a str)>,
+}
+
+// the parts of the message a post is sent as
+struct Composed<'a> {
+    embed: EmbedBuilder<'a>,
+    buttons: Vec<LinkButton<'a>>,
+    content: Option<String>,
+    silent: bool,
+}
+
+impl Composed<'_> {
+    fn message<'a>(&'a self, outgoing: &'a Outgoing) -> Message<'a> {
+        let details = outgoing.details;
+        Message {
+            content: self.content.as_deref(),
+            embed: &self.embed,
+            attachments: outgoing.attachments,
+            buttons: &self.buttons,
+            title: &details.display_title,
+            category: details.category,
+            data: Some(outgoing.data),
+            silent: self.silent,
+        }
+    }
+}
+
+impl DestinationSink<'_> {
+    fn compose<'a>(&self, outgoing: &Outgoing<'a>) -> Composed<'a> {
+        let post = outgoing.post;
+        let details = outgoing.details;
+        // the template fields are shown separately
+        let content = details
+            .fields
+            .as_ref()
+            .map_or(post.content.as_str(), |fields| fields.rest.as_str());
+        // long posts continue in further embeds instead of being cut
+        let mut descriptions =
+            split_with_continuation(content, self.destination.max_description_length.min(2048))
+                .into_iter();
+        let mut embed = build_embed(
+            post,
+            details,
+            descriptions.next().unwrap_or_default(),
+            outgoing.avatar_icon_url,
+            outgoing.image_url,
+        );
+        for description in descriptions {
+            embed.continuation(description);
+        }
+        let escalation = self.destination.escalation.as_ref();
+        let escalated = escalation.filter(|escalation| {
+            price::discount(details.price, details.typical_price)
+                .is_some_and(|discount| discount >= escalation.min_discount)
+        });
+        let mut parts: Vec<&str> = Vec::new();
+        // nobody is notified of a deal already sent
+        let duplicate = details.duplicate_of.is_some();
+        for (matcher, mention) in self.mentions.iter().filter(|_| !duplicate) {
+            if (matcher.matches(&post.title) || matcher.matches(&post.content))
+                && !parts.contains(mention)
+            {
+                parts.push(mention);
+            }
+        }
+        // whoever is mentioned gets notified even when the rest is sent silently
+        let mentioned = !parts.is_empty();
+        if let Some(escalation) = escalated.filter(|_| !duplicate) {
+            if !parts.contains(&escalation.mention.as_str()) {
+                parts.push(&escalation.mention);
+            }
+        }
+        parts.extend(details.price_drop_notice.as_deref());
+        let content = Some(parts.join(" ")).filter(|content| !content.is_empty());
+        let mut buttons = Vec::new();
+        if self.destination.link_buttons {
+            let urls = [
+                ("Avaa tarjous", Some(post.url.as_str())),
+                ("Avaa kauppa", get_store_url(&post.links)),
+            ];
+            for (label, url) in urls.iter().copied() {
+                // longer urls would get the whole message rejected
+                if let Some(url) = url.filter(|url| url.len() <= webhook::BUTTON_URL_LIMIT) {
+                    buttons.push(LinkButton::new(label, url));
+                }
+            }
+        }
+        Composed {
+            embed,
+            buttons,
+            content,
+            silent: duplicate
+                || (escalated.is_none()
+                    && !mentioned
+                    && escalation.is_some_and(|escalation| escalation.silent)),
+        }
+    }
+}
+
+fn bot_message_sent(ctx: &Context, outgoing: &Outgoing, sent: &SentMessage) {
+    let details = outgoing.details;
+    // the message is already sent, so it isn't retried over these
+    if let Err(err) = pin::message_sent(ctx, sent, details.price, details.typical_price) {
+        warn!("recording sent message failed: {err}");
+    }
+    if let Err(err) = reactions::seed(ctx, sent) {
+        warn!("adding reactions failed: {err}");
+    }
+    let thread_name = details
+        .canonical_name
+        .as_deref()
+        .unwrap_or(&details.display_title);
+    if let Err(err) = discussion::start_thread(ctx, sent, thread_name) {
+        warn!("starting discussion thread failed: {err}");
+    }
+}
+
+impl Sink for DestinationSink<'_> {
+    fn kind(&self) -> &'static str {
+        self.destination.target.kind()
+    }
+
+    fn key(&self) -> &str {
+        self.destination.target.key()
+    }
+
+    fn send(&self, ctx: &Context, outgoing: &Outgoing) -> Result<()> {
+        let post = outgoing.post;
+        let details = outgoing.details;
+        // the digest of the previous window of a throttled destination goes first
+        throttle::send_digest(ctx, self.destination)?;
+        // nothing is held back during sale events
+        if let Some(throttle) = self
+            .destination
+            .throttle
+            .as_ref()
+            .filter(|_| !is_burst_active(ctx))
+        {
+            let entry = DigestEntry {
+                title: details.display_title.to_string(),
+                url: post.url.clone(),
+                price: details.price,
+                author: Some(post.username.clone()),
+                posted_at: parse_timestamp(&post.timestamp),
+            };
+            if !ctx
+                .throttler
+                .admit(ctx, self.destination.target.key(), throttle, entry)?
+            {
+                info!("Throttled, post {} held back for the digest", post.id);
+                return Ok(());
+            }
+        }
+        let composed = self.compose(outgoing);
+        let message_id = send_message(ctx, &self.destination.target, &composed.message(outgoing))?;
+        match (message_id, &self.destination.target) {
+            (Some(message_id), Target::Bot { channel_id, .. }) => {
+                let sent = SentMessage {
+                    channel_id: channel_id.clone(),
+                    message_id,
+                    post_id: post.id,
+                    sent_at: Utc::now().timestamp(),
+                };
+                bot_message_sent(ctx, outgoing, &sent);
+            }
+            (Some(message_id), Target::Webhook { .. }) if ctx.config.edit_tracking.is_some() => {
+                let tracked = TrackedMessage {
+                    destination: outbox::sink_id(self.key()),
+                    post_id: post.id,
+                    message_id,
+                    content_hash: edits::content_hash(&post.content),
+                };
+                // the message is already sent, so it isn't retried over this
+                if let Err(err) = ctx.archive.insert_tracked_message(&tracked) {
+                    warn!("recording sent message failed: {err}");
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn is_outdated(&self, ctx: &Context, post: &ScrapedPost) -> Result<bool> {
+        if !matches!(self.destination.target, Target::Webhook { .. }) {
+            return Ok(false);
+        }
+        let tracked = ctx
+            .archive
+            .tracked_message(&outbox::sink_id(self.key()), post.id)?;
+        Ok(tracked
+            .is_some_and(|tracked| tracked.content_hash != edits::content_hash(&post.content)))
+    }
+
+    // only the first message of a post continued in several is updated
+    fn update(&self, ctx: &Context, outgoing: &Outgoing) -> Result<()> {
+        let Target::Webhook {
+            webhook_url,
+            thread_id,
+            forum,
+        } = &self.destination.target
+        else {
+            return Ok(());
+        };
+        let post = outgoing.post;
+        let Some(tracked) = ctx
+            .archive
+            .tracked_message(&outbox::sink_id(self.key()), post.id)?
+        else {
+            return Ok(());
+        };
+        let composed = self.compose(outgoing);
+        if ctx.dry_run {
+            return print_payload(&self.destination.target, &composed.message(outgoing));
+        }
+        info!(message_id = %tracked.message_id, "Update message");
+        let embeds = composed.embed.split();
+        embeds[0].validate()?;
+        ctx.rate_limiter.acquire(self.key());
+        let mut edit = ctx.webhook.edit_message(webhook_url, &tracked.message_id);
+        edit.embed(&embeds[0]);
+        // a forum post has the id of its first message
+        if *forum {
+            edit.thread_id(tracked.message_id.as_str());
+        } else if let Some(thread_id) = thread_id {
+            edit.thread_id(thread_id.as_str());
+        }
+        if let Some(content) = &composed.content {
+            edit.content(content.as_str());
+        }
+        edit.send()?.error_for_status()?;
+        ctx.archive.insert_tracked_message(&TrackedMessage {
+            content_hash: edits::content_hash(&post.content),
+            ..tracked
+        })
+    }
+
+    // bypasses the throttle, the digest already takes only a few messages
+    fn send_digest(&self, ctx: &Context, posts: &[&ScrapedPost]) -> Result<()> {
+        digest::send(ctx, &self.destination.target, posts)
+    }
+
+    fn last_forwarded(&self, ctx: &Context) -> Result<Option<u32>> {
+        let Target::Bot {
+            bot_token,
+            channel_id,
+            forum: false,
+            ..
+        } = &self.destination.target
+        else {
+            return Ok(None);
+        };
+        let messages: Vec<ReceivedMessage> = Bot::with_client(ctx.webhook.client(), bot_token)
+            .get_messages(channel_id, HISTORY_LIMIT)?
+            .error_for_status()?
+            .json()?;
+        Ok(messages
+            .iter()
+            .flat_map(|message| {
+                let embeds = message
+                    .embeds
+                    .iter()
+                    .flat_map(|embed| embed.url.iter().chain(&embed.description));
+                std::iter::once(&message.content).chain(embeds)
+            })
+            .flat_map(|text| forum::linked_post_ids(text))
+            .max())
+    }
+}
+
+// messages of the channel scanned for posts linked before the bot took over
+const HISTORY_LIMIT: u32 = 100;
+// xenforo shows this many posts per page by default
+const POSTS_PER_PAGE: usize = 20;
+
+// prints the scraped posts as json lines
+pub struct NdjsonSink;
+
+impl Sink for NdjsonSink {
+    fn kind(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn key(&self) -> &'static str {
+        "ndjson"
+    }
+
+    fn send(&self, _ctx: &Context, outgoing: &Outgoing) -> Result<()> {
+        println!("{}", serde_json::to_string(outgoing.post)?);
+        Ok(())
+    }
+
+    fn send_digest(&self, _ctx: &Context, posts: &[&ScrapedPost]) -> Result<()> {
+        for post in posts {
+            println!("{}", serde_json::to_string(post)?);
+        }
+        Ok(())
+    }
+}
+
+pub struct Pipeline<'a> {
+    pub name: String,
+    state_directory: PathBuf,
+    source: Box<dyn Source + 'a>,
+    filters: Vec<Box<dyn Filter + 'a>>,
+    sinks: Vec<Box<dyn Sink + 'a>>,
+    // where a dry run left off, kept in memory for the lifetime of the daemon
+    dry_run_state: Option<SourceState>,
+}
+
+impl<'a> Pipeline<'a> {
+    // the content is rewritten first, then the keyword and destination filters apply
+    // and the plugins run before the scripts, route decisions refer to the destinations
+    pub fn new(
+        spec: PipelineSpec<'a>,
+        rewrites: &'a Rewrites,
+        keywords: &'a Keywords,
+        plugins: &'a Plugins,
+        scripts: &'a Scripts,
+    ) -> Result<Self> {
+        let mut filters: Vec<Box<dyn Filter>> = Vec::new();
+        if !rewrites.is_empty() {
+            filters.push(Box::new(RewriteFilter { rewrites }));
+        }
+        if !spec.posters.ignored.is_empty() {
+            filters.push(Box::new(PosterFilter {
+                posters: spec.posters,
+            }));
+        }
+        if !keywords.is_empty() {
+            filters.push(Box::new(KeywordFilter { keywords }));
+        }
+        let matchers = spec
+            .destinations
+            .iter()
+            .map(|destination| destination.filter.as_ref().map(Matcher::new).transpose())
+            .collect::<Result<Vec<_>>>()?;
+        if matchers.iter().any(Option::is_some) {
+            filters.push(Box::new(DestinationFilter { matchers }));
+        }
+        let rules: Vec<&[PriceRule]> = spec
+            .destinations
+            .iter()
+            .map(|destination| destination.prices.as_slice())
+            .collect();
+        if rules.iter().any(|rules| !rules.is_empty()) {
+            filters.push(Box::new(PriceFilter {
+                categories: spec.categories,
+                rules,
+            }));
+        }
+        for index in 0..plugins.len() {
+            filters.push(Box::new(PluginFilter { plugins, index }));
+        }
+        for index in 0..scripts.len() {
+            filters.push(Box::new(ScriptFilter { scripts, index }));
+        }
+        let sinks = spec
+            .destinations
+            .iter()
+            .map(|destination| {
+                let mentions = destination
+                    .mentions
+                    .iter()
+                    .map(|rule| Ok((Matcher::new(&rule.rules)?, rule.mention.as_str())))
+                    .collect::<Result<_>>()?;
+                Ok(Box::new(DestinationSink {
+                    destination,
+                    mentions,
+                }) as Box<dyn Sink>)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            source: source(&spec),
+            name: spec.name,
+            state_directory: spec.state_directory,
+            filters,
+            sinks,
+            dry_run_state: None,
+        })
+    }
+
+    // only scrapes, without filtering or notifying anyone
+    pub fn ndjson(spec: PipelineSpec<'a>) -> Self {
+        Self {
+            source: source(&spec),
+            name: spec.name,
+            state_directory: spec.state_directory,
+            filters: Vec::new(),
+            sinks: vec![Box::new(NdjsonSink)],
+            dry_run_state: None,
+        }
+    }
+
+    // resends the posts queued on previous runs, keeping those failing again
+    fn flush_outbox(&self, ctx: &Context, outbox: &mut Outbox) -> Result<()> {
+        let mut entries = outbox.take().into_iter();
+        let result = entries
+            .by_ref()
+            .try_for_each(|entry| self.resend(ctx, outbox, entry));
+        // the entries after a failed one weren't tried
+        for entry in entries {
+            outbox.restore(entry);
+        }
+        outbox.save()?;
+        result
+    }
+
+    fn resend(&self, ctx: &Context, outbox: &mut Outbox, entry: OutboxEntry) -> Result<()> {
+        let Some(sink) = self.sinks.iter().find(|sink| entry.is_for(sink.key())) else {
+            warn!(
+                "Destination of queued post {} removed, dropped",
+                entry.post.id
+            );
+            return Ok(());
+        };
+        info!("Resend queued post {}", entry.post.id);
+        let failed = match forward_post(&entry.post, &[&**sink], ctx) {
+            Ok(failed) => failed,
+            Err(err) => {
+                outbox.restore(entry);
+                return Err(err);
+            }
+        };
+        match failed.first() {
+            None => {}
+            Some((_, err)) if err.is_permanent() => {
+                warn!("Queued post {} dropped: {err}", entry.post.id);
+            }
+            Some(_) if entry.is_expired() => {
+                warn!(
+                    "Queued post {} dropped after retrying too long",
+                    entry.post.id
+                );
+            }
+            Some(_) => outbox.restore(entry),
+        }
+        Ok(())
+    }
+
+    // the indices of the sinks left after the filters, None if the post was dropped,
+    // a filter failing on the post doesn't keep the pipeline from getting past it
+    fn apply_filters(&self, ctx: &Context, post: &mut ScrapedPost) -> Option<Vec<usize>> {
+        let mut sinks: Vec<usize> = (0..self.sinks.len()).collect();
+        for filter in &self.filters {
+            match filter.apply(post, &mut sinks) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => {
+                    warn!(post_id = post.id, "filter failed: {err}");
+                    ctx.metrics.increment("errors");
+                    if ctx.config.filter_errors == FilterErrors::Drop {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(sinks)
+    }
+
+    // applies the filters to a new post, recording the decision
+    fn filter(&self, ctx: &Context, post: &mut ScrapedPost) -> Result<Option<Vec<usize>>> {
+        let mut decision_span = ctx.tracer.span("filter decision");
+        decision_span.attribute("post_id", &post.id);
+        let sinks = self.apply_filters(ctx, post);
+        // collapsed duplicates are told apart when the post is sent
+        let dedup = ctx.config.dedup.as_ref().filter(|dedup| !dedup.collapse);
+        if let (Some(dedup), Some(_)) = (dedup, &sinks) {
+            if let Some(earlier) = dedup::earlier_post(ctx, dedup, post)? {
+                info!(post_id = post.id, "Duplicate of {earlier}");
+                decision_span.attribute("decision", &"duplicate");
+                ctx.metrics.increment("posts.duplicate");
+                return Ok(None);
+            }
+        }
+        if sinks.is_some() {
+            decision_span.attribute("decision", &"forward");
+        } else {
+            info!(post_id = post.id, "Dropped by a filter");
+            decision_span.attribute("decision", &"drop");
+            ctx.metrics.increment("posts.filtered");
+        }
+        Ok(sinks)
+    }
+
+    // updates the messages of the newest forwarded posts edited since
+    fn track_edits(
+        &self,
+        ctx: &Context,
+        posts: &[Post],
+        last_sent: u32,
+        count: usize,
+    ) -> Result<()> {
+        let forwarded: Vec<&Post> = posts.iter().filter(|post| post.id <= last_sent).collect();
+        for post in &forwarded[forwarded.len().saturating_sub(count)..] {
+            // filtered again as the message was sent with the filtered content
+            let mut post = ScrapedPost::from((*post).clone());
+            let Some(sinks) = self.apply_filters(ctx, &mut post) else {
+                continue;
+            };
+            let mut outdated: Vec<&dyn Sink> = Vec::new();
+            for index in sinks {
+                if self.sinks[index].is_outdated(ctx, &post)? {
+                    outdated.push(&*self.sinks[index]);
+                }
+            }
+            if !outdated.is_empty() {
+                info!(post_id = post.id, "Post edited");
+                update_post(&post, &outdated, ctx)?;
+            }
+        }
+        Ok(())
+    }
+
+    // runs the filters and sends the post to the sinks left, false if it was dropped
+    fn forward(&self, ctx: &Context, mut post: ScrapedPost, outbox: &mut Outbox) -> Result<bool> {
+        let Some(sinks) = self.filter(ctx, &mut post)? else {
+            return Ok(false);
+        };
+        info!(post_id = post.id, "New message");
+
+        let sinks: Vec<&dyn Sink> = sinks.iter().map(|&index| &*self.sinks[index]).collect();
+        let failed = forward_post(&post, &sinks, ctx)?;
+        for (index, err) in failed {
+            if err.is_permanent() {
+                continue;
+            }
+            info!(
+                post_id = post.id,
+                destination = sinks[index].kind(),
+                "Queued"
+            );
+            outbox.push(sinks[index].key(), post.clone());
+        }
+        if !ctx.dry_run {
+            outbox.save()?;
+        }
+        Ok(true)
+    }
+
+    // sends the posts passing the filters as a digest per sink, at most max_posts of them
+    // as when sent one by one, the posts of a failed digest are queued to be resent
+    fn forward_digest(
+        &self,
+        ctx: &Context,
+        posts: Vec<ScrapedPost>,
+        state: &mut SourceState,
+        max_posts: Option<usize>,
+        outbox: &mut Outbox,
+    ) -> Result<()> {
+        let mut forwarded = Vec::new();
+        for mut post in posts {
+            let id = post.id;
+            ctx.metrics.increment("posts.parsed");
+            if let Some(sinks) = self.filter(ctx, &mut post)? {
+                if max_posts.is_some_and(|max_posts| forwarded.len() >= max_posts) {
+                    info!("Reached the maximum of {} posts per run", forwarded.len());
+                    ctx.metrics.increment("posts.limited");
+                    break;
+                }
+                forwarded.push((post, sinks));
+            }
+            state.forwarded(id);
+        }
+        for (index, sink) in self.sinks.iter().enumerate() {
+            let posts: Vec<&ScrapedPost> = forwarded
+                .iter()
+                .filter(|(_, sinks)| sinks.contains(&index))
+                .map(|(post, _)| post)
+                .collect();
+            if posts.is_empty() {
+                continue;
+            }
+            let _send = info_span!("send", destination = sink.kind()).entered();
+            if let Err(err) = sink.send_digest(ctx, &posts) {
+                warn!("sending digest failed: {err}");
+                ctx.metrics.increment("errors");
+                for post in posts {
+                    info!(post_id = post.id, destination = sink.kind(), "Queued");
+                    outbox.push(sink.key(), post.clone());
+                }
+            }
+        }
+        for (post, _) in &forwarded {
+            digest::record(ctx, post)?;
+        }
+        if !ctx.dry_run {
+            outbox.save()?;
+        }
+        Ok(())
+    }
+
+    // sends the newest posts again without moving the position of the thread
+    pub fn backfill(&mut self, ctx: &Context, count: usize) -> Result<()> {
+        let mut state = SourceState::load(&self.state_directory)?;
+        // enough pages back to have the posts, the source continues up to the newest one
+        let pages_back = u32::try_from(count.div_ceil(POSTS_PER_PAGE)).unwrap_or(u32::MAX);
+        state.page = state
+            .page
+            .map(|page| page.saturating_sub(pages_back).max(1));
+        // every post and listed thread counts as new, as on no run before
+        state.last_post = Some(0);
+        state.last_thread = state.last_thread.and(Some(0));
+        let posts = self.source.poll(ctx, &mut state)?;
+        let mut outbox = Outbox::load(&self.state_directory)?;
+        let skipped = posts.len().saturating_sub(count);
+        for post in posts.into_iter().skip(skipped) {
+            self.forward(ctx, ScrapedPost::from(post), &mut outbox)?;
+        }
+        if ctx.dry_run {
+            return Ok(());
+        }
+        ctx.favicons.borrow().save()
+    }
+
+    fn save(ctx: &Context, state: &SourceState) -> Result<()> {
+        if ctx.dry_run {
+            return Ok(());
+        }
+        ctx.favicons.borrow().save()?;
+        state.save()
+    }
+
+    // forwards new posts, at most max_posts of them if given
+    pub fn run(&mut self, ctx: &Context, max_posts: Option<usize>) -> Result<()> {
+        let mut state = match self.dry_run_state.take() {
+            Some(state) => state,
+            None => SourceState::load(&self.state_directory)?,
+        };
+        let result = self.forward_new(ctx, &mut state, max_posts);
+        if ctx.dry_run {
+            self.dry_run_state = Some(state);
+        }
+        result
+    }
+
+    fn forward_new(
+        &mut self,
+        ctx: &Context,
+        state: &mut SourceState,
+        max_posts: Option<usize>,
+    ) -> Result<()> {
+        let mut outbox = Outbox::load(&self.state_directory)?;
+        if !ctx.dry_run {
+            self.flush_outbox(ctx, &mut outbox)?;
+        }
+        // posts already linked at the destinations before the first run are continued from,
+        // starting from the page the newest of them is on
+        if state.last_post.is_none() {
+            let mut linked = None;
+            for sink in &self.sinks {
+                linked = linked.max(sink.last_forwarded(ctx)?);
+            }
+            if let Some(linked) = linked {
+                info!("Continuing after post {linked} found at a destination");
+                self.source.seek(ctx, state, linked)?;
+                state.last_post = Some(linked);
+            }
+        }
+        let posts = self.source.poll(ctx, state)?;
+
+        // otherwise nothing is sent on the first run, only the current position is recorded
+        let Some(last_sent) = state.last_post else {
+            state.last_post = posts.iter().map(|post| post.id).max();
+            return Self::save(ctx, state);
+        };
+        if let Some(edit_tracking) = &ctx.config.edit_tracking {
+            self.track_edits(ctx, &posts, last_sent, edit_tracking.posts)?;
+        }
+        let posts: Vec<ScrapedPost> = posts
+            .into_iter()
+            .filter(|post| post.id > last_sent)
+            .map(ScrapedPost::from)
+            .collect();
+
+        // every deal is sent on its own during sale events
+        if !is_burst_active(ctx)
+            && ctx
+                .config
+                .digest_after
+                .is_some_and(|digest_after| posts.len() >= digest_after.max(1))
+        {
+            self.forward_digest(ctx, posts, state, max_posts, &mut outbox)?;
+            return Self::save(ctx, state);
+        }
+
+        let mut forwarded = 0;
+        for post in posts {
+            let id = post.id;
+            ctx.metrics.increment("posts.parsed");
+            if max_posts.is_some_and(|max_posts| forwarded >= max_posts) {
+                let mut decision_span = ctx.tracer.span("filter decision");
+                decision_span.attribute("post_id", &id);
+                info!("Reached the maximum of {forwarded} posts per run");
+                decision_span.attribute("decision", &"limit");
+                ctx.metrics.increment("posts.limited");
+                break;
+            }
+            if self.forward(ctx, post, &mut outbox)? {
+                forwarded += 1;
+            }
+            state.forwarded(id);
+            if !ctx.dry_run {
+                state.save()?;
+            }
+        }
+
+        Self::save(ctx, state)
+    }
+}
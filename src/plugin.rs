@@ -0,0 +1,103 @@
+use std::convert::TryFrom;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use wasmtime::{Config, Engine, Instance, Module, Store, Trap};
+
+use crate::error::{Error, Result};
+
+// returned by filters as json, e.g. {"action": "route", "destinations": [0, 2]}
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Decision {
+    Forward,
+    Drop,
+    Modify {
+        title: Option<String>,
+        content: Option<String>,
+    },
+    // indices into the configured destinations
+    Route {
+        destinations: Vec<usize>,
+    },
+}
+
+fn out_of_range() -> Error {
+    Error::Plugin("value out of range".to_owned())
+}
+
+fn call_failed(err: wasmtime::Error) -> Error {
+    if err.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) {
+        return Error::Plugin("ran out of fuel".to_owned());
+    }
+    err.into()
+}
+
+// wasm modules exporting memory, alloc(len) -> ptr and filter(ptr, len) -> (ptr << 32 | len)
+pub struct Plugins {
+    engine: Engine,
+    modules: Vec<Module>,
+    fuel: u64,
+}
+
+impl Plugins {
+    // a call running out of fuel fails instead of blocking the pipeline
+    pub fn load(paths: &[PathBuf], fuel: u64) -> Result<Self> {
+        let engine = Engine::new(Config::new().consume_fuel(true))?;
+        let modules = paths
+            .iter()
+            .map(|path| Ok(Module::from_file(&engine, path)?))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            engine,
+            modules,
+            fuel,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    // each call gets a fresh instance so plugins can't keep state between posts
+    fn call(&self, module: &Module, input: &[u8]) -> Result<Decision> {
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(self.fuel)?;
+        let instance = Instance::new(&mut store, module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| Error::Plugin("no exported memory".to_owned()))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let filter = instance.get_typed_func::<(i32, i32), i64>(&mut store, "filter")?;
+
+        let input_len = i32::try_from(input.len()).map_err(|_| out_of_range())?;
+        let input_ptr = alloc.call(&mut store, input_len).map_err(call_failed)?;
+        let input_offset = usize::try_from(input_ptr).map_err(|_| out_of_range())?;
+        memory
+            .write(&mut store, input_offset, input)
+            .map_err(|_| out_of_range())?;
+
+        let packed = filter
+            .call(&mut store, (input_ptr, input_len))
+            .map_err(call_failed)?;
+        let packed = u64::from_ne_bytes(packed.to_ne_bytes());
+        let output_offset = usize::try_from(packed >> 32).map_err(|_| out_of_range())?;
+        let output_len = usize::try_from(packed & 0xffff_ffff).map_err(|_| out_of_range())?;
+        // checked before allocating, the length could be anything the plugin returns
+        if output_offset
+            .checked_add(output_len)
+            .is_none_or(|end| end > memory.data_size(&store))
+        {
+            return Err(out_of_range());
+        }
+        let mut output = vec![0; output_len];
+        memory
+            .read(&store, output_offset, &mut output)
+            .map_err(|_| out_of_range())?;
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    pub fn decide(&self, index: usize, post: &impl Serialize) -> Result<Decision> {
+        self.call(&self.modules[index], &serde_json::to_vec(post)?)
+    }
+}
@@ -0,0 +1,55 @@
+// the fields of a post following the thread template, e.g.
+// Tuote: Näytönohjain
+// Hinta: 499 €
+// Linkki: https://kauppa.example/tuote
+// the product is already the title of the post
+pub struct DealFields<'a> {
+    pub price: Option<&'a str>,
+    pub link: Option<&'a str>,
+    // the lines that aren't part of the template
+    pub rest: String,
+}
+
+// the value of a "Label: value" line, ignoring case and markdown emphasis
+pub fn labeled<'a>(line: &'a str, label: &str) -> Option<&'a str> {
+    let line = line.trim().trim_start_matches('*');
+    let (name, value) = line.split_once(':')?;
+    if !name.trim_end_matches('*').eq_ignore_ascii_case(label) {
+        return None;
+    }
+    Some(value.trim().trim_start_matches('*').trim()).filter(|value| !value.is_empty())
+}
+
+fn link(line: &str) -> Option<&str> {
+    let line = line.trim();
+    (line.starts_with("https://") || line.starts_with("http://")).then_some(line)
+}
+
+// None when the post doesn't follow the template
+pub fn parse(content: &str) -> Option<DealFields<'_>> {
+    let mut product = None;
+    let mut price = None;
+    let mut link_line = None;
+    let mut rest = Vec::new();
+    for line in content.lines() {
+        if let (None, Some(value)) = (product, labeled(line, "Tuote")) {
+            product = Some(value);
+        } else if let (None, Some(value)) = (price, labeled(line, "Hinta")) {
+            price = Some(value);
+        } else if let (None, Some(value)) =
+            (link_line, labeled(line, "Linkki").or_else(|| link(line)))
+        {
+            link_line = Some(value);
+        } else {
+            rest.push(line);
+        }
+    }
+    if product.is_none() || price.is_none() && link_line.is_none() {
+        return None;
+    }
+    Some(DealFields {
+        price,
+        link: link_line,
+        rest: rest.join("\n").trim().to_owned(),
+    })
+}
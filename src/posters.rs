@@ -0,0 +1,35 @@
+use serde::Deserialize;
+
+// usernames are compared case insensitively
+#[derive(Deserialize, Default)]
+pub struct PosterConfig {
+    // posts by these are never sent
+    #[serde(default)]
+    pub ignored: Vec<String>,
+    // posts by these stand out with their own color and footer
+    #[serde(default)]
+    pub highlighted: Vec<String>,
+    pub highlight_color: Option<i32>,
+}
+
+// gold
+const DEFAULT_HIGHLIGHT_COLOR: i32 = 0x00f1_c40f;
+
+fn contains(usernames: &[String], username: &str) -> bool {
+    let username = username.to_lowercase();
+    usernames
+        .iter()
+        .any(|candidate| candidate.to_lowercase() == username)
+}
+
+impl PosterConfig {
+    pub fn is_ignored(&self, username: &str) -> bool {
+        contains(&self.ignored, username)
+    }
+
+    // the color of the highlighted posts by the poster
+    pub fn highlight(&self, username: &str) -> Option<i32> {
+        contains(&self.highlighted, username)
+            .then(|| self.highlight_color.unwrap_or(DEFAULT_HIGHLIGHT_COLOR))
+    }
+}
@@ -0,0 +1,115 @@
+use serde::Deserialize;
+
+use crate::category::Category;
+
+// a destination only gets the posts passing all of its rules
+#[derive(Deserialize)]
+pub struct PriceRule {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    // posts without a parsed price don't pass
+    #[serde(default)]
+    pub require_price: bool,
+    // posts of other categories pass regardless of the price
+    pub category: Option<String>,
+}
+
+impl PriceRule {
+    pub fn allows(&self, price: Option<f64>, category: Option<&Category>) -> bool {
+        if let Some(name) = &self.category {
+            if category.is_none_or(|category| &category.name != name) {
+                return true;
+            }
+        }
+        match price {
+            Some(price) => {
+                self.min.is_none_or(|min| price >= min) && self.max.is_none_or(|max| price <= max)
+            }
+            None => !self.require_price,
+        }
+    }
+}
+
+// parses the price from the "Hinta:" line of the post template, e.g. "149,90 €",
+// "149.9e" or "1 299,-"
+pub fn parse_price(content: &str) -> Option<f64> {
+    let line = content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Hinta:"))?;
+    let number: String = line
+        .trim()
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit() || *c == ',' || *c == '.' || *c == ' ')
+        .filter(|c| *c != ' ')
+        .collect();
+    let number = number.trim_end_matches([',', '.']);
+    // the last separator is the decimal one when followed by at most two digits,
+    // the others separate thousands as in "1.299,90"
+    let normalized = match number.rfind([',', '.']) {
+        Some(index) if number.len() - index - 1 <= 2 => format!(
+            "{}.{}",
+            number[..index].replace([',', '.'], ""),
+            &number[index + 1..]
+        ),
+        _ => number.replace([',', '.'], ""),
+    };
+    normalized.parse().ok()
+}
+
+pub fn format_price(price: f64) -> String {
+    if price.fract() == 0.0 {
+        format!("{price:.0}€")
+    } else {
+        format!("{price:.2}€").replace('.', ",")
+    }
+}
+
+// relative to the typical price, 0.3 is 30 % off
+pub fn discount(price: Option<f64>, typical_price: Option<f64>) -> Option<f64> {
+    match (price, typical_price) {
+        (Some(price), Some(typical_price)) if typical_price > 0.0 => {
+            Some((typical_price - price) / typical_price)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_template_prices() {
+        assert_eq!(parse_price("Tuote: Näyttö\nHinta: 149,90 €"), Some(149.9));
+        assert_eq!(parse_price("Hinta: 149.9e"), Some(149.9));
+        assert_eq!(parse_price("Hinta: 1 299,-"), Some(1299.0));
+        assert_eq!(parse_price("  Hinta: alk. 25€ "), Some(25.0));
+    }
+
+    #[test]
+    fn separates_thousands_from_decimals() {
+        assert_eq!(parse_price("Hinta: 1.299,90 €"), Some(1299.9));
+        assert_eq!(parse_price("Hinta: 1,299.90 €"), Some(1299.9));
+        assert_eq!(parse_price("Hinta: 1.299 €"), Some(1299.0));
+    }
+
+    #[test]
+    fn needs_a_price_line() {
+        assert_eq!(parse_price("Hyvä tarjous 149,90 €"), None);
+        assert_eq!(parse_price("Hinta: ilmainen"), None);
+    }
+
+    #[test]
+    fn formats_whole_and_decimal_prices() {
+        assert_eq!(format_price(25.0), "25€");
+        assert_eq!(format_price(149.9), "149,90€");
+    }
+
+    #[test]
+    fn computes_discounts() {
+        assert_eq!(discount(Some(75.0), Some(100.0)), Some(0.25));
+        assert_eq!(discount(Some(75.0), Some(0.0)), None);
+        assert_eq!(discount(None, Some(100.0)), None);
+    }
+}
@@ -0,0 +1,59 @@
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::price;
+
+#[derive(Deserialize)]
+pub struct PriceComparisonConfig {
+    // search endpoint, the product name is appended as a query parameter
+    pub url: String,
+    #[serde(default = "default_query_parameter")]
+    pub query_parameter: String,
+    // JSON pointer to the typical price in the response
+    #[serde(default = "default_price_pointer")]
+    pub price_pointer: String,
+}
+
+fn default_query_parameter() -> String {
+    "q".to_owned()
+}
+
+fn default_price_pointer() -> String {
+    "/price".to_owned()
+}
+
+fn parse_price_value(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(s) => s
+            .trim()
+            .trim_end_matches('€')
+            .trim()
+            .replace(',', ".")
+            .parse()
+            .ok(),
+        _ => None,
+    }
+}
+
+pub fn typical_price(
+    client: &Client,
+    config: &PriceComparisonConfig,
+    product: &str,
+) -> Result<Option<f64>> {
+    let mut url = Url::parse(&config.url).or(Err(Error::Scraping))?;
+    url.query_pairs_mut()
+        .append_pair(&config.query_parameter, product);
+
+    let response: Value = client.get(url).send()?.error_for_status()?.json()?;
+    Ok(response
+        .pointer(&config.price_pointer)
+        .and_then(parse_price_value))
+}
+
+pub fn format_typical_price(price: f64) -> String {
+    format!("~{}", price::format_price(price))
+}
@@ -0,0 +1,118 @@
+use reqwest::blocking::Client;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+#[derive(Deserialize)]
+pub struct ProductDatabaseConfig {
+    // lookup endpoint, the EAN is appended as a query parameter
+    pub url: String,
+    #[serde(default = "default_query_parameter")]
+    pub query_parameter: String,
+    // JSON pointer to the canonical product name in the response
+    #[serde(default = "default_name_pointer")]
+    pub name_pointer: String,
+}
+
+fn default_query_parameter() -> String {
+    "ean".to_owned()
+}
+
+fn default_name_pointer() -> String {
+    "/name".to_owned()
+}
+
+// units that make e.g. "16GB" or "144Hz" look like model numbers
+static UNIT_SUFFIXES: &[&str] = &[
+    "gb", "tb", "mb", "hz", "khz", "mhz", "ghz", "w", "mm", "cm", "m", "l", "kg", "g", "v", "mah",
+    "k", "p", "x", "e", "€", "gbps", "mbps", "rpm", "ms", "cl", "ml", "pcs", "kpl", "fps",
+];
+
+fn is_valid_ean(digits: &str) -> bool {
+    if !(digits.len() == 8 || digits.len() == 13) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let mut values = digits.bytes().rev().map(|b| u32::from(b - b'0'));
+    let check = values.next().unwrap_or(0);
+    let sum: u32 = values
+        .enumerate()
+        .map(|(i, value)| if i % 2 == 0 { value * 3 } else { value })
+        .sum();
+    (10 - sum % 10) % 10 == check
+}
+
+pub fn extract_ean(text: &str) -> Option<&str> {
+    text.split(|c: char| !c.is_ascii_digit())
+        .find(|digits| is_valid_ean(digits))
+}
+
+fn is_model_number(token: &str) -> bool {
+    let has_letter = token.chars().any(|c| c.is_ascii_alphabetic());
+    let has_digit = token.chars().any(|c| c.is_ascii_digit());
+    if token.len() < 4 || !has_letter || !has_digit {
+        return false;
+    }
+    let lowercase = token.to_lowercase();
+    let unit = lowercase.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == ',');
+    !UNIT_SUFFIXES.contains(&unit)
+}
+
+pub fn extract_model(title: &str) -> Option<String> {
+    title
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .map(|token| token.trim_matches('-'))
+        .find(|token| is_model_number(token))
+        .map(|token| token.replace('-', "").to_uppercase())
+}
+
+// e.g. "599€", "49,90e" or "129.-"
+fn is_price(token: &str) -> bool {
+    let number = token.trim_end_matches(['€', 'e', '-', ',', '.']);
+    token == "€"
+        || (number.len() < token.len()
+            && number.starts_with(|c: char| c.is_ascii_digit())
+            && number
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ',' || c == '.'))
+}
+
+// the price is left out so that the same product is recognized at another price
+fn normalize_title(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split_whitespace()
+        .filter(|token| !is_price(token))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// the identity used to recognize the same product across posts
+pub fn identity_key(title: &str, links: &[String]) -> String {
+    if let Some(ean) =
+        extract_ean(title).or_else(|| links.iter().find_map(|link| extract_ean(link)))
+    {
+        return format!("ean:{ean}");
+    }
+    if let Some(model) = extract_model(title) {
+        return format!("model:{model}");
+    }
+    format!("title:{}", normalize_title(title))
+}
+
+pub fn canonical_name(
+    client: &Client,
+    config: &ProductDatabaseConfig,
+    ean: &str,
+) -> Result<Option<String>> {
+    let mut url = Url::parse(&config.url).or(Err(Error::Scraping))?;
+    url.query_pairs_mut()
+        .append_pair(&config.query_parameter, ean);
+
+    let response: Value = client.get(url).send()?.error_for_status()?.json()?;
+    Ok(response
+        .pointer(&config.name_pointer)
+        .and_then(Value::as_str)
+        .map(str::to_owned))
+}
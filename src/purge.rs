@@ -0,0 +1,116 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use chrono::NaiveDate;
+
+use crate::archive::{parse_timestamp, Archive};
+use crate::avatar::AvatarCache;
+use crate::config::{Config, Target};
+use crate::error::{Error, Result};
+use crate::feed;
+use crate::outbox::Outbox;
+use crate::throttle::Throttler;
+
+// midnight utc starting the day
+fn parse_date(date: &str) -> Result<i64> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| Error::Usage)?;
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .ok_or(Error::Usage)?
+        .and_utc()
+        .timestamp())
+}
+
+// matched like the archive purge: usernames in any case, and posts without a known
+// author or date can't be shown to be someone else's or newer, so they are purged too
+struct Conditions<'a> {
+    author: Option<&'a str>,
+    before: Option<i64>,
+}
+
+impl Conditions<'_> {
+    fn matches(&self, author: Option<&str>, posted_at: Option<i64>) -> bool {
+        self.author
+            .is_none_or(|purged| author.is_none_or(|author| author.eq_ignore_ascii_case(purged)))
+            && self
+                .before
+                .is_none_or(|before| posted_at.is_none_or(|posted_at| posted_at < before))
+    }
+}
+
+// the queued posts and feeds of every pipeline, and the posts held back for digests
+fn purge_files(config: &Config, state_directory: &Path, conditions: &Conditions) -> Result<()> {
+    let pipelines = config.pipelines(state_directory);
+    let mut queued = 0;
+    for pipeline in &pipelines {
+        let mut outbox = Outbox::load(&pipeline.state_directory)?;
+        let purged = outbox.purge(|post| {
+            conditions.matches(Some(&post.username), parse_timestamp(&post.timestamp))
+        });
+        if purged > 0 {
+            outbox.save()?;
+            queued += purged;
+        }
+    }
+    println!("Purged {queued} queued posts");
+
+    let held_back = Throttler::load(state_directory)?
+        .purge(|entry| conditions.matches(entry.author.as_deref(), entry.posted_at))?;
+    println!("Purged {held_back} posts held back for digests");
+
+    let feed_paths = pipelines
+        .iter()
+        .flat_map(|pipeline| pipeline.destinations)
+        .filter_map(|destination| match &destination.target {
+            Target::Feed { feed_path, .. } => Some(feed_path),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    for feed_path in feed_paths {
+        let purged = feed::purge(&config.thread_url, feed_path, |author, published| {
+            conditions.matches(author, Some(published))
+        })?;
+        println!("Purged {purged} items from {}", feed_path.display());
+    }
+    Ok(())
+}
+
+// removes posts by an author and/or before a date, for deletion requests
+pub fn run(
+    config_path: &Path,
+    state_directory: &Path,
+    author: Option<&str>,
+    before: Option<&str>,
+) -> Result<()> {
+    let before = before.map(parse_date).transpose()?;
+    if author.is_none() && before.is_none() {
+        return Err(Error::Usage);
+    }
+
+    let purged = Archive::open(state_directory)?.purge(author, before)?;
+    println!("Purged {purged} posts");
+    let config = Config::load(config_path, state_directory)?;
+    purge_files(&config, state_directory, &Conditions { author, before })?;
+    if author.is_some() {
+        AvatarCache::new(state_directory).clear()?;
+        println!("Cleared the avatar cache");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_like_the_archive() {
+        let conditions = Conditions {
+            author: Some("Kirjoittaja"),
+            before: Some(1_700_000_000),
+        };
+        assert!(conditions.matches(Some("kirjoittaja"), Some(1_600_000_000)));
+        assert!(conditions.matches(None, None));
+        assert!(!conditions.matches(Some("toinen"), Some(1_600_000_000)));
+        assert!(!conditions.matches(Some("kirjoittaja"), Some(1_800_000_000)));
+    }
+}
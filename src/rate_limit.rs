@@ -0,0 +1,116 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tracing::warn;
+
+#[derive(Deserialize)]
+pub struct RateLimitConfig {
+    // sustained messages per second to a single webhook, channel or command
+    #[serde(default = "default_per_second")]
+    pub per_second: f64,
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    // limits all destinations together when given
+    pub global_per_second: Option<f64>,
+    #[serde(default = "default_burst")]
+    pub global_burst: u32,
+}
+
+// discord allows 5 webhook requests per 2 seconds
+fn default_per_second() -> f64 {
+    2.0
+}
+
+fn default_burst() -> u32 {
+    5
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_second: default_per_second(),
+            burst: default_burst(),
+            global_per_second: None,
+            global_burst: default_burst(),
+        }
+    }
+}
+
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_second: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        Self {
+            capacity,
+            tokens: capacity,
+            per_second,
+            updated: Instant::now(),
+        }
+    }
+
+    // takes a token, going into debt if there are none, and returns how long to wait
+    fn take(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.updated = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 || self.per_second <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_second)
+        }
+    }
+}
+
+// shared by everything sending messages, so the limits hold across routing rules
+pub struct RateLimiter {
+    per_second: f64,
+    burst: u32,
+    global: RefCell<Option<TokenBucket>>,
+    destinations: RefCell<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            per_second: config.per_second,
+            burst: config.burst,
+            global: RefCell::new(
+                config
+                    .global_per_second
+                    .map(|per_second| TokenBucket::new(per_second, config.global_burst)),
+            ),
+            destinations: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // blocks until a message may be sent to the destination
+    pub fn acquire(&self, destination: &str) {
+        let global_wait = self
+            .global
+            .borrow_mut()
+            .as_mut()
+            .map_or(Duration::ZERO, TokenBucket::take);
+        let destination_wait = self
+            .destinations
+            .borrow_mut()
+            .entry(destination.to_owned())
+            .or_insert_with(|| TokenBucket::new(self.per_second, self.burst))
+            .take();
+        let wait = global_wait.max(destination_wait);
+        if !wait.is_zero() {
+            warn!("Rate limited, waiting {} ms", wait.as_millis());
+            thread::sleep(wait);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::archive::SentMessage;
+use crate::error::Result;
+use crate::pin::{
+    channel_bot, check_messages, days_ago, default_check_interval, default_days, is_due,
+};
+use crate::Context;
+
+// discord allows adding about four reactions per second
+const REACTION_DELAY: Duration = Duration::from_millis(250);
+
+// reactions only work with bot destinations
+#[derive(Deserialize)]
+pub struct ReactionsConfig {
+    // unicode emojis or name:id of custom ones, added to every sent deal
+    #[serde(default)]
+    pub emojis: Vec<String>,
+    // stores the reaction counts in the archive, where the leaderboard reads them from
+    #[serde(default)]
+    pub record: bool,
+    // reactions of messages are recorded for this many days after sending
+    #[serde(default = "default_days::<7>")]
+    pub days: u32,
+    // seconds between recording the reactions
+    #[serde(default = "default_check_interval::<{ 60 * 60 }>")]
+    pub check_interval: u64,
+}
+
+// adds the configured reactions for people to vote with
+pub fn seed(ctx: &Context, message: &SentMessage) -> Result<()> {
+    let Some(config) = &ctx.config.reactions else {
+        return Ok(());
+    };
+    let Some(bot) = channel_bot(ctx, &message.channel_id) else {
+        return Ok(());
+    };
+    for (index, emoji) in config.emojis.iter().enumerate() {
+        if index > 0 {
+            thread::sleep(REACTION_DELAY);
+        }
+        bot.create_reaction(&message.channel_id, &message.message_id, emoji)?
+            .error_for_status()?;
+    }
+    Ok(())
+}
+
+// copies the reaction counts of recent messages to the archive
+pub fn record(ctx: &Context, checked: &mut Option<Instant>) -> Result<()> {
+    let Some(config) = ctx.config.reactions.as_ref().filter(|config| config.record) else {
+        return Ok(());
+    };
+    if !is_due(checked, config.check_interval) {
+        return Ok(());
+    }
+
+    let messages = ctx.archive.recent_messages(days_ago(config.days))?;
+    check_messages(ctx, &messages, |_, message, received| {
+        ctx.archive
+            .set_reactions(message.post_id, received.reaction_count())
+    });
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::{RequestBuilder, Response};
+use serde::Deserialize;
+use tracing::warn;
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct RetryConfig {
+    // including the first one
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    // doubled after every attempt, up to max_delay_ms
+    #[serde(default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_initial_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    30_000
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_delay_ms: default_initial_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+        }
+    }
+}
+
+// connection resets and timeouts, errors with a response are handled by status
+fn is_transient(err: &reqwest::Error) -> bool {
+    err.is_connect() || err.is_timeout() || err.is_request() || err.is_body()
+}
+
+impl RetryConfig {
+    // between half and the whole exponential delay, so that clients don't retry in step
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay_ms
+            .saturating_mul(1 << attempt.min(16))
+            .min(self.max_delay_ms);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| u64::from(now.subsec_nanos()));
+        Duration::from_millis(delay / 2 + nanos % (delay / 2 + 1))
+    }
+
+    // builds and sends the request again after transient errors and 5xx responses
+    pub fn send(&self, request: impl Fn() -> RequestBuilder) -> reqwest::Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let result = request().send();
+            let failure = match &result {
+                Ok(response) if response.status().is_server_error() => {
+                    format!("status {}", response.status())
+                }
+                // not the error itself, its url may contain a token
+                Err(err) if is_transient(err) => {
+                    if err.is_timeout() {
+                        "a timeout".to_owned()
+                    } else if err.is_connect() {
+                        "a connection error".to_owned()
+                    } else {
+                        "a request error".to_owned()
+                    }
+                }
+                _ => return result,
+            };
+            if attempt >= self.max_attempts {
+                return result;
+            }
+            let delay = self.delay(attempt - 1);
+            warn!(
+                "Request failed with {failure}, retrying in {} ms",
+                delay.as_millis()
+            );
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::convert::TryFrom;
+
+use regex::Regex;
+use serde::Deserialize;
+
+// the pattern is compiled when the config is loaded, so that an invalid one fails it
+#[derive(Deserialize, Clone)]
+#[serde(try_from = "RawRewriteRule")]
+pub struct RewriteRule {
+    pattern: Regex,
+    replacement: String,
+}
+
+#[derive(Deserialize)]
+struct RawRewriteRule {
+    pattern: String,
+    // may refer to capture groups, e.g. $1 or ${name}
+    #[serde(default)]
+    replacement: String,
+}
+
+impl TryFrom<RawRewriteRule> for RewriteRule {
+    type Error = String;
+
+    fn try_from(rule: RawRewriteRule) -> Result<Self, Self::Error> {
+        let pattern = Regex::new(&rule.pattern)
+            .map_err(|err| format!("invalid rewrite pattern {}: {err}", rule.pattern))?;
+        Ok(Self {
+            pattern,
+            replacement: rule.replacement,
+        })
+    }
+}
+
+// regex substitutions applied to the content of every post in order
+pub struct Rewrites {
+    rules: Vec<RewriteRule>,
+}
+
+impl Rewrites {
+    pub fn new(rules: &[RewriteRule]) -> Self {
+        Self {
+            rules: rules.to_vec(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn apply(&self, content: &str) -> String {
+        self.rules.iter().fold(content.to_owned(), |content, rule| {
+            rule.pattern
+                .replace_all(&content, rule.replacement.as_str())
+                .into_owned()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(rules: &[(&str, &str)]) -> Rewrites {
+        let rules: Vec<RewriteRule> = rules
+            .iter()
+            .map(|&(pattern, replacement)| {
+                RewriteRule::try_from(RawRewriteRule {
+                    pattern: pattern.to_owned(),
+                    replacement: replacement.to_owned(),
+                })
+                .unwrap()
+            })
+            .collect();
+        Rewrites::new(&rules)
+    }
+
+    #[test]
+    fn applies_rules_in_order() {
+        let rewrites = build(&[("foo", "bar"), ("bar", "baz")]);
+        assert_eq!(rewrites.apply("foo bar"), "baz baz");
+        let reversed = build(&[("bar", "baz"), ("foo", "bar")]);
+        assert_eq!(reversed.apply("foo bar"), "bar baz");
+    }
+
+    #[test]
+    fn replaces_capture_groups() {
+        let rewrites = build(&[
+            (r"(\d+),(\d+) ?€", "$1.$2 €"),
+            (r"(?P<store>\w+)\.fi", "${store}"),
+        ]);
+        assert_eq!(
+            rewrites.apply("Hinta 12,90€ kaupasta verkkokauppa.fi"),
+            "Hinta 12.90 € kaupasta verkkokauppa"
+        );
+    }
+
+    #[test]
+    fn keeps_content_without_matches() {
+        let rewrites = build(&[("ei löydy", "")]);
+        assert_eq!(rewrites.apply("Hyvä tarjous"), "Hyvä tarjous");
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let config: Result<Vec<RewriteRule>, _> =
+            serde_json::from_str(r#"[{"pattern": "(unclosed"}]"#);
+        assert!(config.is_err());
+    }
+}
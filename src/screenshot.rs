@@ -0,0 +1,33 @@
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::read_image;
+
+pub static SCREENSHOT_FILENAME: &str = "screenshot.png";
+pub static SCREENSHOT_DESCRIPTION: &str = "Kuvakaappaus kaupan sivusta";
+
+#[derive(Deserialize)]
+pub struct ScreenshotConfig {
+    // browserless style endpoint taking {"url": ...} and returning a png
+    pub url: String,
+}
+
+#[derive(Serialize)]
+struct ScreenshotRequest<'a> {
+    url: &'a str,
+}
+
+// none when the screenshot is over the image size limit
+pub fn capture(
+    client: &Client,
+    config: &ScreenshotConfig,
+    page_url: &str,
+) -> Result<Option<Vec<u8>>> {
+    let response = client
+        .post(&config.url)
+        .json(&ScreenshotRequest { url: page_url })
+        .send()?
+        .error_for_status()?;
+    read_image(response)
+}
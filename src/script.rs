@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use crate::error::{Error, Result};
+use crate::plugin::Decision;
+
+// a script looping forever fails on these instead of blocking the pipeline
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+// characters, long enough for the content of any post
+const MAX_STRING_SIZE: usize = 1_000_000;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+
+// rhai scripts see title, content, price and author; changes to title and content
+// are kept, and the script may return "drop", "forward" or a decision map
+pub struct Scripts {
+    engine: Engine,
+    scripts: Vec<AST>,
+}
+
+fn returned_decision(value: Dynamic) -> Result<Option<Decision>> {
+    if value.is_unit() {
+        return Ok(None);
+    }
+    if value.is_string() {
+        return match value.into_string().unwrap_or_default().as_str() {
+            "forward" => Ok(Some(Decision::Forward)),
+            "drop" => Ok(Some(Decision::Drop)),
+            other => Err(Error::Script(format!("unknown decision {other:?}"))),
+        };
+    }
+    Ok(Some(rhai::serde::from_dynamic(&value)?))
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_string_size(MAX_STRING_SIZE)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE);
+    engine
+}
+
+impl Scripts {
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let engine = engine();
+        let scripts = paths
+            .iter()
+            .map(|path| Ok(engine.compile_file(path.clone())?))
+            .collect::<Result<_>>()?;
+        Ok(Self { engine, scripts })
+    }
+
+    pub fn len(&self) -> usize {
+        self.scripts.len()
+    }
+
+    pub fn decide(
+        &self,
+        index: usize,
+        title: &str,
+        content: &str,
+        price: Option<f64>,
+        author: &str,
+    ) -> Result<Vec<Decision>> {
+        let mut scope = Scope::new();
+        scope.push("title", title.to_owned());
+        scope.push("content", content.to_owned());
+        scope.push("price", price.map_or(Dynamic::UNIT, Dynamic::from));
+        scope.push_constant("author", author.to_owned());
+
+        let value = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.scripts[index])
+            .map_err(|err| match *err {
+                EvalAltResult::ErrorTooManyOperations(_) => {
+                    Error::Script(format!("script {index} ran too long"))
+                }
+                _ => err.into(),
+            })?;
+
+        let new_title = scope
+            .get_value::<String>("title")
+            .filter(|new_title| new_title != title);
+        let new_content = scope
+            .get_value::<String>("content")
+            .filter(|new_content| new_content != content);
+        let mut decisions = Vec::new();
+        if new_title.is_some() || new_content.is_some() {
+            decisions.push(Decision::Modify {
+                title: new_title,
+                content: new_content,
+            });
+        }
+        decisions.extend(returned_decision(value)?);
+        Ok(decisions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scripts(source: &str) -> Scripts {
+        let engine = engine();
+        let scripts = vec![engine.compile(source).unwrap()];
+        Scripts { engine, scripts }
+    }
+
+    fn decide(scripts: &Scripts) -> Result<Vec<Decision>> {
+        scripts.decide(0, "RTX 4070", "hinta 549 €", Some(549.0), "user")
+    }
+
+    #[test]
+    fn fails_on_runtime_errors() {
+        assert!(decide(&scripts("title.len() / 0")).is_err());
+    }
+
+    #[test]
+    fn fails_on_unknown_decisions() {
+        assert!(decide(&scripts(r#""maybe""#)).is_err());
+    }
+
+    #[test]
+    fn fails_on_endless_loops() {
+        let result = decide(&scripts("loop {}"));
+        assert!(matches!(result, Err(err) if err.to_string().contains("ran too long")));
+    }
+
+    #[test]
+    fn keeps_working_after_a_failure() {
+        let scripts = scripts(r#"if price > 500.0 { throw "liian kallis" } "forward""#);
+        assert!(decide(&scripts).is_err());
+        let decisions = scripts
+            .decide(0, "RTX 4060", "hinta 299 €", Some(299.0), "user")
+            .unwrap();
+        assert!(matches!(decisions[..], [Decision::Forward]));
+    }
+}
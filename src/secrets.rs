@@ -0,0 +1,50 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::error::{Error, Result};
+
+const CREDENTIAL_PREFIX: &str = "credential:";
+
+// directory populated by systemd LoadCredential=
+fn credentials_directory() -> Option<PathBuf> {
+    env::var_os("CREDENTIALS_DIRECTORY").map(PathBuf::from)
+}
+
+pub fn read_credential(name: &str) -> Result<Option<String>> {
+    let Some(directory) = credentials_directory() else {
+        return Ok(None);
+    };
+    let path = directory.join(name);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read_to_string(path)?.trim_end().to_owned()))
+}
+
+// values like "credential:webhook_url" are replaced with the credential contents
+pub fn resolve(value: &mut String) -> Result<()> {
+    if let Some(name) = value.strip_prefix(CREDENTIAL_PREFIX) {
+        *value = read_credential(name)?
+            .ok_or_else(|| Error::InvalidConfig(format!("missing credential {name}")))?;
+    }
+    Ok(())
+}
+
+// runs the command through the shell and returns its first output line,
+// e.g. "pass show tarjousbot/webhook" or "secret-tool lookup service tarjousbot"
+pub fn run_command(command: &str) -> Result<String> {
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+    if !output.status.success() {
+        return Err(Error::InvalidConfig(format!(
+            "secret command {command:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned())
+}
@@ -0,0 +1,144 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use tarjousbot::forum::FORUM_URL;
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::Context;
+
+const COOKIE_FILE: &str = "cookies.txt";
+
+// some threads and the search are only visible to members
+#[derive(Deserialize)]
+pub struct LoginConfig {
+    pub username: Option<String>,
+    // this and the cookie may refer to credentials like the destination secrets
+    pub password: Option<String>,
+    // e.g. "xf_user=...; xf_session=..." copied from a browser, used until it expires
+    pub cookie: Option<String>,
+}
+
+fn forum_url() -> Url {
+    Url::parse(FORUM_URL).unwrap()
+}
+
+fn add_cookies(jar: &Jar, cookies: &str) {
+    let url = forum_url();
+    for cookie in cookies
+        .split(';')
+        .map(str::trim)
+        .filter(|cookie| !cookie.is_empty())
+    {
+        jar.add_cookie_str(cookie, &url);
+    }
+}
+
+// the cookies of the previous session, or the configured ones on the first start
+pub fn load_cookies(state_directory: &Path, config: &LoginConfig) -> Result<Jar> {
+    let jar = Jar::default();
+    match fs::read_to_string(state_directory.join(COOKIE_FILE)) {
+        Ok(cookies) => add_cookies(&jar, &cookies),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            if let Some(cookies) = &config.cookie {
+                add_cookies(&jar, cookies);
+            }
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(jar)
+}
+
+// only readable by the owner, and written to a temporary file first so that a crash
+// never leaves a half written file replacing the configured cookie
+fn save_cookies(state_directory: &Path, jar: &Jar) -> Result<()> {
+    let Some(cookies) = jar.cookies(&forum_url()) else {
+        return Ok(());
+    };
+    let cookies = cookies.to_str().or(Err(Error::Scraping))?;
+    let temporary = state_directory.join(format!("{COOKIE_FILE}.tmp"));
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&temporary)?;
+    // the mode only applies to a newly created file
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(cookies.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(temporary, state_directory.join(COOKIE_FILE))?;
+    Ok(())
+}
+
+fn is_logged_in(body: &str) -> bool {
+    body.contains("data-logged-in=\"true\"")
+}
+
+fn get_page(ctx: &Context, url: &str) -> Result<String> {
+    Ok(ctx
+        .config
+        .retry
+        .send(|| ctx.forum_client.get(url))?
+        .error_for_status()?
+        .text()?)
+}
+
+// the login form is protected by a token of the session
+fn login(ctx: &Context, username: &str, password: &str) -> Result<()> {
+    info!("Log in to the forum as {username}");
+    let body = get_page(ctx, &format!("{FORUM_URL}/login/"))?;
+    let selector = Selector::parse("input[name=\"_xfToken\"]").unwrap();
+    let token = Html::parse_document(&body)
+        .select(&selector)
+        .find_map(|element| element.value().attr("value").map(str::to_owned))
+        .ok_or(Error::Scraping)?;
+    let login_url = format!("{FORUM_URL}/login/login");
+    let form = [
+        ("login", username),
+        ("password", password),
+        ("remember", "1"),
+        ("_xfToken", token.as_str()),
+    ];
+    ctx.config
+        .retry
+        .send(|| ctx.forum_client.post(&login_url).form(&form))?
+        .error_for_status()?;
+    Ok(())
+}
+
+// logs in again when the session has expired, the cookies are saved either way
+// as the forum renews them
+pub fn ensure_logged_in(ctx: &Context) -> Result<()> {
+    let (Some(config), Some(jar)) = (&ctx.config.login, ctx.cookies) else {
+        return Ok(());
+    };
+    if !is_logged_in(&get_page(ctx, FORUM_URL)?) {
+        warn!("Not logged in to the forum");
+        match (&config.username, &config.password) {
+            (Some(username), Some(password)) => login(ctx, username, password)?,
+            // the configured cookie may have been replaced since it was saved
+            _ => {
+                if let Some(cookies) = &config.cookie {
+                    add_cookies(jar, cookies);
+                }
+            }
+        }
+        if !is_logged_in(&get_page(ctx, FORUM_URL)?) {
+            return Err(Error::Login(
+                "check the username and password or the cookie".to_owned(),
+            ));
+        }
+    }
+    if ctx.dry_run {
+        return Ok(());
+    }
+    save_cookies(&ctx.state_directory, jar)
+}
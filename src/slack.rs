@@ -0,0 +1,95 @@
+use chrono::DateTime;
+use serde_json::{json, Value};
+
+use crate::error::Result;
+use crate::webhook::{send_retrying, Embed};
+use crate::{truncate, Context, Message};
+
+// slack rejects longer section texts
+const MAX_SECTION_LENGTH: usize = 3000;
+const MAX_FIELDS: usize = 10;
+
+// only these have a special meaning in mrkdwn text
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn mrkdwn(text: &str) -> Value {
+    json!({ "type": "mrkdwn", "text": truncate(text, MAX_SECTION_LENGTH) })
+}
+
+// shown in the reader's own timezone, falling back to the timestamp as is
+fn format_timestamp(timestamp: &str) -> String {
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(time) => format!(
+            "<!date^{}^{{date_short_pretty}} {{time}}|{timestamp}>",
+            time.timestamp()
+        ),
+        Err(_) => escape(timestamp),
+    }
+}
+
+// the embed as block kit blocks, the text is used for notifications
+pub fn format_message(embed: &Embed, content: Option<&str>) -> Value {
+    let title = embed.title.as_deref().unwrap_or_default();
+    let mut blocks = Vec::new();
+    if let Some(content) = content {
+        blocks.push(json!({ "type": "section", "text": mrkdwn(&escape(content)) }));
+    }
+    let heading = match embed.url.as_deref() {
+        Some(url) => format!("*<{url}|{}>*", escape(title)),
+        None => format!("*{}*", escape(title)),
+    };
+    blocks.push(json!({ "type": "section", "text": mrkdwn(&heading) }));
+    if let Some(description) = embed.description.as_deref() {
+        blocks.push(json!({ "type": "section", "text": mrkdwn(&escape(description)) }));
+    }
+    let fields: Vec<Value> = embed
+        .fields
+        .iter()
+        .take(MAX_FIELDS)
+        .map(|field| {
+            mrkdwn(&format!(
+                "*{}*\n{}",
+                escape(&field.name),
+                escape(&field.value)
+            ))
+        })
+        .collect();
+    if !fields.is_empty() {
+        blocks.push(json!({ "type": "section", "fields": fields }));
+    }
+    let mut footer = Vec::new();
+    if let Some(author) = embed.author_name() {
+        footer.push(mrkdwn(&escape(author)));
+    }
+    if let Some(timestamp) = embed.timestamp.as_deref() {
+        footer.push(mrkdwn(&format_timestamp(timestamp)));
+    }
+    if !footer.is_empty() {
+        blocks.push(json!({ "type": "context", "elements": footer }));
+    }
+    json!({
+        "text": content.map_or_else(|| title.to_owned(), |content| format!("{content} {title}")),
+        "blocks": blocks,
+    })
+}
+
+pub fn send_json(ctx: &Context, webhook_url: &str, payload: &Value) -> Result<()> {
+    let client = ctx.webhook.client();
+    send_retrying(&ctx.webhook.retry(), || {
+        client.post(webhook_url).json(payload)
+    })?
+    .error_for_status()?;
+    Ok(())
+}
+
+pub fn send(ctx: &Context, webhook_url: &str, message: &Message) -> Result<()> {
+    send_json(
+        ctx,
+        webhook_url,
+        &format_message(&message.embed.embed, message.content),
+    )
+}
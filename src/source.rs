@@ -0,0 +1,416 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use tarjousbot::forum::{self, Post, Selectors, FORUM_URL};
+use tracing::{debug, debug_span, info_span, warn};
+
+use crate::discovery;
+use crate::error::{Error, Result};
+use crate::state::State;
+use crate::Context;
+
+// where a source left off, persisted between runs
+pub struct SourceState {
+    directory: PathBuf,
+    pub last_post: Option<u32>,
+    // sources with pages resume from here
+    pub page: Option<u32>,
+    // forum sources only look at threads newer than this
+    pub last_thread: Option<u32>,
+    // the thread of each polled post of a forum source, last_thread moves past it only
+    // once the post has been forwarded
+    pub threads: HashMap<u32, u32>,
+    // the posts parsed by this poll and how many of them each selector missed
+    pub parsed_posts: u32,
+    pub missing_selectors: BTreeMap<String, u32>,
+}
+
+impl SourceState {
+    pub fn load(directory: &Path) -> Result<Self> {
+        fs::create_dir_all(directory)?;
+        let state = State::load(directory)?;
+        Ok(Self {
+            directory: directory.to_owned(),
+            last_post: state.last_post,
+            page: state.last_page,
+            last_thread: state.last_thread,
+            threads: HashMap::new(),
+            parsed_posts: 0,
+            missing_selectors: BTreeMap::new(),
+        })
+    }
+
+    // moves the position past a post once it has been forwarded or filtered out
+    pub fn forwarded(&mut self, post_id: u32) {
+        self.last_post = Some(post_id);
+        if let Some(&thread) = self.threads.get(&post_id) {
+            self.last_thread = self.last_thread.max(Some(thread));
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        State::update(&self.directory, |state| {
+            state.last_page = self.page.or(state.last_page);
+            state.last_post = self.last_post.or(state.last_post);
+            state.last_thread = self.last_thread.or(state.last_thread);
+            // kept from an earlier poll until there are new posts to check
+            if self.parsed_posts > 0 {
+                state.parsed_posts = self.parsed_posts;
+                state.missing_selectors.clone_from(&self.missing_selectors);
+            }
+        })
+    }
+}
+
+// returns posts oldest first, possibly including already sent ones
+// which are skipped based on the state afterwards
+pub trait Source {
+    fn poll(&mut self, ctx: &Context, state: &mut SourceState) -> Result<Vec<Post>>;
+
+    // moves the position to just before the post, on the first run
+    fn seek(&mut self, _ctx: &Context, _state: &mut SourceState, _post_id: u32) -> Result<()> {
+        Ok(())
+    }
+}
+
+// the page number of a thread page url, the first page has none
+fn page_of_url(url: &Url) -> Option<u32> {
+    url.path_segments()?
+        .find_map(|segment| segment.strip_prefix("page-"))?
+        .parse()
+        .ok()
+}
+
+// a post the selectors don't fit is left out instead of stopping the whole poll
+pub(crate) fn parse_or_warn(element: ElementRef, selectors: &Selectors) -> Option<Post> {
+    match forum::parse_post(element, selectors) {
+        Ok(post) => Some(post),
+        Err(err) => {
+            let id =
+                forum::get_post_id(element).map_or_else(|_| "?".to_owned(), |id| id.to_string());
+            warn!("Skipping post {id} that couldn't be parsed: {err}");
+            None
+        }
+    }
+}
+
+// a XenForo thread, read page by page from the last known page onwards
+pub struct XenForoSource {
+    thread_url: String,
+}
+
+impl XenForoSource {
+    pub fn new(thread_url: &str) -> Self {
+        Self {
+            thread_url: thread_url.to_owned(),
+        }
+    }
+}
+
+impl Source for XenForoSource {
+    fn poll(&mut self, ctx: &Context, state: &mut SourceState) -> Result<Vec<Post>> {
+        let mut page_number = state.page.unwrap_or(u32::MAX);
+        let mut resume_page = None;
+        let mut posts = Vec::new();
+        let mut missing: HashMap<&str, usize> = HashMap::new();
+        let selectors = ctx.selectors.borrow();
+        let track_edits = ctx.config.edit_tracking.is_some();
+
+        loop {
+            let _page = info_span!("fetch_page", page = page_number).entered();
+            debug!("Get page");
+            let mut page_span = ctx.tracer.span("scrape page");
+            page_span.attribute("page", &page_number);
+            let started = Instant::now();
+            let page_url = forum::page_url(&self.thread_url, page_number);
+            let response = ctx
+                .config
+                .retry
+                .send(|| ctx.forum_client.get(&page_url))?
+                .error_for_status()?;
+            ctx.metrics.timing("fetch.latency", started.elapsed());
+            if page_number == u32::MAX {
+                // figure out the actual page from the url
+                page_number = page_of_url(response.url()).ok_or(Error::Scraping)?;
+            }
+
+            let body = response.text()?;
+            let fragment = Html::parse_document(&body);
+
+            for element in fragment.select(&selectors.post) {
+                let Ok(id) = forum::get_post_id(element) else {
+                    warn!("Skipping a post without an id on page {page_number}");
+                    continue;
+                };
+                let sent = state.last_post.is_some_and(|last| id <= last);
+                if resume_page.is_none() && !sent {
+                    resume_page = Some(page_number);
+                }
+                // sent posts are only looked at again for their edits
+                if sent && !track_edits {
+                    continue;
+                }
+                let _span = ctx.tracer.span("parse post");
+                let _parse = debug_span!("parse_post").entered();
+                let Some(post) = parse_or_warn(element, &selectors) else {
+                    continue;
+                };
+                // an optional selector missing is only worth noting for posts that parsed
+                for name in forum::missing_selectors(element, &selectors) {
+                    ctx.metrics.increment(&format!("selector.{name}.missing"));
+                    *missing.entry(name).or_insert(0) += 1;
+                }
+                posts.push(post);
+            }
+
+            if let Some(next_page) = fragment.select(&selectors.next_page).next() {
+                page_number = next_page
+                    .text()
+                    .next()
+                    .ok_or(Error::Scraping)?
+                    .parse()
+                    .or(Err(Error::Scraping))?;
+                continue;
+            }
+            break;
+        }
+
+        for (name, count) in &missing {
+            if *count == posts.len() {
+                warn!("The {name} selector matched none of the {count} posts");
+            }
+        }
+        state.parsed_posts = u32::try_from(posts.len()).unwrap_or(u32::MAX);
+        state.missing_selectors = missing
+            .into_iter()
+            .map(|(name, count)| (name.to_owned(), u32::try_from(count).unwrap_or(u32::MAX)))
+            .collect();
+
+        // the next run starts from the first page with unsent posts
+        state.page = Some(resume_page.unwrap_or(page_number));
+        Ok(posts)
+    }
+
+    // the forum redirects a post to the page of its thread it is on
+    fn seek(&mut self, ctx: &Context, state: &mut SourceState, post_id: u32) -> Result<()> {
+        let post_url = format!("{FORUM_URL}/posts/{post_id}/");
+        let response = ctx
+            .config
+            .retry
+            .send(|| ctx.forum_client.get(&post_url))?
+            .error_for_status()?;
+        state.page = Some(page_of_url(response.url()).unwrap_or(1));
+        Ok(())
+    }
+}
+
+// a XenForo thread read from its feed, which is lighter and doesn't depend on the
+// theme, the pages are read instead when the feed can't be used
+pub struct XenForoFeedSource {
+    thread_url: String,
+    pages: XenForoSource,
+}
+
+impl XenForoFeedSource {
+    pub fn new(thread_url: &str) -> Self {
+        Self {
+            thread_url: thread_url.to_owned(),
+            pages: XenForoSource::new(thread_url),
+        }
+    }
+
+    // None when posts may have been left out of the feed since the last poll
+    fn poll_feed(&self, ctx: &Context, state: &SourceState) -> Result<Option<Vec<Post>>> {
+        debug!("Get feed");
+        let started = Instant::now();
+        let feed_url = forum::feed_url(&self.thread_url);
+        let body = ctx
+            .config
+            .retry
+            .send(|| ctx.forum_client.get(&feed_url))?
+            .error_for_status()?
+            .text()?;
+        ctx.metrics.timing("fetch.latency", started.elapsed());
+        let posts = forum::parse_feed(&body, &ctx.selectors.borrow())?;
+        let continues = state
+            .last_post
+            .is_none_or(|last| posts.first().is_some_and(|post| post.id <= last));
+        Ok(Some(posts).filter(|_| continues))
+    }
+}
+
+impl Source for XenForoFeedSource {
+    fn poll(&mut self, ctx: &Context, state: &mut SourceState) -> Result<Vec<Post>> {
+        // rehosting needs the avatars, which only the pages have
+        if ctx.config.rehost_avatars {
+            return self.pages.poll(ctx, state);
+        }
+        match self.poll_feed(ctx, state) {
+            Ok(Some(posts)) => Ok(posts),
+            Ok(None) => {
+                debug!("The feed doesn't reach the last post, reading the pages");
+                self.pages.poll(ctx, state)
+            }
+            Err(err) => {
+                warn!("reading the feed failed, reading the pages instead: {err}");
+                self.pages.poll(ctx, state)
+            }
+        }
+    }
+
+    fn seek(&mut self, ctx: &Context, state: &mut SourceState, post_id: u32) -> Result<()> {
+        self.pages.seek(ctx, state, post_id)
+    }
+}
+
+// a XenForo forum listing, polled for the first posts of threads created since
+pub struct XenForoForumSource {
+    forum_url: String,
+}
+
+impl XenForoForumSource {
+    pub fn new(forum_url: &str) -> Self {
+        Self {
+            forum_url: forum_url.to_owned(),
+        }
+    }
+}
+
+impl Source for XenForoForumSource {
+    fn poll(&mut self, ctx: &Context, state: &mut SourceState) -> Result<Vec<Post>> {
+        debug!("Get forum listing");
+        let body = ctx
+            .config
+            .retry
+            .send(|| ctx.forum_client.get(&self.forum_url))?
+            .error_for_status()?
+            .text()?;
+        let mut threads: Vec<(u32, String)> = discovery::find_threads(&body)
+            .into_iter()
+            .filter_map(|(_, url)| Some((discovery::thread_id(&url)?.parse().ok()?, url)))
+            .collect();
+        threads.sort_unstable();
+        threads.dedup();
+
+        let Some(last_thread) = state.last_thread else {
+            // the threads listed now count as seen, and every new thread gets sent
+            state.last_thread = threads.last().map(|(id, _)| *id);
+            state.last_post = state.last_post.or(Some(0));
+            return Ok(Vec::new());
+        };
+        let selectors = ctx.selectors.borrow();
+        let mut posts = Vec::new();
+        for (id, thread_url) in threads.into_iter().filter(|(id, _)| *id > last_thread) {
+            let _thread = info_span!("fetch_thread", thread = id).entered();
+            debug!("Get first page");
+            let started = Instant::now();
+            let body = ctx
+                .config
+                .retry
+                .send(|| ctx.forum_client.get(forum::page_url(&thread_url, 1)))?
+                .error_for_status()?
+                .text()?;
+            ctx.metrics.timing("fetch.latency", started.elapsed());
+            let fragment = Html::parse_document(&body);
+            let post = fragment.select(&selectors.post).next();
+            if post.is_none() {
+                warn!("No posts found in {thread_url}");
+            }
+            match post.and_then(|element| parse_or_warn(element, &selectors)) {
+                Some(post) => {
+                    state.threads.insert(post.id, id);
+                    posts.push(post);
+                }
+                // nothing to forward, so passed right away unless a post before it is pending
+                None if posts.is_empty() => state.last_thread = Some(id),
+                None => {}
+            }
+        }
+        // posts are sent oldest first
+        posts.sort_unstable_by_key(|post| post.id);
+        Ok(posts)
+    }
+}
+
+// ids of the posts on a search result page, newest first
+fn find_result_post_ids(body: &str) -> Vec<u32> {
+    let selector = Selector::parse(".contentRow-title a[href]").unwrap();
+    Html::parse_document(body)
+        .select(&selector)
+        .filter_map(|element| element.value().attr("href"))
+        .flat_map(|href| forum::linked_post_ids(&format!("{FORUM_URL}{href}")))
+        .collect()
+}
+
+// the posts matching a search query across the whole forum, the forum may only
+// allow searching when logged in
+pub struct XenForoSearchSource {
+    query: String,
+}
+
+impl XenForoSearchSource {
+    pub fn new(query: &str) -> Self {
+        Self {
+            query: query.to_owned(),
+        }
+    }
+}
+
+impl Source for XenForoSearchSource {
+    fn poll(&mut self, ctx: &Context, state: &mut SourceState) -> Result<Vec<Post>> {
+        debug!("Search for {}", self.query);
+        let search_url = format!("{FORUM_URL}/search/search");
+        let body = ctx
+            .config
+            .retry
+            .send(|| {
+                ctx.forum_client.get(&search_url).query(&[
+                    ("keywords", self.query.as_str()),
+                    ("t", "post"),
+                    ("o", "date"),
+                ])
+            })?
+            .error_for_status()?
+            .text()?;
+        let mut ids = find_result_post_ids(&body);
+        ids.sort_unstable();
+        ids.dedup();
+
+        // the results found now count as sent
+        let Some(last_post) = state.last_post else {
+            state.last_post = Some(ids.last().copied().unwrap_or(0));
+            return Ok(Vec::new());
+        };
+        let selectors = ctx.selectors.borrow();
+        let mut posts = Vec::new();
+        for id in ids.into_iter().filter(|&id| id > last_post) {
+            let _post = info_span!("fetch_post", post = id).entered();
+            debug!("Get post");
+            let started = Instant::now();
+            let post_url = format!("{FORUM_URL}/posts/{id}/");
+            let body = ctx
+                .config
+                .retry
+                .send(|| ctx.forum_client.get(&post_url))?
+                .error_for_status()?
+                .text()?;
+            ctx.metrics.timing("fetch.latency", started.elapsed());
+            // the post is on a page of its thread among others
+            let fragment = Html::parse_document(&body);
+            let element = fragment
+                .select(&selectors.post)
+                .find(|element| forum::get_post_id(*element).is_ok_and(|post_id| post_id == id));
+            if let Some(element) = element {
+                posts.extend(parse_or_warn(element, &selectors));
+            } else {
+                warn!("Post {id} not found at {post_url}");
+            }
+        }
+        Ok(posts)
+    }
+}
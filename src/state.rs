@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::{Error, Result};
+
+const STATE_FILE: &str = "state.json";
+// bumped whenever the meaning of a field changes, with a migration below
+const VERSION: u32 = 1;
+// the raw little endian u32 files used before the state file
+static LEGACY_FILES: &[&str] = &["last_page", "last_post", "import_page"];
+
+// where the thread of a state directory left off
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    version: u32,
+    pub last_page: Option<u32>,
+    pub last_post: Option<u32>,
+    // where an import continues from
+    pub import_page: Option<u32>,
+    // the newest thread seen by a forum source
+    pub last_thread: Option<u32>,
+    // how many of the posts parsed on the last poll with new posts each selector missed
+    #[serde(default)]
+    pub parsed_posts: u32,
+    #[serde(default)]
+    pub missing_selectors: BTreeMap<String, u32>,
+}
+
+fn read_legacy_u32(path: PathBuf) -> Result<Option<u32>> {
+    match File::open(path) {
+        Ok(mut file) => Ok(file.read_u32::<LittleEndian>().ok()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// written to a temporary file first, so that a crash never leaves it half written
+pub fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(temporary, path)?;
+    Ok(())
+}
+
+impl State {
+    fn migrate_legacy(directory: &Path) -> Result<Self> {
+        let state = Self {
+            version: VERSION,
+            last_page: read_legacy_u32(directory.join("last_page"))?,
+            last_post: read_legacy_u32(directory.join("last_post"))?,
+            import_page: read_legacy_u32(directory.join("import_page"))?,
+            ..Self::default()
+        };
+        if state.last_page.is_some() || state.last_post.is_some() || state.import_page.is_some() {
+            info!("Migrating the state in {}", directory.display());
+            state.save(directory)?;
+            for name in LEGACY_FILES {
+                match fs::remove_file(directory.join(name)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                    _ => {}
+                }
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn load(directory: &Path) -> Result<Self> {
+        let state: Self = match fs::read_to_string(directory.join(STATE_FILE)) {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Self::migrate_legacy(directory)
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if state.version > VERSION {
+            return Err(Error::InvalidState(format!(
+                "{} is from a newer version",
+                directory.join(STATE_FILE).display()
+            )));
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, directory: &Path) -> Result<()> {
+        write_atomically(
+            &directory.join(STATE_FILE),
+            &serde_json::to_string(&Self {
+                version: VERSION,
+                missing_selectors: self.missing_selectors.clone(),
+                ..*self
+            })?,
+        )
+    }
+
+    // rereads the state before changing it, so that other fields written meanwhile are kept
+    pub fn update(directory: &Path, change: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut state = Self::load(directory)?;
+        change(&mut state);
+        state.save(directory)
+    }
+}
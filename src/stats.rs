@@ -0,0 +1,189 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Timelike, Weekday};
+use serde::Deserialize;
+
+use crate::archive::Archive;
+use crate::config::Target;
+use crate::error::Result;
+use crate::price;
+
+#[derive(Deserialize)]
+pub struct WeeklyStatsConfig {
+    #[serde(flatten)]
+    pub target: Target,
+    #[serde(default = "default_weekday")]
+    pub weekday: Weekday,
+    #[serde(default = "default_hour")]
+    pub hour: u32,
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardConfig {
+    #[serde(flatten)]
+    pub target: Target,
+    #[serde(default = "default_day")]
+    pub day: u32,
+    #[serde(default = "default_hour")]
+    pub hour: u32,
+}
+
+fn default_day() -> u32 {
+    1
+}
+
+fn default_weekday() -> Weekday {
+    Weekday::Mon
+}
+
+fn default_hour() -> u32 {
+    9
+}
+
+pub struct Leaderboard {
+    pub month: String,
+    pub most_active: String,
+    pub most_liked: String,
+}
+
+pub struct WeeklyStats {
+    pub categories: String,
+    pub stores: String,
+    pub discounts: String,
+    pub most_reacted: String,
+}
+
+fn last_sent_path(state_directory: &Path, name: &str) -> PathBuf {
+    state_directory.join(format!("{name}_sent"))
+}
+
+fn last_sent(state_directory: &Path, name: &str) -> Result<Option<NaiveDate>> {
+    match fs::read_to_string(last_sent_path(state_directory, name)) {
+        Ok(s) => Ok(s.trim().parse().ok()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn is_due(
+    state_directory: &Path,
+    name: &str,
+    day_matches: bool,
+    hour: u32,
+    now: DateTime<Local>,
+) -> Result<bool> {
+    if !day_matches || now.hour() < hour {
+        return Ok(false);
+    }
+    Ok(last_sent(state_directory, name)? != Some(now.date_naive()))
+}
+
+pub fn is_weekly_stats_due(
+    config: &WeeklyStatsConfig,
+    state_directory: &Path,
+    now: DateTime<Local>,
+) -> Result<bool> {
+    let day_matches = now.weekday() == config.weekday;
+    is_due(
+        state_directory,
+        "weekly_stats",
+        day_matches,
+        config.hour,
+        now,
+    )
+}
+
+pub fn is_leaderboard_due(
+    config: &LeaderboardConfig,
+    state_directory: &Path,
+    now: DateTime<Local>,
+) -> Result<bool> {
+    let day_matches = now.day() == config.day;
+    is_due(
+        state_directory,
+        "leaderboard",
+        day_matches,
+        config.hour,
+        now,
+    )
+}
+
+// remembers the date so that the message is sent only once on that day
+pub fn mark_sent(state_directory: &Path, name: &str, now: DateTime<Local>) -> Result<()> {
+    fs::write(
+        last_sent_path(state_directory, name),
+        now.date_naive().to_string(),
+    )?;
+    Ok(())
+}
+
+fn format_counts(counts: &[(String, u32)]) -> String {
+    counts
+        .iter()
+        .map(|(name, count)| format!("{name}: {count}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn collect(archive: &Archive, now: DateTime<Local>) -> Result<WeeklyStats> {
+    let since = (now - Duration::days(7)).timestamp();
+    let discounts = archive
+        .biggest_discounts(since, 5)?
+        .iter()
+        .map(|discount| {
+            format!(
+                "[{}]({}) {} (normaalisti {})",
+                discount.title,
+                discount.url,
+                price::format_price(discount.price),
+                price::format_price(discount.typical_price)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let most_reacted = archive
+        .most_reacted(since, 5)?
+        .iter()
+        .map(|post| {
+            format!(
+                "[{}]({}) {} reaktiota",
+                post.title, post.url, post.reactions
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Ok(WeeklyStats {
+        categories: format_counts(&archive.category_counts(since)?),
+        stores: format_counts(&archive.store_counts(since)?),
+        discounts,
+        most_reacted,
+    })
+}
+
+// covers the previous calendar month
+pub fn collect_leaderboard(archive: &Archive, now: DateTime<Local>) -> Result<Leaderboard> {
+    let this_month = now
+        .date_naive()
+        .with_day(1)
+        .unwrap_or_else(|| now.date_naive());
+    let previous_month = (this_month - Duration::days(1))
+        .with_day(1)
+        .unwrap_or(this_month);
+    let since = local_timestamp(previous_month);
+    let until = local_timestamp(this_month);
+
+    Ok(Leaderboard {
+        month: previous_month.format("%m/%Y").to_string(),
+        most_active: format_counts(&archive.author_post_counts(since, until, 10)?),
+        most_liked: format_counts(&archive.author_reaction_counts(since, until, 10)?),
+    })
+}
+
+fn local_timestamp(date: NaiveDate) -> i64 {
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|datetime| datetime.and_local_timezone(Local).earliest())
+        .map_or(0, |datetime| datetime.timestamp())
+}
@@ -0,0 +1,160 @@
+use std::fmt::Write;
+
+use regex::Regex;
+use reqwest::blocking::{Client, Response};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::retry::RetryConfig;
+use crate::webhook::{send_retrying, Embed};
+use crate::{truncate, Context, Message};
+
+static API_BASE_URL: &str = "https://api.telegram.org";
+pub static TELEGRAM_HOST: &str = "api.telegram.org";
+
+// telegram allows 4096 characters, escaping takes some of them
+const MAX_DESCRIPTION_LENGTH: usize = 3000;
+
+pub struct Telegram<'a> {
+    client: &'a Client,
+    token: &'a str,
+    retry: RetryConfig,
+}
+
+#[derive(Serialize)]
+struct SendMessage<'a> {
+    chat_id: &'a str,
+    text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<&'a str>,
+    disable_notification: bool,
+}
+
+impl<'a> Telegram<'a> {
+    pub fn with_client(client: &'a Client, token: &'a str) -> Self {
+        Self {
+            client,
+            token,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn url(&self, method: &str) -> String {
+        format!("{API_BASE_URL}/bot{}/{method}", self.token)
+    }
+
+    // checks the token is valid
+    pub fn get_me(&self) -> reqwest::Result<Response> {
+        checked(self.client.get(self.url("getMe")).send())
+    }
+
+    // the text is sent as is without formatting unless markdown is set
+    pub fn send_message(
+        &self,
+        chat_id: &str,
+        text: &str,
+        markdown: bool,
+        silent: bool,
+    ) -> reqwest::Result<Response> {
+        let payload = SendMessage {
+            chat_id,
+            text,
+            parse_mode: markdown.then_some("MarkdownV2"),
+            disable_notification: silent,
+        };
+        let url = self.url("sendMessage");
+        checked(send_retrying(&self.retry, || {
+            self.client.post(&url).json(&payload)
+        }))
+    }
+}
+
+// fails on error statuses, the url is left out of the error as the token is part of it
+fn checked(result: reqwest::Result<Response>) -> reqwest::Result<Response> {
+    result
+        .and_then(Response::error_for_status)
+        .map_err(reqwest::Error::without_url)
+}
+
+// backslashes the characters markdownv2 reserves
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// inside the parentheses of a link only these need escaping
+fn escape_url(url: &str) -> String {
+    url.replace('\\', "\\\\").replace(')', "\\)")
+}
+
+// the markdown links of the description and fields are kept as links
+fn escape_with_links(text: &str) -> String {
+    let links = Regex::new(r"\[([^\]]+)\]\((https?://[^)\s]+)\)").unwrap();
+    let mut escaped = String::with_capacity(text.len());
+    let mut rest = 0;
+    for captures in links.captures_iter(text) {
+        let link = captures.get(0).unwrap();
+        escaped.push_str(&escape(&text[rest..link.start()]));
+        let _ = write!(
+            escaped,
+            "[{}]({})",
+            escape(&captures[1]),
+            escape_url(&captures[2])
+        );
+        rest = link.end();
+    }
+    escaped.push_str(&escape(&text[rest..]));
+    escaped
+}
+
+// the embed as a markdownv2 message, the mentions of the content first
+pub fn format_message(embed: &Embed, content: Option<&str>) -> String {
+    let mut lines = Vec::new();
+    if let Some(content) = content {
+        lines.push(escape(content));
+    }
+    match (embed.title.as_deref(), embed.url.as_deref()) {
+        (Some(title), Some(url)) => {
+            lines.push(format!("*[{}]({})*", escape(title), escape_url(url)));
+        }
+        (Some(title), None) => lines.push(format!("*{}*", escape(title))),
+        _ => {}
+    }
+    if let Some(description) = embed.description.as_deref() {
+        lines.push(escape_with_links(truncate(
+            description,
+            MAX_DESCRIPTION_LENGTH,
+        )));
+    }
+    for field in &embed.fields {
+        lines.push(format!(
+            "*{}:* {}",
+            escape(&field.name),
+            escape_with_links(&field.value)
+        ));
+    }
+    if let Some(author) = embed.author_name() {
+        lines.push(format!("_{}_", escape(author)));
+    }
+    lines.join("\n")
+}
+
+// the whole embed goes in one message, so it isn't split like for discord
+pub fn send(ctx: &Context, token: &str, chat_id: &str, message: &Message) -> Result<()> {
+    let text = format_message(&message.embed.embed, message.content);
+    Telegram::with_client(ctx.webhook.client(), token)
+        .with_retry(ctx.webhook.retry())
+        .send_message(chat_id, &text, true, message.silent)?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::error::Result;
+
+#[derive(Deserialize, Clone)]
+pub struct TelemetryConfig {
+    // OTLP/HTTP collector endpoint, e.g. http://localhost:4318
+    pub otlp_endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+}
+
+fn default_service_name() -> String {
+    env!("CARGO_PKG_NAME").to_owned()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpanData {
+    trace_id: String,
+    span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_span_id: Option<String>,
+    name: &'static str,
+    kind: u8,
+    start_time_unix_nano: String,
+    end_time_unix_nano: String,
+    attributes: Vec<serde_json::Value>,
+    status: serde_json::Value,
+}
+
+struct State {
+    trace_id: String,
+    stack: Vec<String>,
+    finished: Vec<SpanData>,
+}
+
+// collects spans of one poll and exports them over OTLP/HTTP JSON
+pub struct Tracer {
+    config: Option<TelemetryConfig>,
+    state: RefCell<State>,
+}
+
+pub struct SpanGuard<'a> {
+    tracer: &'a Tracer,
+    span: Option<SpanData>,
+}
+
+fn random_id(bytes: usize) -> String {
+    let mut id = String::with_capacity(bytes * 2);
+    while id.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(now_nanos());
+        let _ = write!(id, "{:016x}", hasher.finish());
+    }
+    id.truncate(bytes * 2);
+    id
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos())
+}
+
+impl Tracer {
+    pub fn new(config: Option<TelemetryConfig>) -> Self {
+        Self {
+            config,
+            state: RefCell::new(State {
+                trace_id: random_id(16),
+                stack: Vec::new(),
+                finished: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn start_trace(&self) {
+        self.state.borrow_mut().trace_id = random_id(16);
+    }
+
+    // the span ends when the guard is dropped
+    pub fn span(&self, name: &'static str) -> SpanGuard<'_> {
+        if self.config.is_none() {
+            return SpanGuard {
+                tracer: self,
+                span: None,
+            };
+        }
+        let mut state = self.state.borrow_mut();
+        let span_id = random_id(8);
+        let span = SpanData {
+            trace_id: state.trace_id.clone(),
+            span_id: span_id.clone(),
+            parent_span_id: state.stack.last().cloned(),
+            name,
+            kind: 1,
+            start_time_unix_nano: now_nanos().to_string(),
+            end_time_unix_nano: String::new(),
+            attributes: Vec::new(),
+            status: json!({}),
+        };
+        state.stack.push(span_id);
+        SpanGuard {
+            tracer: self,
+            span: Some(span),
+        }
+    }
+
+    pub fn flush(&self, client: &Client) -> Result<()> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        let spans: Vec<SpanData> = self.state.borrow_mut().finished.drain(..).collect();
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let body = json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{
+                        "key": "service.name",
+                        "value": { "stringValue": config.service_name },
+                    }],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": env!("CARGO_PKG_NAME") },
+                    "spans": spans,
+                }],
+            }],
+        });
+        let url = format!("{}/v1/traces", config.otlp_endpoint.trim_end_matches('/'));
+        client.post(url).json(&body).send()?.error_for_status()?;
+        Ok(())
+    }
+}
+
+impl SpanGuard<'_> {
+    pub fn attribute(&mut self, key: &str, value: &impl ToString) -> &mut Self {
+        if let Some(span) = &mut self.span {
+            span.attributes.push(json!({
+                "key": key,
+                "value": { "stringValue": value.to_string() },
+            }));
+        }
+        self
+    }
+
+    pub fn error(&mut self, message: &impl ToString) -> &mut Self {
+        if let Some(span) = &mut self.span {
+            span.status = json!({ "code": 2, "message": message.to_string() });
+        }
+        self
+    }
+}
+
+impl Drop for SpanGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut span) = self.span.take() {
+            span.end_time_unix_nano = now_nanos().to_string();
+            let mut state = self.tracer.state.borrow_mut();
+            state.stack.pop();
+            state.finished.push(span);
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::Destination;
+use crate::error::Result;
+use crate::outbox::sink_id;
+use crate::state::write_atomically;
+use crate::webhook::EmbedBuilder;
+use crate::{price, send_message, Context, Message};
+
+const DIGEST_DESCRIPTION_LIMIT: usize = 4096;
+const THROTTLE_FILE: &str = "throttle.json";
+
+#[derive(Deserialize)]
+pub struct ThrottleConfig {
+    pub max_messages: u32,
+    // seconds, the posts over the cap are sent as one digest when the window ends
+    #[serde(default = "default_window")]
+    pub window: u64,
+}
+
+fn default_window() -> u64 {
+    60 * 60
+}
+
+// a post held back for the digest
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DigestEntry {
+    pub title: String,
+    pub url: String,
+    pub price: Option<f64>,
+    // for purges, missing from the entries held back before they were stored
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub posted_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Window {
+    // unix timestamp
+    started: i64,
+    sent: u32,
+    overflow: Vec<DigestEntry>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            started: Utc::now().timestamp(),
+            sent: 0,
+            overflow: Vec::new(),
+        }
+    }
+
+    fn has_ended(&self, config: &ThrottleConfig) -> bool {
+        let window = i64::try_from(config.window).unwrap_or(i64::MAX);
+        Utc::now().timestamp().saturating_sub(self.started) >= window
+    }
+}
+
+// windows start from the first message and are stored in the state directory,
+// so that the posts held back are sent even if the bot is restarted before the window ends
+pub struct Throttler {
+    path: PathBuf,
+    // by the hash of the destination key, which may be a secret webhook url
+    windows: RefCell<HashMap<String, Window>>,
+}
+
+impl Throttler {
+    pub fn load(state_directory: &Path) -> Result<Self> {
+        let path = state_directory.join(THROTTLE_FILE);
+        let windows = match fs::read_to_string(&path) {
+            Ok(s) => serde_json::from_str(&s)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            windows: RefCell::new(windows),
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        write_atomically(&self.path, &serde_json::to_string(&*self.windows.borrow())?)
+    }
+
+    // the posts held back during a window that has ended, kept until the digest is sent
+    fn pending_digest(&self, destination: &str, config: &ThrottleConfig) -> Vec<DigestEntry> {
+        let id = sink_id(destination);
+        let mut windows = self.windows.borrow_mut();
+        let Some(window) = windows.get(&id).filter(|window| window.has_ended(config)) else {
+            return Vec::new();
+        };
+        if window.overflow.is_empty() {
+            windows.remove(&id);
+            return Vec::new();
+        }
+        window.overflow.clone()
+    }
+
+    // starts a new window once the digest of the ended one was sent
+    fn digest_sent(&self, destination: &str) {
+        self.windows.borrow_mut().remove(&sink_id(destination));
+    }
+
+    // drops the held back posts matching, returns how many were dropped
+    pub fn purge(&self, matches: impl Fn(&DigestEntry) -> bool) -> Result<usize> {
+        let mut purged = 0;
+        for window in self.windows.borrow_mut().values_mut() {
+            let count = window.overflow.len();
+            window.overflow.retain(|entry| !matches(entry));
+            purged += count - window.overflow.len();
+        }
+        if purged > 0 {
+            self.save()?;
+        }
+        Ok(purged)
+    }
+
+    // counts the message towards the cap, or holds the post back when over it
+    pub fn admit(
+        &self,
+        ctx: &Context,
+        destination: &str,
+        config: &ThrottleConfig,
+        entry: DigestEntry,
+    ) -> Result<bool> {
+        let admitted = {
+            let mut windows = self.windows.borrow_mut();
+            let window = windows
+                .entry(sink_id(destination))
+                .or_insert_with(Window::new);
+            if window.sent < config.max_messages {
+                window.sent += 1;
+                true
+            } else {
+                window.overflow.push(entry);
+                false
+            }
+        };
+        if !ctx.dry_run {
+            self.save()?;
+        }
+        Ok(admitted)
+    }
+}
+
+// the entries that don't fit are counted at the end instead
+pub fn digest_description(entries: &[DigestEntry]) -> String {
+    let mut description = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let mut line = format!("[{}]({})", entry.title, entry.url);
+        if let Some(price) = entry.price {
+            let _ = write!(line, " {}", price::format_price(price));
+        }
+        if !description.is_empty() {
+            line.insert(0, '\n');
+        }
+        let rest = format!("… ja {} muuta", entries.len() - index);
+        let length = description.chars().count() + line.chars().count();
+        let is_last = index + 1 == entries.len();
+        if length > DIGEST_DESCRIPTION_LIMIT
+            || (!is_last && length + rest.chars().count() + 1 > DIGEST_DESCRIPTION_LIMIT)
+        {
+            if !description.is_empty() {
+                description.push('\n');
+            }
+            description.push_str(&rest);
+            break;
+        }
+        description.push_str(&line);
+    }
+    description
+}
+
+// sends the posts held back during an ended window as one message
+pub fn send_digest(ctx: &Context, destination: &Destination) -> Result<()> {
+    let Some(config) = &destination.throttle else {
+        return Ok(());
+    };
+    let key = destination.target.key();
+    let entries = ctx.throttler.pending_digest(key, config);
+    if entries.is_empty() {
+        return Ok(());
+    }
+    info!("Send digest of {} posts", entries.len());
+    let title = format!("Lisää tarjouksia ({})", entries.len());
+    let description = digest_description(&entries);
+    let mut embed = EmbedBuilder::new();
+    embed.title(&title).description(&description);
+    let message = Message {
+        content: None,
+        embed: &embed,
+        attachments: &[],
+        buttons: &[],
+        title: &title,
+        category: None,
+        data: None,
+        silent: false,
+    };
+    send_message(ctx, &destination.target, &message)?;
+    if ctx.dry_run {
+        return Ok(());
+    }
+    ctx.throttler.digest_sent(key);
+    ctx.throttler.save()
+}
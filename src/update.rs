@@ -0,0 +1,138 @@
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use reqwest::blocking::Client;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+
+pub static UPDATE_HOSTS: &[&str] = &[
+    "api.github.com",
+    "github.com",
+    "objects.githubusercontent.com",
+    "release-assets.githubusercontent.com",
+];
+
+static RELEASES_URL: &str = "https://api.github.com/repos/lasa01/tarjousbot/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+// release binaries are named like tarjousbot-x86_64-linux
+fn binary_name() -> String {
+    format!(
+        "{}-{}-{}",
+        env!("CARGO_PKG_NAME"),
+        env::consts::ARCH,
+        env::consts::OS
+    )
+}
+
+fn download(client: &Client, release: &Release, name: &str) -> Result<Option<Vec<u8>>> {
+    let Some(asset) = release.assets.iter().find(|asset| asset.name == name) else {
+        return Ok(None);
+    };
+    let bytes = client
+        .get(&asset.browser_download_url)
+        .send()?
+        .error_for_status()?
+        .bytes()?;
+    Ok(Some(bytes.to_vec()))
+}
+
+fn verify_checksum(binary: &[u8], checksum: &[u8]) -> Result<()> {
+    // sha256sum output, the hash is followed by the file name
+    let expected = String::from_utf8_lossy(checksum)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    if hex::encode(Sha256::digest(binary)) != expected {
+        return Err(Error::Update("checksum mismatch".to_owned()));
+    }
+    Ok(())
+}
+
+fn verify_signature(binary: &[u8], signature: &[u8], public_key: &str) -> Result<()> {
+    let public_key: [u8; 32] = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::InvalidConfig("invalid update public key".to_owned()))?;
+    let public_key = VerifyingKey::from_bytes(&public_key)
+        .map_err(|_| Error::InvalidConfig("invalid update public key".to_owned()))?;
+    let signature: [u8; 64] = signature
+        .try_into()
+        .map_err(|_| Error::Update("malformed signature".to_owned()))?;
+    public_key
+        .verify(binary, &Signature::from_bytes(&signature))
+        .map_err(|_| Error::Update("signature verification failed".to_owned()))
+}
+
+// writes next to the current executable and renames over it
+fn replace_executable(path: &Path, binary: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension("new");
+    fs::write(&temp_path, binary)?;
+    fs::set_permissions(&temp_path, fs::metadata(path)?.permissions())?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn parse_version(version: &str) -> Result<Version> {
+    Version::parse(version.trim_start_matches('v'))
+        .map_err(|err| Error::Update(format!("invalid version {version}: {err}")))
+}
+
+// the checksum comes from the same release as the binary,
+// so without a public key the update needs to be allowed explicitly
+pub fn self_update(client: &Client, public_key: Option<&str>, insecure: bool) -> Result<()> {
+    if public_key.is_none() && !insecure {
+        return Err(Error::Update(
+            "no update_public_key configured, use --insecure to update without a signature"
+                .to_owned(),
+        ));
+    }
+    let release: Release = client
+        .get(RELEASES_URL)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    let version = parse_version(&release.tag_name)?;
+    let current = parse_version(env!("CARGO_PKG_VERSION"))?;
+    if version <= current {
+        info!("Already running the latest version {current}");
+        return Ok(());
+    }
+
+    let name = binary_name();
+    let binary = download(client, &release, &name)?
+        .ok_or_else(|| Error::Update(format!("no release binary named {name}")))?;
+    let checksum = download(client, &release, &format!("{name}.sha256"))?
+        .ok_or_else(|| Error::Update(format!("no checksum for {name}")))?;
+    verify_checksum(&binary, &checksum)?;
+    if let Some(public_key) = public_key {
+        let signature = download(client, &release, &format!("{name}.sig"))?
+            .ok_or_else(|| Error::Update(format!("no signature for {name}")))?;
+        verify_signature(&binary, &signature, public_key)?;
+    } else {
+        warn!("Updating without verifying a signature");
+    }
+
+    replace_executable(&env::current_exe()?, &binary)?;
+    info!("Updated to version {version}");
+    Ok(())
+}
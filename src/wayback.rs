@@ -0,0 +1,23 @@
+use reqwest::blocking::Client;
+
+use crate::error::Result;
+
+pub static WAYBACK_HOST: &str = "web.archive.org";
+static SAVE_URL: &str = "https://web.archive.org/save/";
+
+// asks the internet archive to save the page and returns the snapshot url
+pub fn save(client: &Client, url: &str) -> Result<String> {
+    let response = client
+        .get(format!("{SAVE_URL}{url}"))
+        .send()?
+        .error_for_status()?;
+    let snapshot_url = match response
+        .headers()
+        .get("Content-Location")
+        .and_then(|location| location.to_str().ok())
+    {
+        Some(location) => format!("https://web.archive.org{location}"),
+        None => response.url().to_string(),
+    };
+    Ok(snapshot_url)
+}
@@ -1,63 +1,120 @@
 #![allow(dead_code)]
 
-use reqwest::blocking::Client;
-use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use reqwest::blocking::multipart::{Form, Part};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::retry::RetryConfig;
+
+// discord rejects messages with more embed text than this in total
+const EMBED_TOTAL_LIMIT: usize = 6000;
+const EMBED_FIELD_LIMIT: usize = 25;
+const EMBED_TITLE_LIMIT: usize = 256;
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+const FIELD_NAME_LIMIT: usize = 256;
+const FIELD_VALUE_LIMIT: usize = 1024;
+const FOOTER_TEXT_LIMIT: usize = 2048;
+const AUTHOR_NAME_LIMIT: usize = 256;
+const MESSAGE_EMBED_LIMIT: usize = 10;
+const ROW_BUTTON_LIMIT: usize = 5;
+pub const BUTTON_URL_LIMIT: usize = 512;
+pub(crate) const SUPPRESS_NOTIFICATIONS: u32 = 1 << 12;
+// after this many the rate limited response is returned as is
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
 
 pub struct Webhook<'a> {
     client: &'a Client,
+    retry: RetryConfig,
 }
 
 impl<'a> Webhook<'a> {
     pub fn with_client(client: &'a Client) -> Self {
-        Self { client }
+        Self {
+            client,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn client(&self) -> &'a Client {
+        self.client
+    }
+
+    pub fn retry(&self) -> RetryConfig {
+        self.retry
     }
 }
 
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, Clone)]
 struct EmbedFooter<'a> {
-    text: &'a str,
+    text: Cow<'a, str>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    icon_url: Option<&'a str>,
+    icon_url: Option<Cow<'a, str>>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct EmbedImage<'a> {
-    url: &'a str,
+    url: Cow<'a, str>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct EmbedThumbnail<'a> {
-    url: &'a str,
+    url: Cow<'a, str>,
 }
 
-#[derive(Serialize, Default)]
-struct EmbedAuthor<'a> {
+#[derive(Serialize, Clone)]
+struct EmbedVideo<'a> {
+    url: Cow<'a, str>,
+}
+
+#[derive(Serialize, Default, Clone)]
+struct EmbedProvider<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<Cow<'a, str>>,
+}
+
+#[derive(Serialize, Default, Clone)]
+pub(crate) struct EmbedAuthor<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    name: Option<&'a str>,
+    pub(crate) name: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    url: Option<&'a str>,
+    url: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    icon_url: Option<&'a str>,
+    icon_url: Option<Cow<'a, str>>,
 }
 
-#[derive(Serialize, Default)]
-struct EmbedField<'a> {
-    name: &'a str,
-    value: &'a str,
+#[derive(Serialize, Default, Clone)]
+pub(crate) struct EmbedField<'a> {
+    pub(crate) name: Cow<'a, str>,
+    pub(crate) value: Cow<'a, str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     inline: Option<bool>,
 }
 
-#[derive(Serialize, Default)]
-struct Embed<'a> {
+#[derive(Serialize, Default, Clone)]
+pub(crate) struct Embed<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    title: Option<&'a str>,
+    pub(crate) title: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<&'a str>,
+    pub(crate) description: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    url: Option<&'a str>,
+    pub(crate) url: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    timestamp: Option<&'a str>,
+    pub(crate) timestamp: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     color: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -67,55 +124,389 @@ struct Embed<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     thumbnail: Option<EmbedThumbnail<'a>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    author: Option<EmbedAuthor<'a>>,
+    video: Option<EmbedVideo<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider: Option<EmbedProvider<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) author: Option<EmbedAuthor<'a>>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
-    fields: Vec<EmbedField<'a>>,
+    pub(crate) fields: Vec<EmbedField<'a>>,
+}
+
+fn text_length(text: Option<&str>) -> usize {
+    text.map_or(0, |text| text.chars().count())
+}
+
+// cuts borrowed text by borrowing less and owned text in place
+fn trim_text(text: &mut Cow<str>, max_chars: usize) {
+    let Some((index, _)) = text.char_indices().nth(max_chars) else {
+        return;
+    };
+    match text {
+        Cow::Borrowed(borrowed) => *borrowed = &borrowed[..index],
+        Cow::Owned(owned) => owned.truncate(index),
+    }
+}
+
+impl EmbedField<'_> {
+    fn text_length(&self) -> usize {
+        self.name.chars().count() + self.value.chars().count()
+    }
+}
+
+impl Embed<'_> {
+    fn footer_text(&self) -> Option<&str> {
+        self.footer.as_ref().map(|footer| footer.text.as_ref())
+    }
+
+    pub(crate) fn author_name(&self) -> Option<&str> {
+        self.author
+            .as_ref()
+            .and_then(|author| author.name.as_deref())
+    }
+
+    // the text counted towards the total limit
+    fn text_length(&self) -> usize {
+        text_length(self.title.as_deref())
+            + text_length(self.description.as_deref())
+            + text_length(self.footer_text())
+            + text_length(self.author_name())
+            + self
+                .fields
+                .iter()
+                .map(EmbedField::text_length)
+                .sum::<usize>()
+    }
+
+    // cuts the texts over their own limits, the total is left to split
+    fn trim(&mut self) {
+        if let Some(title) = &mut self.title {
+            trim_text(title, EMBED_TITLE_LIMIT);
+        }
+        if let Some(description) = &mut self.description {
+            trim_text(description, EMBED_DESCRIPTION_LIMIT);
+        }
+        if let Some(footer) = &mut self.footer {
+            trim_text(&mut footer.text, FOOTER_TEXT_LIMIT);
+        }
+        if let Some(name) = self.author.as_mut().and_then(|author| author.name.as_mut()) {
+            trim_text(name, AUTHOR_NAME_LIMIT);
+        }
+        for field in &mut self.fields {
+            trim_text(&mut field.name, FIELD_NAME_LIMIT);
+            trim_text(&mut field.value, FIELD_VALUE_LIMIT);
+        }
+    }
+
+    fn validate(&self) -> Result<(), LimitExceeded> {
+        let texts = [
+            ("title", self.title.as_deref(), EMBED_TITLE_LIMIT),
+            (
+                "description",
+                self.description.as_deref(),
+                EMBED_DESCRIPTION_LIMIT,
+            ),
+            ("footer text", self.footer_text(), FOOTER_TEXT_LIMIT),
+            ("author name", self.author_name(), AUTHOR_NAME_LIMIT),
+        ];
+        let fields = self.fields.iter().flat_map(|field| {
+            [
+                ("field name", Some(field.name.as_ref()), FIELD_NAME_LIMIT),
+                ("field value", Some(field.value.as_ref()), FIELD_VALUE_LIMIT),
+            ]
+        });
+        for (name, text, limit) in texts.iter().copied().chain(fields) {
+            LimitExceeded::check(name, text_length(text), limit)?;
+        }
+        LimitExceeded::check("field count", self.fields.len(), EMBED_FIELD_LIMIT)?;
+        LimitExceeded::check("total text", self.text_length(), EMBED_TOTAL_LIMIT)
+    }
+}
+
+// tells which of the limits discord documents an embed is over, instead of
+// the plain bad request discord would respond with
+#[derive(Debug)]
+pub struct LimitExceeded {
+    pub name: &'static str,
+    pub length: usize,
+    pub limit: usize,
+}
+
+impl LimitExceeded {
+    fn check(name: &'static str, length: usize, limit: usize) -> Result<(), Self> {
+        if length > limit {
+            return Err(Self {
+                name,
+                length,
+                limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "embed {} is {}, over the limit of {}",
+            self.name, self.length, self.limit
+        )
+    }
 }
 
 #[derive(Serialize, Default)]
 struct ExecuteWebhook<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    content: Option<&'a str>,
+    content: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    username: Option<&'a str>,
+    username: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    avatar_url: Option<&'a str>,
+    avatar_url: Option<Cow<'a, str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tts: Option<bool>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    file: Option<&'a str>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     embeds: Vec<&'a Embed<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flags: Option<u32>,
+    allowed_mentions: AllowedMentions,
+    // creates a post in a forum channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread_name: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    components: Vec<ActionRow<'a>>,
+}
+
+// webhooks not owned by an application can only send link buttons
+#[derive(Serialize, Clone)]
+pub struct LinkButton<'a> {
+    #[serde(rename = "type")]
+    kind: u8,
+    style: u8,
+    label: Cow<'a, str>,
+    url: Cow<'a, str>,
+}
+
+impl<'a> LinkButton<'a> {
+    pub fn new(label: impl Into<Cow<'a, str>>, url: impl Into<Cow<'a, str>>) -> Self {
+        Self {
+            kind: 2,
+            style: 5,
+            label: label.into(),
+            url: url.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ActionRow<'a> {
+    #[serde(rename = "type")]
+    kind: u8,
+    components: Vec<&'a LinkButton<'a>>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MentionType {
+    Roles,
+    Users,
+    // also @here
+    Everyone,
+}
+
+// the mentions in the content that ping, none by default so that text scraped
+// from the forum can't ping anyone
+#[derive(Serialize, Default, Clone)]
+pub struct AllowedMentions {
+    parse: Vec<MentionType>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    roles: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    users: Vec<String>,
+}
+
+impl AllowedMentions {
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    // every mention of the type pings
+    pub fn parse(mut self, mention_type: MentionType) -> Self {
+        if !self.parse.contains(&mention_type) {
+            self.parse.push(mention_type);
+        }
+        self
+    }
+
+    pub fn role(mut self, role_id: impl Into<String>) -> Self {
+        self.roles.push(role_id.into());
+        self
+    }
+
+    pub fn user(mut self, user_id: impl Into<String>) -> Self {
+        self.users.push(user_id.into());
+        self
+    }
+
+    // allows exactly the mentions written in trusted text, e.g. from the config
+    pub fn from_text(text: &str) -> Self {
+        let mut mentions = Self::none();
+        if text.contains("@everyone") || text.contains("@here") {
+            mentions = mentions.parse(MentionType::Everyone);
+        }
+        for (start, _) in text.match_indices("<@") {
+            let rest = &text[start + 2..];
+            let (is_role, rest) = match rest.strip_prefix('&') {
+                Some(rest) => (true, rest),
+                None => (false, rest.strip_prefix('!').unwrap_or(rest)),
+            };
+            let Some((id, _)) = rest.split_once('>') else {
+                continue;
+            };
+            if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) {
+                continue;
+            }
+            mentions = if is_role {
+                mentions.role(id)
+            } else {
+                mentions.user(id)
+            };
+        }
+        mentions
+    }
+}
+
+// only the given parts of the message are changed
+#[derive(Serialize, Default)]
+struct EditWebhookMessage<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embeds: Option<Vec<&'a Embed<'a>>>,
+}
+
+// the message an execution created, only returned when waited for
+#[derive(Deserialize)]
+pub struct WebhookMessage {
+    pub id: String,
+    pub channel_id: String,
+    pub timestamp: String,
+}
+
+pub(crate) struct Attachment<'a> {
+    pub(crate) filename: &'a str,
+    pub(crate) data: &'a [u8],
+    // alt text for screen readers
+    pub(crate) description: Option<&'a str>,
 }
 
+// discord tells how long to wait in the body as a fraction and in the header as seconds
+fn retry_after(response: Response) -> Duration {
+    let header = response
+        .headers()
+        .get("Retry-After")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<f64>().ok());
+    let body = response
+        .json::<Value>()
+        .ok()
+        .and_then(|body| body.get("retry_after")?.as_f64());
+    Duration::from_secs_f64(body.or(header).unwrap_or(1.0).max(0.0))
+}
+
+// builds and sends the request again after waiting out rate limits and transient errors
+pub(crate) fn send_retrying(
+    retry: &RetryConfig,
+    request: impl Fn() -> RequestBuilder,
+) -> reqwest::Result<Response> {
+    let mut retries = 0;
+    loop {
+        let response = retry.send(&request)?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || retries == MAX_RATE_LIMIT_RETRIES {
+            return Ok(response);
+        }
+        retries += 1;
+        let wait = retry_after(response);
+        warn!(
+            "Rate limited by Discord, retrying in {} ms",
+            wait.as_millis()
+        );
+        thread::sleep(wait);
+    }
+}
+
+// discord expects the json payload and the files as separate multipart fields,
+// the attachments array of the payload refers to the files by index
+pub(crate) fn multipart_form<T: Serialize>(payload: &T, attachments: &[Attachment]) -> Form {
+    let mut payload = serde_json::to_value(payload).unwrap();
+    if let Value::Object(fields) = &mut payload {
+        let metadata = attachments
+            .iter()
+            .enumerate()
+            .map(|(i, attachment)| {
+                let mut metadata = json!({ "id": i, "filename": attachment.filename });
+                if let Some(description) = attachment.description {
+                    metadata["description"] = description.into();
+                }
+                metadata
+            })
+            .collect();
+        fields.insert("attachments".to_owned(), Value::Array(metadata));
+    }
+    let payload_json = payload.to_string();
+    attachments.iter().enumerate().fold(
+        Form::new().text("payload_json", payload_json),
+        |form, (i, attachment)| {
+            let part =
+                Part::bytes(attachment.data.to_vec()).file_name(attachment.filename.to_owned());
+            form.part(format!("files[{i}]"), part)
+        },
+    )
+}
+
+// the setters take borrowed or owned strings alike
 pub struct EmbedBuilder<'a> {
-    embed: Embed<'a>,
+    pub(crate) embed: Embed<'a>,
+    // the rest of a description too long for one embed
+    continued: Vec<Cow<'a, str>>,
 }
 
 impl<'a> EmbedBuilder<'a> {
     pub fn new() -> Self {
         Self {
             embed: Embed::default(),
+            continued: Vec::new(),
         }
     }
 
-    pub fn title(&mut self, title: &'a str) -> &mut Self {
-        self.embed.title = Some(title);
+    pub fn title(&mut self, title: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.title = Some(title.into());
+        self
+    }
+
+    pub fn description(&mut self, description: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.description = Some(description.into());
         self
     }
 
-    pub fn description(&mut self, description: &'a str) -> &mut Self {
-        self.embed.description = Some(description);
+    // the embeds from split are always within the limits
+    pub fn validate(&self) -> Result<(), LimitExceeded> {
+        self.embed.validate()
+    }
+
+    // continues the description in an embed of its own after this one
+    pub fn continuation(&mut self, description: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.continued.push(description.into());
         self
     }
 
-    pub fn url(&mut self, url: &'a str) -> &mut Self {
-        self.embed.url = Some(url);
+    pub fn url(&mut self, url: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.url = Some(url.into());
         self
     }
 
-    pub fn timestamp(&mut self, timestamp: &'a str) -> &mut Self {
-        self.embed.timestamp = Some(timestamp);
+    pub fn timestamp(&mut self, timestamp: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.timestamp = Some(timestamp.into());
         self
     }
 
@@ -124,64 +515,176 @@ impl<'a> EmbedBuilder<'a> {
         self
     }
 
-    pub fn footer(&mut self, text: &'a str, icon_url: Option<&'a str>) -> &mut Self {
-        self.embed.footer = Some(EmbedFooter { text, icon_url });
+    pub fn footer(
+        &mut self,
+        text: impl Into<Cow<'a, str>>,
+        icon_url: Option<impl Into<Cow<'a, str>>>,
+    ) -> &mut Self {
+        self.embed.footer = Some(EmbedFooter {
+            text: text.into(),
+            icon_url: icon_url.map(Into::into),
+        });
+        self
+    }
+
+    pub fn image(&mut self, url: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.image = Some(EmbedImage { url: url.into() });
         self
     }
 
-    pub fn image(&mut self, url: &'a str) -> &mut Self {
-        self.embed.image = Some(EmbedImage { url });
+    pub fn thumbnail(&mut self, url: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.thumbnail = Some(EmbedThumbnail { url: url.into() });
         self
     }
 
-    pub fn thumbnail(&mut self, url: &'a str) -> &mut Self {
-        self.embed.thumbnail = Some(EmbedThumbnail { url });
+    pub fn video(&mut self, url: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.embed.video = Some(EmbedVideo { url: url.into() });
+        self
+    }
+
+    pub fn provider(
+        &mut self,
+        name: Option<impl Into<Cow<'a, str>>>,
+        url: Option<impl Into<Cow<'a, str>>>,
+    ) -> &mut Self {
+        self.embed.provider = Some(EmbedProvider {
+            name: name.map(Into::into),
+            url: url.map(Into::into),
+        });
         self
     }
 
     pub fn author(
         &mut self,
-        name: Option<&'a str>,
-        url: Option<&'a str>,
-        icon_url: Option<&'a str>,
+        name: Option<impl Into<Cow<'a, str>>>,
+        url: Option<impl Into<Cow<'a, str>>>,
+        icon_url: Option<impl Into<Cow<'a, str>>>,
     ) -> &mut Self {
         self.embed.author = Some(EmbedAuthor {
-            name,
-            url,
-            icon_url,
+            name: name.map(Into::into),
+            url: url.map(Into::into),
+            icon_url: icon_url.map(Into::into),
         });
         self
     }
 
-    pub fn field(&mut self, name: &'a str, value: &'a str, inline: Option<bool>) -> &mut Self {
+    pub fn field(
+        &mut self,
+        name: impl Into<Cow<'a, str>>,
+        value: impl Into<Cow<'a, str>>,
+        inline: Option<bool>,
+    ) -> &mut Self {
         self.embed.fields.push(EmbedField {
-            name,
-            value,
+            name: name.into(),
+            value: value.into(),
             inline,
         });
         self
     }
+
+    // splits an embed over the limits into ones sent as separate messages in order,
+    // the continued description, fields and footer follow in embeds of the same color
+    pub fn split(&self) -> Vec<EmbedBuilder<'a>> {
+        let mut embed = self.embed.clone();
+        embed.trim();
+        if self.continued.is_empty()
+            && embed.text_length() <= EMBED_TOTAL_LIMIT
+            && embed.fields.len() <= EMBED_FIELD_LIMIT
+        {
+            return vec![EmbedBuilder {
+                embed,
+                continued: Vec::new(),
+            }];
+        }
+        let continuation = || Embed {
+            color: embed.color,
+            ..Embed::default()
+        };
+        let mut embeds = vec![Embed {
+            fields: Vec::new(),
+            footer: None,
+            timestamp: None,
+            ..embed.clone()
+        }];
+        for description in &self.continued {
+            let mut description = description.clone();
+            trim_text(&mut description, EMBED_DESCRIPTION_LIMIT);
+            embeds.push(Embed {
+                description: Some(description),
+                ..continuation()
+            });
+        }
+        for field in &embed.fields {
+            let last = embeds.last().unwrap();
+            if last.text_length() + field.text_length() > EMBED_TOTAL_LIMIT
+                || last.fields.len() == EMBED_FIELD_LIMIT
+            {
+                embeds.push(continuation());
+            }
+            embeds.last_mut().unwrap().fields.push(field.clone());
+        }
+        if let Some(footer) = &embed.footer {
+            let last = embeds.last().unwrap();
+            if last.text_length() + footer.text.chars().count() > EMBED_TOTAL_LIMIT {
+                embeds.push(continuation());
+            }
+            embeds.last_mut().unwrap().footer = Some(footer.clone());
+        }
+        embeds.last_mut().unwrap().timestamp = embed.timestamp;
+        embeds
+            .into_iter()
+            .map(|embed| EmbedBuilder {
+                embed,
+                continued: Vec::new(),
+            })
+            .collect()
+    }
+}
+
+// groups embeds in order into as few messages as the limits per message allow
+pub fn group_embeds<'b, 'a>(embeds: &'b [EmbedBuilder<'a>]) -> Vec<&'b [EmbedBuilder<'a>]> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    let mut length = 0;
+    for (index, embed) in embeds.iter().enumerate() {
+        let embed_length = embed.embed.text_length();
+        if index > start
+            && (index - start == MESSAGE_EMBED_LIMIT || length + embed_length > EMBED_TOTAL_LIMIT)
+        {
+            groups.push(&embeds[start..index]);
+            start = index;
+            length = 0;
+        }
+        length += embed_length;
+    }
+    if start < embeds.len() {
+        groups.push(&embeds[start..]);
+    }
+    groups
 }
 
 pub struct ExecutionBuilder<'a> {
     webhook: &'a Webhook<'a>,
     url: &'a str,
     payload: ExecuteWebhook<'a>,
+    attachments: Vec<Attachment<'a>>,
+    wait: bool,
+    thread_id: Option<Cow<'a, str>>,
 }
 
 impl<'a> ExecutionBuilder<'a> {
-    pub fn content(&mut self, content: &'a str) -> &mut Self {
-        self.payload.content = Some(content);
+    pub fn content(&mut self, content: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.payload.content = Some(content.into());
         self
     }
 
-    pub fn username(&mut self, username: &'a str) -> &mut Self {
-        self.payload.username = Some(username);
+    pub fn username(&mut self, username: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.payload.username = Some(username.into());
         self
     }
 
-    pub fn avatar_url(&mut self, avatar_url: &'a str) -> &mut Self {
-        self.payload.avatar_url = Some(avatar_url);
+    pub fn avatar_url(&mut self, avatar_url: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.payload.avatar_url = Some(avatar_url.into());
         self
     }
 
@@ -190,8 +693,51 @@ impl<'a> ExecutionBuilder<'a> {
         self
     }
 
-    pub fn file(&mut self, file: &'a str) -> &mut Self {
-        self.payload.file = Some(file);
+    // uploads the data with the message, shown as is unless an embed refers to it
+    pub fn file(&mut self, filename: &'a str, data: &'a [u8]) -> &mut Self {
+        self.attachment(filename, data, None)
+    }
+
+    // sends the message in a thread of the channel, or a post of a forum channel
+    pub fn thread_id(&mut self, thread_id: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.thread_id = Some(thread_id.into());
+        self
+    }
+
+    // the webhook has to be in a forum channel, the id of the post created is the
+    // channel id of the message
+    pub fn thread_name(&mut self, thread_name: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.payload.thread_name = Some(thread_name.into());
+        self
+    }
+
+    // a row holds up to five buttons, further ones go on the next row
+    pub fn link_button(&mut self, button: &'a LinkButton<'a>) -> &mut Self {
+        match self.payload.components.last_mut() {
+            Some(row) if row.components.len() < ROW_BUTTON_LIMIT => row.components.push(button),
+            _ => self.payload.components.push(ActionRow {
+                kind: 1,
+                components: vec![button],
+            }),
+        }
+        self
+    }
+
+    pub fn allowed_mentions(&mut self, allowed_mentions: AllowedMentions) -> &mut Self {
+        self.payload.allowed_mentions = allowed_mentions;
+        self
+    }
+
+    // discord responds with the created message instead of no content,
+    // see WebhookMessage
+    pub fn wait(&mut self) -> &mut Self {
+        self.wait = true;
+        self
+    }
+
+    // delivers the message without push notifications
+    pub fn silent(&mut self) -> &mut Self {
+        self.payload.flags = Some(SUPPRESS_NOTIFICATIONS);
         self
     }
 
@@ -200,21 +746,251 @@ impl<'a> ExecutionBuilder<'a> {
         self
     }
 
+    // attached files can be referenced in embeds with attachment://filename
+    pub fn attachment(
+        &mut self,
+        filename: &'a str,
+        data: &'a [u8],
+        description: Option<&'a str>,
+    ) -> &mut Self {
+        self.attachments.push(Attachment {
+            filename,
+            data,
+            description,
+        });
+        self
+    }
+
+    pub fn send(&self) -> reqwest::Result<reqwest::blocking::Response> {
+        send_retrying(&self.webhook.retry, || {
+            let mut request = self.webhook.client.post(self.url);
+            if self.wait {
+                request = request.query(&[("wait", "true")]);
+            }
+            if let Some(thread_id) = &self.thread_id {
+                request = request.query(&[("thread_id", thread_id)]);
+            }
+            // otherwise the components are ignored
+            if !self.payload.components.is_empty() {
+                request = request.query(&[("with_components", "true")]);
+            }
+            if self.attachments.is_empty() {
+                request.json(&self.payload)
+            } else {
+                request.multipart(multipart_form(&self.payload, &self.attachments))
+            }
+        })
+    }
+}
+
+pub struct EditBuilder<'a> {
+    webhook: &'a Webhook<'a>,
+    url: String,
+    payload: EditWebhookMessage<'a>,
+    attachments: Vec<Attachment<'a>>,
+    thread_id: Option<Cow<'a, str>>,
+}
+
+impl<'a> EditBuilder<'a> {
+    // needed for messages in threads and forum posts
+    pub fn thread_id(&mut self, thread_id: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.thread_id = Some(thread_id.into());
+        self
+    }
+
+    pub fn content(&mut self, content: impl Into<Cow<'a, str>>) -> &mut Self {
+        self.payload.content = Some(content.into());
+        self
+    }
+
+    // the embeds given replace all of the previous ones
+    pub fn embed(&mut self, embed: &'a EmbedBuilder) -> &mut Self {
+        self.payload
+            .embeds
+            .get_or_insert_with(Vec::new)
+            .push(&embed.embed);
+        self
+    }
+
+    // the attachments given replace all of the previous ones
+    pub fn attachment(
+        &mut self,
+        filename: &'a str,
+        data: &'a [u8],
+        description: Option<&'a str>,
+    ) -> &mut Self {
+        self.attachments.push(Attachment {
+            filename,
+            data,
+            description,
+        });
+        self
+    }
+
     pub fn send(&self) -> reqwest::Result<reqwest::blocking::Response> {
-        self.webhook
-            .client
-            .post(self.url)
-            .json(&self.payload)
-            .send()
+        send_retrying(&self.webhook.retry, || {
+            let mut request = self.webhook.client.patch(&self.url);
+            if let Some(thread_id) = &self.thread_id {
+                request = request.query(&[("thread_id", thread_id)]);
+            }
+            if self.attachments.is_empty() {
+                request.json(&self.payload)
+            } else {
+                request.multipart(multipart_form(&self.payload, &self.attachments))
+            }
+        })
+    }
+}
+
+// a message sent by the webhook, the query of the webhook url is kept
+fn message_url(url: &str, message_id: &str) -> String {
+    match url.split_once('?') {
+        Some((base, query)) => format!(
+            "{}/messages/{message_id}?{query}",
+            base.trim_end_matches('/')
+        ),
+        None => format!("{}/messages/{message_id}", url.trim_end_matches('/')),
     }
 }
 
 impl<'a> Webhook<'a> {
+    // fetches the webhook object, useful for checking the url is valid
+    pub fn get(&self, url: &str) -> reqwest::Result<reqwest::blocking::Response> {
+        self.client.get(url).send()
+    }
+
+    // sends an already formatted payload as is
+    pub fn execute_json<T: Serialize>(
+        &self,
+        url: &str,
+        payload: &T,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        send_retrying(&self.retry, || self.client.post(url).json(payload))
+    }
+
     pub fn execute(&'a self, url: &'a str) -> ExecutionBuilder<'a> {
         ExecutionBuilder {
-            webhook: &self,
+            webhook: self,
             url,
             payload: ExecuteWebhook::default(),
+            attachments: Vec::new(),
+            wait: false,
+            thread_id: None,
+        }
+    }
+
+    // changes a message the webhook sent earlier, see ExecutionBuilder::wait for the id
+    pub fn edit_message(&'a self, url: &str, message_id: &str) -> EditBuilder<'a> {
+        EditBuilder {
+            webhook: self,
+            url: message_url(url, message_id),
+            payload: EditWebhookMessage::default(),
+            attachments: Vec::new(),
+            thread_id: None,
+        }
+    }
+
+    pub fn delete_message(
+        &self,
+        url: &str,
+        message_id: &str,
+    ) -> reqwest::Result<reqwest::blocking::Response> {
+        let url = message_url(url, message_id);
+        send_retrying(&self.retry, || self.client.delete(&url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_embeds_within_the_limits() {
+        let mut embed = EmbedBuilder::new();
+        embed.title("Tarjous").description("Halpa näyttö");
+        let embeds = embed.split();
+        assert_eq!(embeds.len(), 1);
+        assert_eq!(embeds[0].embed.description.as_deref(), Some("Halpa näyttö"));
+    }
+
+    #[test]
+    fn continues_the_description_in_embeds_of_the_same_color() {
+        let mut embed = EmbedBuilder::new();
+        embed
+            .title("Tarjous")
+            .description("alku")
+            .continuation("loppu")
+            .color(0x00ff_0000)
+            .footer("alatunniste", None::<&str>)
+            .timestamp("2024-01-01T00:00:00Z");
+        let embeds = embed.split();
+        assert_eq!(embeds.len(), 2);
+        assert_eq!(embeds[0].embed.title.as_deref(), Some("Tarjous"));
+        assert!(embeds[0].embed.footer.is_none());
+        assert_eq!(embeds[1].embed.title, None);
+        assert_eq!(embeds[1].embed.description.as_deref(), Some("loppu"));
+        assert_eq!(embeds[1].embed.color, Some(0x00ff_0000));
+        assert_eq!(embeds[1].embed.footer_text(), Some("alatunniste"));
+        assert!(embeds[1].embed.timestamp.is_some());
+    }
+
+    #[test]
+    fn moves_fields_over_the_limit_to_another_embed() {
+        let mut embed = EmbedBuilder::new();
+        embed.title("Tarjous");
+        for index in 0..30 {
+            embed.field(format!("kenttä {index}"), "arvo", Some(true));
         }
+        let embeds = embed.split();
+        assert_eq!(embeds.len(), 2);
+        assert_eq!(embeds[0].embed.fields.len(), EMBED_FIELD_LIMIT);
+        assert_eq!(embeds[1].embed.fields.len(), 5);
+        assert!(embeds.iter().all(|embed| embed.validate().is_ok()));
+    }
+
+    #[test]
+    fn groups_embeds_by_the_message_limit() {
+        let embeds: Vec<EmbedBuilder> = (0..12)
+            .map(|_| {
+                let mut embed = EmbedBuilder::new();
+                embed.description("tarjous");
+                embed
+            })
+            .collect();
+        let groups = group_embeds(&embeds);
+        assert_eq!(
+            groups.iter().map(|group| group.len()).collect::<Vec<_>>(),
+            [MESSAGE_EMBED_LIMIT, 2]
+        );
+    }
+
+    #[test]
+    fn allows_no_mentions_by_default() {
+        assert_eq!(
+            serde_json::to_value(AllowedMentions::none()).unwrap(),
+            serde_json::json!({ "parse": [] })
+        );
+    }
+
+    #[test]
+    fn allows_the_mentions_written_in_the_text() {
+        let mentions = AllowedMentions::from_text("<@123> <@!456> <@&789> ja @here");
+        assert_eq!(
+            serde_json::to_value(mentions).unwrap(),
+            serde_json::json!({
+                "parse": ["everyone"],
+                "roles": ["789"],
+                "users": ["123", "456"],
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_mentions() {
+        let mentions = AllowedMentions::from_text("<@> <@abc> <@12 <@&>");
+        assert_eq!(
+            serde_json::to_value(mentions).unwrap(),
+            serde_json::json!({ "parse": [] })
+        );
     }
 }